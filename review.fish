@@ -1,8 +1,23 @@
 complete -c review -l help           -d 'Print the help message.' -f
-complete -c review -l frequency      -d 'Frequent words will be reviewed.' -f
+complete -c review -l frequency-max  -d 'Frequent words will be reviewed.' -f
+complete -c review -l frequency-min  -d 'Skip the most frequent words.' -f
+complete -c review -l exam           -d 'Only review words of the exam' -x -a 'zk gk cet4 cet6 ky toefl ielts gre'
+complete -c review -l collins        -d 'Collins star, e.g. >=3' -x
+complete -c review -l metric         -d 'Metric of the spelling extension' -x -a 'levenshtein damerau jaro-winkler'
+complete -c review -l max-distance   -d 'Max edit distance of the spelling extension' -x
+complete -c review -l min-similarity -d 'Min Jaro-Winkler similarity' -x
 complete -c review -l merriam        -d 'merriam' -f
+complete -c review -l relation       -d 'Only extend by these relations' -x -a 'synonym antonym see-also derived'
+complete -c review -l phonetic       -d 'Queue words sounding alike' -f
 complete -c review -l no-extend      -d 'Never extend' -f
+complete -c review -l clusters       -d 'Review due words cluster by cluster' -f
+complete -c review -l clusters-k     -d 'Number of clusters' -x
+complete -c review -l cluster-method -d 'How clusters are grouped' -x -a 'kmeans agglomerative'
+complete -c review -l confusables    -d 'Drill confusable pairs side by side' -f
 complete -c review -l random         -d 'Choose next word to review randomly' -f
+complete -c review -l answer         -d 'How to show the answer' -x -a 'goldendict goldendict-ng ecdict dictionary'
+complete -c review -l audio          -d 'Play the pronunciation of every word' -f
+complete -c review -l listen         -d 'Hide the word until it is played' -f
 complete -c review -l last-no-row    -d 'Only review recently updated words' -f
 
 set -l subcommands favourite phrase (cat ~/.config/goldendict/favorites | grep folder | grep expanded | grep -oP '(?<=name=").+?(?=")')
@@ -0,0 +1,745 @@
+//! Anki packages (`.apkg`): a zip of `collection.anki2` and `media`
+//!
+//! The legacy schema 11 is written, importable by every Anki version
+//! FSRS memory state goes to `cards.data`, read by Anki 23.10+
+
+use anyhow::anyhow;
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use rand::distr::Alphanumeric;
+use rand::Rng;
+use rs_fsrs::{Card, Parameters, Rating, State, FSRS};
+use scraper::Html;
+use serde_json::json;
+use sha1::{Digest, Sha1};
+use sqlx::sqlite::SqliteConnectOptions;
+use sqlx::sqlite::SqlitePool;
+use sqlx::sqlite::SqlitePoolOptions;
+use sqlx::Row;
+use std::collections::HashMap;
+use std::fs;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use zip::write::SimpleFileOptions;
+
+use crate::csv::has_ecdict;
+use crate::dictionary::lookup_ecdict;
+use crate::fsrs::get_all_cards;
+use crate::fsrs::sqlite_history::SQLiteHistory;
+use crate::revlog::{rating_from_i64, replay};
+
+const SCHEMA: &str = "
+CREATE TABLE col (
+    id integer primary key, crt integer not null, mod integer not null, scm integer not null,
+    ver integer not null, dty integer not null, usn integer not null, ls integer not null,
+    conf text not null, models text not null, decks text not null, dconf text not null, tags text not null
+);
+CREATE TABLE notes (
+    id integer primary key, guid text not null, mid integer not null, mod integer not null,
+    usn integer not null, tags text not null, flds text not null, sfld integer not null,
+    csum integer not null, flags integer not null, data text not null
+);
+CREATE TABLE cards (
+    id integer primary key, nid integer not null, did integer not null, ord integer not null,
+    mod integer not null, usn integer not null, type integer not null, queue integer not null,
+    due integer not null, ivl integer not null, factor integer not null, reps integer not null,
+    lapses integer not null, left integer not null, odue integer not null, odid integer not null,
+    flags integer not null, data text not null
+);
+CREATE TABLE revlog (
+    id integer primary key, cid integer not null, usn integer not null, ease integer not null,
+    ivl integer not null, lastIvl integer not null, factor integer not null, time integer not null,
+    type integer not null
+);
+CREATE TABLE graves (usn integer not null, oid integer not null, type integer not null);
+CREATE INDEX ix_notes_usn on notes (usn);
+CREATE INDEX ix_cards_usn on cards (usn);
+CREATE INDEX ix_revlog_usn on revlog (usn);
+CREATE INDEX ix_cards_nid on cards (nid);
+CREATE INDEX ix_cards_sched on cards (did, queue, due);
+CREATE INDEX ix_revlog_cid on revlog (cid);
+CREATE INDEX ix_notes_csum on notes (csum);
+";
+
+/// Anki field separator
+pub const FIELD_SEPARATOR: char = '\x1f';
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('\n', "<br>")
+}
+
+/// First 8 hex digits of sha1 of the sort field
+fn checksum(field: &str) -> i64 {
+    let digest = Sha1::digest(field.as_bytes());
+    u32::from_be_bytes([digest[0], digest[1], digest[2], digest[3]]) as i64
+}
+
+fn guid() -> String {
+    rand::rng()
+        .sample_iter(&Alphanumeric)
+        .take(10)
+        .map(char::from)
+        .collect()
+}
+
+/// (type, queue, due) of `cards`
+/// `crt`: creation of the collection, in seconds
+fn schedule(card: &Card, crt: i64, position: i64) -> (i64, i64, i64) {
+    match card.state {
+        State::New => (0, 0, position),
+        State::Learning => (1, 1, card.due.timestamp()),
+        State::Relearning => (3, 1, card.due.timestamp()),
+        State::Review => (2, 2, (card.due.timestamp() - crt).div_euclid(86400)),
+    }
+}
+
+fn collection(crt: i64, now_ms: i64, deck: &str, mid: i64, did: i64) -> serde_json::Value {
+    let fields: Vec<_> = ["Front", "Back"]
+        .iter()
+        .enumerate()
+        .map(|(ord, name)| {
+            json!({
+                "name": name,
+                "ord": ord,
+                "sticky": false,
+                "rtl": false,
+                "font": "Arial",
+                "size": 20,
+                "media": []
+            })
+        })
+        .collect();
+    let model = json!({
+        "id": mid,
+        "name": "goldendict",
+        "type": 0,
+        "mod": now_ms / 1000,
+        "usn": -1,
+        "sortf": 0,
+        "did": did,
+        "tmpls": [{
+            "name": "Card 1",
+            "ord": 0,
+            "qfmt": "{{Front}}",
+            "afmt": "{{FrontSide}}<hr id=answer>{{Back}}",
+            "bqfmt": "",
+            "bafmt": "",
+            "did": null,
+            "bfont": "",
+            "bsize": 0
+        }],
+        "flds": fields,
+        "css": ".card { font-family: arial; font-size: 20px; text-align: center; }",
+        "latexPre": "\\documentclass[12pt]{article}\n\\special{papersize=3in,5in}\n\\usepackage[utf8]{inputenc}\n\\usepackage{amssymb,amsmath}\n\\pagestyle{empty}\n\\setlength{\\parindent}{0in}\n\\begin{document}\n",
+        "latexPost": "\\end{document}",
+        "latexsvg": false,
+        "req": [[0, "any", [0]]],
+        "tags": [],
+        "vers": []
+    });
+    let new_deck = |id: i64, name: &str| {
+        json!({
+            "id": id,
+            "name": name,
+            "mod": now_ms / 1000,
+            "usn": -1,
+            "lrnToday": [0, 0],
+            "revToday": [0, 0],
+            "newToday": [0, 0],
+            "timeToday": [0, 0],
+            "collapsed": false,
+            "browserCollapsed": false,
+            "desc": "",
+            "dyn": 0,
+            "conf": 1,
+            "extendNew": 0,
+            "extendRev": 0
+        })
+    };
+    json!({
+        "crt": crt,
+        "conf": {
+            "nextPos": 1,
+            "estTimes": true,
+            "activeDecks": [did],
+            "sortType": "noteFld",
+            "timeLim": 0,
+            "sortBackwards": false,
+            "addToCur": true,
+            "curDeck": did,
+            "newSpread": 0,
+            "dueCounts": true,
+            "curModel": mid,
+            "collapseTime": 1200
+        },
+        "models": { mid.to_string(): model },
+        "decks": {
+            "1": new_deck(1, "Default"),
+            did.to_string(): new_deck(did, deck),
+        },
+        "dconf": {
+            "1": {
+                "id": 1,
+                "name": "Default",
+                "mod": 0,
+                "usn": 0,
+                "maxTaken": 60,
+                "autoplay": true,
+                "timer": 0,
+                "replayq": true,
+                "dyn": false,
+                "new": { "delays": [1, 10], "ints": [1, 4, 0], "initialFactor": 2500, "order": 1, "perDay": 20, "bury": false },
+                "rev": { "perDay": 200, "ease4": 1.3, "maxIvl": 36500, "hardFactor": 1.2, "bury": false },
+                "lapse": { "delays": [10], "mult": 0, "minInt": 1, "leechFails": 8, "leechAction": 1 }
+            }
+        }
+    })
+}
+
+impl SQLiteHistory {
+    /// Back of the note: ECDICT translation and the context sentences
+    async fn anki_back(&self, word: &str, has_ecdict: bool) -> Result<String> {
+        let mut back = String::new();
+        if has_ecdict {
+            if let Some(entry) = lookup_ecdict(&self.conn, word).await? {
+                back.push_str(&escape(entry.translation.trim()));
+            }
+        }
+        for context in self.contexts(word).await? {
+            back.push_str(&format!("<br><i>{}</i>", escape(&context.sentence)));
+            if let Some(book) = context.book {
+                back.push_str(&format!(" — {}", escape(&book)));
+            }
+        }
+        Ok(back)
+    }
+
+    /// Every card into a new deck of an Anki package
+    /// Returns the number of notes
+    pub async fn export_apkg(&self, path: &Path, deck: &str) -> Result<usize> {
+        let now: DateTime<Utc> = Utc::now();
+        let now_ms = now.timestamp_millis();
+        // start of today
+        let crt = now.timestamp() - now.timestamp().rem_euclid(86400);
+        let mid = now_ms;
+        let did = now_ms + 1;
+
+        let dir = tempfile::tempdir()?;
+        let db = dir.path().join("collection.anki2");
+
+        let options = SqliteConnectOptions::new()
+            .filename(&db)
+            .create_if_missing(true);
+        let pool = SqlitePoolOptions::new().connect_with(options).await?;
+        sqlx::raw_sql(SCHEMA).execute(&pool).await?;
+
+        let col = collection(crt, now_ms, deck, mid, did);
+        sqlx::query("INSERT INTO col VALUES (1, $1, $2, $2, 11, 0, 0, 0, $3, $4, $5, $6, '{}');")
+            .bind(crt)
+            .bind(now_ms)
+            .bind(col["conf"].to_string())
+            .bind(col["models"].to_string())
+            .bind(col["decks"].to_string())
+            .bind(col["dconf"].to_string())
+            .execute(&pool)
+            .await?;
+
+        let has_ecdict = has_ecdict(&self.conn).await?;
+        let cards = get_all_cards(&self.conn).await?;
+        let mut tx = pool.begin().await?;
+        for (i, (word, card)) in cards.iter().enumerate() {
+            let id = now_ms + i as i64;
+            let back = self.anki_back(word, has_ecdict).await?;
+            let tags: Vec<String> = self
+                .tags(word)
+                .await?
+                .iter()
+                .map(|tag| tag.replace(' ', "_"))
+                .collect();
+            let tags = if tags.is_empty() {
+                String::new()
+            } else {
+                format!(" {} ", tags.join(" "))
+            };
+
+            sqlx::query("INSERT INTO notes VALUES ($1, $2, $3, $4, -1, $5, $6, $7, $8, 0, '');")
+                .bind(id)
+                .bind(guid())
+                .bind(mid)
+                .bind(now_ms / 1000)
+                .bind(tags)
+                .bind(format!("{}{FIELD_SEPARATOR}{back}", escape(word)))
+                .bind(word)
+                .bind(checksum(word))
+                .execute(&mut *tx)
+                .await?;
+
+            let (card_type, queue, due) = schedule(card, crt, i as i64 + 1);
+            let data = if card.state == State::New {
+                "{}".to_owned()
+            } else {
+                json!({ "s": card.stability, "d": card.difficulty, "dr": 0.9 }).to_string()
+            };
+            sqlx::query("INSERT INTO cards VALUES ($1, $2, $3, 0, $4, -1, $5, $6, $7, $8, 2500, $9, $10, 0, 0, 0, 0, $11);")
+                .bind(id)
+                .bind(id)
+                .bind(did)
+                .bind(now_ms / 1000)
+                .bind(card_type)
+                .bind(queue)
+                .bind(due)
+                .bind(card.scheduled_days)
+                .bind(card.reps)
+                .bind(card.lapses)
+                .bind(data)
+                .execute(&mut *tx)
+                .await?;
+        }
+        tx.commit().await?;
+        pool.close().await;
+
+        let mut zip = zip::ZipWriter::new(fs::File::create(path)?);
+        let options =
+            SimpleFileOptions::default().compression_method(zip::CompressionMethod::Deflated);
+        zip.start_file("collection.anki2", options)?;
+        zip.write_all(&fs::read(&db)?)?;
+        zip.start_file("media", options)?;
+        zip.write_all(b"{}")?;
+        zip.finish()?;
+        Ok(cards.len())
+    }
+}
+
+/// A temporary copy of the collection, removed on drop
+struct Collection {
+    _dir: Option<tempfile::TempDir>,
+    path: PathBuf,
+}
+
+impl Collection {
+    /// `.apkg`/`.colpkg`, or `collection.anki2` itself
+    fn open(path: &Path) -> Result<Self> {
+        if matches!(
+            path.extension().and_then(|x| x.to_str()),
+            Some("anki2" | "anki21")
+        ) {
+            return Ok(Self {
+                _dir: None,
+                path: path.to_owned(),
+            });
+        }
+
+        let mut archive = zip::ZipArchive::new(fs::File::open(path)?)?;
+        // `collection.anki2` is a stub if `collection.anki21b` exists
+        let name = if archive.index_for_name("collection.anki21").is_some() {
+            "collection.anki21"
+        } else if archive.index_for_name("collection.anki21b").is_some() {
+            return Err(anyhow!(
+                "{path:?} is compressed by zstd, export it with \"Support older Anki versions\""
+            ));
+        } else if archive.index_for_name("collection.anki2").is_some() {
+            "collection.anki2"
+        } else {
+            return Err(anyhow!("no collection in {path:?}"));
+        };
+
+        let dir = tempfile::tempdir()?;
+        let mut bytes = Vec::new();
+        archive.by_name(name)?.read_to_end(&mut bytes)?;
+        let collection = dir.path().join(name);
+        fs::write(&collection, bytes)?;
+        Ok(Self {
+            _dir: Some(dir),
+            path: collection,
+        })
+    }
+
+    async fn pool(&self) -> Result<SqlitePool> {
+        let options = SqliteConnectOptions::new()
+            .filename(&self.path)
+            .read_only(true);
+        Ok(SqlitePoolOptions::new().connect_with(options).await?)
+    }
+}
+
+pub(crate) fn strip_html(s: &str) -> String {
+    Html::parse_fragment(s)
+        .root_element()
+        .text()
+        .collect::<String>()
+        .trim()
+        .to_owned()
+}
+
+/// Index of the field in every note type
+/// `None`: the sort field
+fn field_indexes(models: &str, field: Option<&str>) -> Result<HashMap<i64, usize>> {
+    let models: HashMap<String, serde_json::Value> = serde_json::from_str(models)?;
+    let mut res = HashMap::new();
+    let mut names = Vec::new();
+    for (mid, model) in &models {
+        let mid: i64 = mid.parse()?;
+        let index = match field {
+            None => model["sortf"].as_u64().map(|x| x as usize),
+            Some(field) => model["flds"].as_array().and_then(|flds| {
+                flds.iter()
+                    .position(|fld| fld["name"].as_str() == Some(field))
+            }),
+        };
+        match index {
+            Some(index) => {
+                res.insert(mid, index);
+            }
+            None => names.extend(
+                model["flds"]
+                    .as_array()
+                    .into_iter()
+                    .flatten()
+                    .flat_map(|fld| fld["name"].as_str())
+                    .map(str::to_owned),
+            ),
+        }
+    }
+    if res.is_empty() {
+        names.sort();
+        names.dedup();
+        return Err(anyhow!(
+            "no note type has the field {}, available fields: {}",
+            field.unwrap_or_default(),
+            names.join(", ")
+        ));
+    }
+    Ok(res)
+}
+
+/// A row of `cards`
+struct AnkiCard {
+    card_type: i64,
+    due: i64,
+    ivl: i64,
+    reps: i32,
+    lapses: i32,
+    data: String,
+}
+
+impl AnkiCard {
+    /// For cards without review log: the FSRS memory state of `data` if any, else the interval
+    /// `crt`: creation of the collection, in seconds
+    fn card(&self, crt: i64) -> Card {
+        let mut card = Card::new();
+        card.state = match self.card_type {
+            1 => State::Learning,
+            2 => State::Review,
+            3 => State::Relearning,
+            _ => return card,
+        };
+        // learning cards are due in seconds, the others in days since `crt`
+        let due = if self.due > 1_000_000_000 {
+            self.due
+        } else {
+            crt + self.due * 86400
+        };
+        card.due = DateTime::from_timestamp(due, 0).unwrap_or(card.due);
+        // negative: seconds of a learning step
+        card.scheduled_days = self.ivl.max(0);
+        card.reps = self.reps;
+        card.lapses = self.lapses;
+
+        let data: serde_json::Value = serde_json::from_str(&self.data).unwrap_or_default();
+        card.stability = data["s"]
+            .as_f64()
+            .unwrap_or(card.scheduled_days.max(1) as f64);
+        if let Some(difficulty) = data["d"].as_f64() {
+            card.difficulty = difficulty;
+        }
+        // last review time of recent Anki
+        card.last_review = data["lrt"]
+            .as_i64()
+            .and_then(|lrt| DateTime::from_timestamp(lrt, 0))
+            .unwrap_or(card.due - chrono::Duration::days(card.scheduled_days));
+        card
+    }
+}
+
+/// (word, card) of an Anki collection
+/// Cards are rebuilt by replaying the review log through FSRS, see `AnkiCard::card` for the others
+/// If a note has several cards, the most reviewed one is kept
+pub async fn read_apkg(path: &Path, field: Option<&str>) -> Result<Vec<(String, Card)>> {
+    let collection = Collection::open(path)?;
+    let pool = collection.pool().await?;
+
+    let col = sqlx::query("SELECT models, crt FROM col;")
+        .fetch_one(&pool)
+        .await?;
+    let indexes = field_indexes(col.get(0), field)?;
+    let crt: i64 = col.get(1);
+
+    let mut words: HashMap<i64, String> = HashMap::new();
+    for sqlite_row in sqlx::query("SELECT id, mid, flds FROM notes;")
+        .fetch_all(&pool)
+        .await?
+    {
+        let Some(index) = indexes.get(&sqlite_row.get::<i64, _>(1)) else {
+            continue;
+        };
+        let flds: &str = sqlite_row.get(2);
+        let Some(word) = flds.split(FIELD_SEPARATOR).nth(*index).map(strip_html) else {
+            continue;
+        };
+        if !word.is_empty() {
+            words.insert(sqlite_row.get(0), word);
+        }
+    }
+
+    // (card id, note id, card)
+    let cards: Vec<(i64, i64, AnkiCard)> =
+        sqlx::query("SELECT id, nid, type, due, ivl, reps, lapses, data FROM cards;")
+            .fetch_all(&pool)
+            .await?
+            .into_iter()
+            .map(|sqlite_row| {
+                let card = AnkiCard {
+                    card_type: sqlite_row.get(2),
+                    due: sqlite_row.get(3),
+                    ivl: sqlite_row.get(4),
+                    reps: sqlite_row.get(5),
+                    lapses: sqlite_row.get(6),
+                    data: sqlite_row.get(7),
+                };
+                (sqlite_row.get(0), sqlite_row.get(1), card)
+            })
+            .collect();
+
+    // (card id, review time in ms, ease)
+    // type 4: rescheduled by hand, ease 0: manual entry
+    let mut revlog: HashMap<i64, Vec<(i64, i64)>> = HashMap::new();
+    for sqlite_row in sqlx::query(
+        "SELECT cid, id, ease FROM revlog WHERE type != 4 AND ease BETWEEN 1 AND 4 ORDER BY id;",
+    )
+    .fetch_all(&pool)
+    .await?
+    {
+        revlog
+            .entry(sqlite_row.get(0))
+            .or_default()
+            .push((sqlite_row.get(1), sqlite_row.get(2)));
+    }
+    pool.close().await;
+
+    let fsrs = FSRS::new(Parameters::default());
+    let mut res: HashMap<i64, Card> = HashMap::new();
+    for (cid, nid, anki_card) in cards {
+        if !words.contains_key(&nid) {
+            continue;
+        }
+        let reviews: Vec<(i64, Rating)> = revlog
+            .get(&cid)
+            .into_iter()
+            .flatten()
+            .flat_map(|(ms, ease)| Some((*ms, rating_from_i64(*ease)?)))
+            .collect();
+        let card = if reviews.is_empty() {
+            anki_card.card(crt)
+        } else {
+            replay(&fsrs, &reviews)
+        };
+        match res.get(&nid) {
+            Some(old) if old.reps >= card.reps => {}
+            _ => {
+                res.insert(nid, card);
+            }
+        }
+    }
+
+    Ok(res
+        .into_iter()
+        .map(|(nid, card)| (words.remove(&nid).unwrap(), card))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::import::Context;
+
+    #[tokio::test]
+    async fn apkg_opens_in_anki() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut history = SQLiteHistory::temp(dir.path()).await;
+        history.write_card("apple", Card::new()).await.unwrap();
+        let mut card = Card::new();
+        card.state = State::Review;
+        card.stability = 12.5;
+        card.difficulty = 4.25;
+        card.scheduled_days = 12;
+        card.reps = 3;
+        card.lapses = 1;
+        history.write_card("a<b", card).await.unwrap();
+        history.add_tag("a<b", "Work Legal").await.unwrap();
+        let context = Context {
+            sentence: "a<b is true".to_owned(),
+            book: None,
+            source: "kindle".to_owned(),
+            timestamp: None,
+        };
+        history.insert_context("a<b", &context).await.unwrap();
+
+        let apkg = dir.path().join("out.apkg");
+        assert_eq!(history.export_apkg(&apkg, "Vocabulary").await.unwrap(), 2);
+
+        let mut archive = zip::ZipArchive::new(fs::File::open(&apkg).unwrap()).unwrap();
+        let mut media = String::new();
+        archive
+            .by_name("media")
+            .unwrap()
+            .read_to_string(&mut media)
+            .unwrap();
+        assert_eq!(media, "{}");
+        let mut bytes = Vec::new();
+        archive
+            .by_name("collection.anki2")
+            .unwrap()
+            .read_to_end(&mut bytes)
+            .unwrap();
+        let db = dir.path().join("collection.anki2");
+        fs::write(&db, bytes).unwrap();
+        let pool = SqlitePoolOptions::new()
+            .connect_with(SqliteConnectOptions::new().filename(&db))
+            .await
+            .unwrap();
+
+        let col = sqlx::query("SELECT id, ver, models, decks FROM col;")
+            .fetch_all(&pool)
+            .await
+            .unwrap();
+        assert_eq!(col.len(), 1);
+        assert_eq!(col[0].get::<i64, _>(1), 11);
+        let models: serde_json::Value = serde_json::from_str(col[0].get(2)).unwrap();
+        let (mid, model) = models.as_object().unwrap().iter().next().unwrap();
+        assert_eq!(model["flds"][0]["name"], "Front");
+        assert_eq!(model["flds"][1]["name"], "Back");
+        let decks: serde_json::Value = serde_json::from_str(col[0].get(3)).unwrap();
+        let did = model["did"].as_i64().unwrap();
+        assert_eq!(decks[did.to_string()]["name"], "Vocabulary");
+        assert_eq!(field_indexes(col[0].get(2), Some("Back")).unwrap().len(), 1);
+
+        let notes = sqlx::query("SELECT id, mid, tags, flds, sfld, csum FROM notes ORDER BY sfld;")
+            .fetch_all(&pool)
+            .await
+            .unwrap();
+        assert_eq!(notes.len(), 2);
+        let note = &notes[0];
+        assert_eq!(note.get::<i64, _>(1).to_string(), *mid);
+        assert_eq!(note.get::<&str, _>(2), " Work_Legal ");
+        assert_eq!(
+            note.get::<&str, _>(3),
+            "a&lt;b\x1f<br><i>a&lt;b is true</i>"
+        );
+        assert_eq!(note.get::<&str, _>(4), "a<b");
+        assert_eq!(note.get::<i64, _>(5), checksum("a<b"));
+
+        let cards = sqlx::query(
+            "SELECT nid, did, type, queue, ivl, reps, lapses, data FROM cards ORDER BY reps;",
+        )
+        .fetch_all(&pool)
+        .await
+        .unwrap();
+        assert_eq!(cards.len(), 2);
+        let (new, review) = (&cards[0], &cards[1]);
+        assert_eq!(new.get::<i64, _>(1), did);
+        assert_eq!((new.get::<i64, _>(2), new.get::<i64, _>(3)), (0, 0));
+        assert_eq!(new.get::<&str, _>(7), "{}");
+        assert_eq!(review.get::<i64, _>(0), note.get::<i64, _>(0));
+        assert_eq!((review.get::<i64, _>(2), review.get::<i64, _>(3)), (2, 2));
+        assert_eq!(review.get::<i64, _>(4), 12);
+        assert_eq!((review.get::<i64, _>(5), review.get::<i64, _>(6)), (3, 1));
+        let data: serde_json::Value = serde_json::from_str(review.get(7)).unwrap();
+        assert_eq!(data["s"], 12.5);
+        assert_eq!(data["d"], 4.25);
+    }
+
+    #[tokio::test]
+    async fn ecdict_line_breaks() {
+        let dir = tempfile::tempdir().unwrap();
+        let csv = dir.path().join("ecdict.csv");
+        fs::write(
+            &csv,
+            "word,phonetic,definition,translation,pos,collins,oxford,tag,bnc,frq,exchange,detail,audio
+quay,kiː,n. a wharf,n. 码头\\n[网络] 堤岸,,0,0,,0,0,,,
+",
+        )
+        .unwrap();
+        let mut history = SQLiteHistory::temp(dir.path()).await;
+        history.import_ecdict(&csv).await.unwrap();
+        assert_eq!(
+            history.anki_back("quay", true).await.unwrap(),
+            "n. 码头<br>[网络] 堤岸"
+        );
+    }
+
+    #[tokio::test]
+    async fn export_then_import() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut history = SQLiteHistory::temp(dir.path()).await;
+        history.write_card("apple", Card::new()).await.unwrap();
+        let mut card = Card::new();
+        card.state = State::Review;
+        card.stability = 30.5;
+        card.difficulty = 6.75;
+        card.scheduled_days = 30;
+        card.due = Utc::now() + chrono::Duration::days(30);
+        card.reps = 4;
+        card.lapses = 2;
+        history.write_card("pear", card.clone()).await.unwrap();
+
+        let apkg = dir.path().join("out.apkg");
+        history.export_apkg(&apkg, "Vocabulary").await.unwrap();
+        let mut cards = read_apkg(&apkg, None).await.unwrap();
+        cards.sort_by(|a, b| a.0.cmp(&b.0));
+
+        let (word, new) = &cards[0];
+        assert_eq!(word, "apple");
+        assert_eq!(new.state, State::New);
+        assert_eq!(new.reps, 0);
+
+        let (word, review) = &cards[1];
+        assert_eq!(word, "pear");
+        assert_eq!(review.state, State::Review);
+        assert_eq!(review.stability, card.stability);
+        assert_eq!(review.difficulty, card.difficulty);
+        // days are kept, not the time of day
+        assert_eq!(review.due.date_naive(), card.due.date_naive());
+        assert_eq!(review.scheduled_days, 30);
+        assert_eq!((review.reps, review.lapses), (4, 2));
+        assert_eq!(review.last_review, review.due - chrono::Duration::days(30));
+    }
+
+    #[test]
+    fn learning_cards_are_due_in_seconds() {
+        let anki_card = AnkiCard {
+            card_type: 1,
+            due: 1_700_000_000,
+            ivl: 0,
+            reps: 1,
+            lapses: 0,
+            data: String::new(),
+        };
+        let card = anki_card.card(0);
+        assert_eq!(card.state, State::Learning);
+        assert_eq!(card.due.timestamp(), 1_700_000_000);
+        assert_eq!(card.stability, 1.0);
+
+        let new = AnkiCard {
+            card_type: 0,
+            due: 3,
+            ivl: 0,
+            reps: 0,
+            lapses: 0,
+            data: "{}".to_owned(),
+        };
+        assert_eq!(new.card(0).state, State::New);
+    }
+}
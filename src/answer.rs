@@ -0,0 +1,214 @@
+//! How `review` shows the answer
+//!
+//! ```json
+//! { "answer": "goldendict" }
+//! { "answer": "goldendict-ng" }
+//! { "answer": "ecdict" }
+//! { "answer": "dictionary" }
+//! { "answer": { "command": "sdcv -n {word}" } }
+//! ```
+
+use anyhow::anyhow;
+use anyhow::Result;
+use sqlx::sqlite::SqlitePool;
+use std::process::{Command, Stdio};
+use std::str::FromStr;
+use urlencoding::encode;
+
+use crate::audio::template;
+use crate::config::Config;
+use crate::dictionary::{open_dictionaries, Dictionary};
+
+pub trait Answer {
+    /// Text to show in the TUI, `None` if shown by another program
+    fn show(&mut self, word: &str) -> Result<Option<String>>;
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Backend {
+    /// `xdg-open goldendict://<word>`
+    #[default]
+    Goldendict,
+    /// `goldendict <word>`
+    GoldendictNg,
+    /// inline, `ecdict.csv` is imported on first use
+    Ecdict,
+    /// inline, ECDICT if imported and `dictionaries`
+    Dictionary,
+    /// `{word}` is replaced, the output is shown inline if any
+    Command(String),
+}
+
+/// `goldendict`, `goldendict-ng`, `ecdict`, `dictionary` or a command containing `{word}`
+impl FromStr for Backend {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "goldendict" => Ok(Self::Goldendict),
+            "goldendict-ng" => Ok(Self::GoldendictNg),
+            "ecdict" => Ok(Self::Ecdict),
+            "dictionary" => Ok(Self::Dictionary),
+            s if s.contains("{word}") => Ok(Self::Command(s.to_owned())),
+            s => Err(format!(
+                "{s}: goldendict, goldendict-ng, ecdict, dictionary or a command containing {{word}}"
+            )),
+        }
+    }
+}
+
+impl Backend {
+    pub async fn open(&self, conn: &SqlitePool, config: &Config) -> Result<Box<dyn Answer>> {
+        Ok(match self {
+            Self::Goldendict => Box::new(GoldendictUrl),
+            Self::GoldendictNg => Box::new(GoldendictCli),
+            Self::Ecdict => Box::new(Dictionaries(vec![Dictionary::ecdict(conn)])),
+            Self::Dictionary => {
+                let dictionaries = open_dictionaries(config, conn).await?;
+                if dictionaries.is_empty() {
+                    return Err(anyhow!("no `dictionaries` in the config"));
+                }
+                Box::new(Dictionaries(dictionaries))
+            }
+            Self::Command(template) => Box::new(CommandTemplate(template.clone())),
+        })
+    }
+}
+
+fn spawn(command: &mut Command) -> Result<()> {
+    let program = command.get_program().to_string_lossy().into_owned();
+    command
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .map_err(|e| anyhow!("{program}: {e}"))?;
+    Ok(())
+}
+
+pub struct GoldendictUrl;
+
+impl Answer for GoldendictUrl {
+    fn show(&mut self, word: &str) -> Result<Option<String>> {
+        let url = format!("goldendict://{}", encode(word));
+        let status = Command::new("xdg-open")
+            .arg(&url)
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .map_err(|e| anyhow!("xdg-open: {e}"))?;
+        if !status.success() {
+            return Err(anyhow!("xdg-open {url}: {status}"));
+        }
+        Ok(None)
+    }
+}
+
+/// Not waited: it's the main window if goldendict isn't running
+pub struct GoldendictCli;
+
+impl Answer for GoldendictCli {
+    fn show(&mut self, word: &str) -> Result<Option<String>> {
+        spawn(Command::new("goldendict").arg(word))?;
+        Ok(None)
+    }
+}
+
+pub struct Dictionaries(Vec<Dictionary>);
+
+impl Answer for Dictionaries {
+    fn show(&mut self, word: &str) -> Result<Option<String>> {
+        let mut sections = Vec::new();
+        for dictionary in &mut self.0 {
+            if let Some(text) = dictionary.lookup_text(word)? {
+                sections.push(format!("[{}]\n{text}", dictionary.name));
+            }
+        }
+        if sections.is_empty() {
+            return Ok(Some(format!("{word} not found")));
+        }
+        Ok(Some(sections.join("\n\n")))
+    }
+}
+
+/// Split by whitespace, no shell involved
+pub struct CommandTemplate(String);
+
+impl Answer for CommandTemplate {
+    fn show(&mut self, word: &str) -> Result<Option<String>> {
+        let mut command = template(&self.0, "{word}", word)?;
+        let program = command.get_program().to_string_lossy().into_owned();
+        let output = command
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .output()
+            .map_err(|e| anyhow!("{program}: {e}"))?;
+        if !output.status.success() {
+            return Err(anyhow!(
+                "{program}: {}\n{}",
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim()
+            ));
+        }
+        let stdout = String::from_utf8_lossy(&output.stdout).trim().to_owned();
+        Ok((!stdout.is_empty()).then_some(stdout))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    #[test]
+    fn parse_backend() {
+        assert_eq!("goldendict-ng".parse(), Ok(Backend::GoldendictNg));
+        assert_eq!(
+            "sdcv -n {word}".parse(),
+            Ok(Backend::Command("sdcv -n {word}".to_owned()))
+        );
+        assert!("sdcv".parse::<Backend>().is_err());
+
+        let config: Config = serde_json::from_str(r#"{ "answer": "ecdict" }"#).unwrap();
+        assert_eq!(config.answer, Backend::Ecdict);
+        let config: Config =
+            serde_json::from_str(r#"{ "answer": { "command": "sdcv -n {word}" } }"#).unwrap();
+        assert_eq!(config.answer, Backend::Command("sdcv -n {word}".to_owned()));
+        let config: Config = serde_json::from_str("{}").unwrap();
+        assert_eq!(config.answer, Backend::Goldendict);
+    }
+
+    #[test]
+    fn command_output_inline() {
+        let mut echo = CommandTemplate("echo [{word}]".to_owned());
+        assert_eq!(
+            echo.show("ice cream").unwrap().as_deref(),
+            Some("[ice cream]")
+        );
+        assert_eq!(
+            CommandTemplate("true {word}".to_owned()).show("x").unwrap(),
+            None
+        );
+        assert!(CommandTemplate("false {word}".to_owned())
+            .show("x")
+            .is_err());
+        assert!(CommandTemplate("no-such-program-here {word}".to_owned())
+            .show("x")
+            .is_err());
+    }
+
+    #[test]
+    fn dictionaries_inline() {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("testdata/tiny.mdx");
+        let mut dictionaries = Dictionaries(vec![Dictionary::open(&path).unwrap()]);
+        assert_eq!(
+            dictionaries.show("apple").unwrap().as_deref(),
+            Some("[tiny]\napple play")
+        );
+        assert_eq!(
+            dictionaries.show("banana").unwrap().as_deref(),
+            Some("banana not found")
+        );
+    }
+}
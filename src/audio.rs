@@ -0,0 +1,221 @@
+//! Pronunciation of `review --audio` and `review --listen`
+//!
+//! ```json
+//! { "audio": { "mdx": "/path/to/LDOCE.mdx" }, "player": "mpv --really-quiet {file}" }
+//! { "audio": { "command": "piper-say {word}" } }
+//! ```
+//!
+//! The sound of an mdx is the first `sound://` link of the entry, found in the .mdd next to it
+
+use anyhow::anyhow;
+use anyhow::Result;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, ExitStatus, Stdio};
+use tempfile::NamedTempFile;
+
+use crate::config::Config;
+use crate::mdict::Mdx;
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum AudioSource {
+    /// resources in `<stem>.mdd`, `<stem>.1.mdd`, ...
+    Mdx(PathBuf),
+    /// `{word}` is replaced, it plays the sound itself
+    Command(String),
+}
+
+impl Default for AudioSource {
+    fn default() -> Self {
+        Self::Command("espeak-ng {word}".to_owned())
+    }
+}
+
+pub fn default_player() -> String {
+    "mpv --really-quiet {file}".to_owned()
+}
+
+/// `{key}` of every argument replaced, no shell involved
+/// stdin and the output are null
+pub(crate) fn template(template: &str, key: &str, value: &str) -> Result<Command> {
+    let mut args = template
+        .split_whitespace()
+        .map(|arg| arg.replace(key, value));
+    let program = args.next().ok_or_else(|| anyhow!("empty command"))?;
+    let mut command = Command::new(program);
+    command
+        .args(args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null());
+    Ok(command)
+}
+
+/// `stem.mdd`, `stem.1.mdd`, `stem.2.mdd`, ...
+fn mdd_paths(mdx: &Path) -> Vec<PathBuf> {
+    let mut v = Vec::new();
+    let first = mdx.with_extension("mdd");
+    if first.exists() {
+        v.push(first);
+    }
+    for i in 1.. {
+        let path = mdx.with_extension(format!("{i}.mdd"));
+        if !path.exists() {
+            break;
+        }
+        v.push(path);
+    }
+    v
+}
+
+/// `sound://us/apple.mp3` -> `us/apple.mp3`
+fn sound_link(html: &str) -> Option<&str> {
+    let start = html.find("sound://")? + "sound://".len();
+    let len = html[start..].find(['"', '\'', ' ', '>'])?;
+    Some(&html[start..start + len])
+}
+
+enum Source {
+    Mdd { mdx: Box<Mdx>, mdds: Vec<Mdx> },
+    Command(String),
+}
+
+/// A command playing a word, not started yet
+pub struct Playback {
+    command: Command,
+    /// the sound of an mdd, removed once played
+    _sound: Option<NamedTempFile>,
+}
+
+impl Playback {
+    /// Waits until played
+    pub fn status(mut self) -> std::io::Result<ExitStatus> {
+        self.command.status()
+    }
+}
+
+pub struct Audio {
+    source: Source,
+    /// `{file}` is replaced
+    player: String,
+}
+
+impl Audio {
+    pub fn open(source: &AudioSource, player: String) -> Result<Self> {
+        let source = match source {
+            AudioSource::Mdx(path) => {
+                let mdds = mdd_paths(path)
+                    .iter()
+                    .map(|path| Mdx::open(path))
+                    .collect::<Result<Vec<_>>>()?;
+                if mdds.is_empty() {
+                    return Err(anyhow!("no .mdd next to {:?}", path));
+                }
+                Source::Mdd {
+                    mdx: Box::new(Mdx::open(path)?),
+                    mdds,
+                }
+            }
+            AudioSource::Command(template) => Source::Command(template.clone()),
+        };
+        Ok(Self { source, player })
+    }
+
+    /// `audio` and `player` of the config, espeak-ng and mpv by default
+    pub fn from_config(config: &Config) -> Result<Self> {
+        Self::open(
+            &config.audio.clone().unwrap_or_default(),
+            config.player.clone().unwrap_or_else(default_player),
+        )
+    }
+
+    /// `None` if the word has no sound
+    pub fn command(&mut self, word: &str) -> Result<Option<Playback>> {
+        let (mdx, mdds) = match &mut self.source {
+            Source::Command(s) => {
+                return Ok(Some(Playback {
+                    command: template(s, "{word}", word)?,
+                    _sound: None,
+                }))
+            }
+            Source::Mdd { mdx, mdds } => (mdx, mdds),
+        };
+        let Some(html) = mdx.lookup(word)? else {
+            return Ok(None);
+        };
+        let Some(link) = sound_link(&html) else {
+            return Ok(None);
+        };
+        for mdd in mdds {
+            let Some(bytes) = mdd.resource(link)? else {
+                continue;
+            };
+            // players tell the format by the extension
+            let extension = Path::new(link)
+                .extension()
+                .and_then(|x| x.to_str())
+                .unwrap_or("mp3");
+            let mut sound = tempfile::Builder::new()
+                .prefix("goldendict-helper-sound")
+                .suffix(&format!(".{extension}"))
+                .tempfile()?;
+            sound.write_all(&bytes)?;
+            sound.flush()?;
+            return Ok(Some(Playback {
+                command: template(&self.player, "{file}", &sound.path().to_string_lossy())?,
+                _sound: Some(sound),
+            }));
+        }
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sound_links() {
+        assert_eq!(
+            sound_link(r#"<a href="sound://us/apple.mp3">play</a>"#),
+            Some("us/apple.mp3")
+        );
+        assert_eq!(sound_link("<a href='sound://uk.spx'>"), Some("uk.spx"));
+        assert_eq!(sound_link("<p>no sound</p>"), None);
+    }
+
+    #[test]
+    fn templates() {
+        let command = template("espeak-ng -v en {word}", "{word}", "ice cream").unwrap();
+        assert_eq!(command.get_program(), "espeak-ng");
+        assert_eq!(
+            command.get_args().collect::<Vec<_>>(),
+            ["-v", "en", "ice cream"]
+        );
+        assert!(template(" ", "{word}", "x").is_err());
+    }
+
+    #[test]
+    fn sound_of_the_mdd_kept_until_played() {
+        let mdx = Path::new(env!("CARGO_MANIFEST_DIR")).join("testdata/tiny.mdx");
+        assert_eq!(mdd_paths(&mdx), [mdx.with_extension("mdd")]);
+        // the player copies the sound it is given
+        let dir = tempfile::tempdir().unwrap();
+        let copy = dir.path().join("played.mp3");
+        let player = format!("cp {{file}} {}", copy.display());
+        let mut audio = Audio::open(&AudioSource::Mdx(mdx), player).unwrap();
+
+        assert!(audio.command("pear").unwrap().is_none());
+        let playback = audio.command("apples").unwrap().unwrap();
+        let sound = playback._sound.as_ref().unwrap().path().to_owned();
+        assert_eq!(sound.extension().unwrap(), "mp3");
+        assert!(
+            std::thread::spawn(move || playback.status().unwrap().success())
+                .join()
+                .unwrap()
+        );
+        assert_eq!(std::fs::read(&copy).unwrap(), b"ID3 apple");
+        assert!(!sound.exists());
+    }
+}
@@ -0,0 +1,73 @@
+use anyhow::anyhow;
+use anyhow::Result;
+use clap::Parser;
+use goldendict_ng_helper::config::{config_path, default_selector, Config, RelatedDictionary};
+use goldendict_ng_helper::mdict::Mdx;
+use goldendict_ng_helper::merriam::{merriam_db_path, RelatedWords, Relation};
+use std::path::PathBuf;
+
+/// Build the related words table used by `review --merriam` from mdx dictionaries
+#[derive(Parser)]
+struct Args {
+    /// mdx files, `related` in the config is used if empty
+    mdx: Vec<PathBuf>,
+
+    /// css selector of the links to related words
+    #[arg(long, default_value_t = default_selector())]
+    selector: String,
+
+    /// relation of the words found by `--selector`
+    #[arg(long, value_enum, default_value_t = Relation::SeeAlso)]
+    relation: Relation,
+
+    /// default: merriam.db next to the executable
+    #[arg(long)]
+    output: Option<PathBuf>,
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let args = Args::parse();
+
+    let dictionaries: Vec<RelatedDictionary> = if args.mdx.is_empty() {
+        Config::load()?.related
+    } else {
+        args.mdx
+            .into_iter()
+            .map(|mdx| RelatedDictionary {
+                mdx,
+                selector: args.selector.clone(),
+                relation: args.relation,
+                selectors: Vec::new(),
+            })
+            .collect()
+    };
+    if dictionaries.is_empty() {
+        return Err(anyhow!(
+            "no mdx given, neither `related` in {:?}",
+            config_path()
+        ));
+    }
+
+    let mut related_words = RelatedWords::default();
+    for dictionary in &dictionaries {
+        let mut mdx = Mdx::open(&dictionary.mdx)?;
+        let source = if mdx.title.is_empty() {
+            dictionary
+                .mdx
+                .file_stem()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .into_owned()
+        } else {
+            mdx.title.clone()
+        };
+        eprintln!("scanning {source} ({} entries)", mdx.len());
+        related_words.add_mdx(&mut mdx, &source, &dictionary.selectors())?;
+    }
+
+    let output = args.output.unwrap_or_else(merriam_db_path);
+    related_words.write(output.to_str().unwrap()).await?;
+    println!("{} relations written to {:?}", related_words.len(), output);
+    Ok(())
+}
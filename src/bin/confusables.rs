@@ -0,0 +1,32 @@
+use anyhow::Result;
+use clap::Parser;
+use goldendict_ng_helper::fsrs::sqlite_history::SQLiteHistory;
+
+/// List the confusable pairs with the most lapses
+/// Drill them with `review --confusables`
+#[derive(Parser)]
+struct Args {
+    #[arg(long, default_value_t = 20)]
+    limit: usize,
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let args = Args::parse();
+
+    let history = SQLiteHistory::default().await;
+    let pairs = history.confusable_pairs().await?;
+    if pairs.is_empty() {
+        println!("no confusable pairs");
+        return Ok(());
+    }
+
+    println!("{:>6}  {:<24}{:<24}reason", "lapses", "word", "word");
+    for pair in pairs.iter().take(args.limit) {
+        println!(
+            "{:>6}  {:<24}{:<24}{}",
+            pair.lapses, pair.words[0], pair.words[1], pair.reason
+        );
+    }
+    Ok(())
+}
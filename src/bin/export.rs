@@ -0,0 +1,63 @@
+use anyhow::Result;
+use clap::Parser;
+use clap::ValueEnum;
+use goldendict_ng_helper::dump::{write_entries, TextFormat};
+use goldendict_ng_helper::fsrs::sqlite_history::SQLiteHistory;
+use std::path::PathBuf;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Format {
+    /// Anki package, FSRS state kept
+    Apkg,
+    Csv,
+    Tsv,
+    /// JSON Lines
+    Jsonl,
+}
+
+/// Export history.db with tags and contexts
+#[derive(Parser)]
+struct Args {
+    /// guessed from the output extension by default
+    #[arg(long, value_enum)]
+    format: Option<Format>,
+
+    #[arg(long, short)]
+    output: PathBuf,
+
+    /// Anki deck
+    #[arg(long, default_value = "goldendict")]
+    deck: String,
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let args = Args::parse();
+
+    let history = SQLiteHistory::default().await;
+    let format = args
+        .format
+        .unwrap_or_else(|| match TextFormat::from_path(&args.output) {
+            Some(TextFormat::Csv) => Format::Csv,
+            Some(TextFormat::Tsv) => Format::Tsv,
+            Some(TextFormat::Jsonl) => Format::Jsonl,
+            None => Format::Apkg,
+        });
+    let text_format = match format {
+        Format::Apkg => None,
+        Format::Csv => Some(TextFormat::Csv),
+        Format::Tsv => Some(TextFormat::Tsv),
+        Format::Jsonl => Some(TextFormat::Jsonl),
+    };
+
+    let n = match text_format {
+        None => history.export_apkg(&args.output, &args.deck).await?,
+        Some(text_format) => {
+            let entries = history.entries().await?;
+            write_entries(&args.output, text_format, &entries)?;
+            entries.len()
+        }
+    };
+    println!("{n} words exported to {:?}", args.output);
+    Ok(())
+}
@@ -0,0 +1,165 @@
+use anyhow::Result;
+use clap::Parser;
+use clap::ValueEnum;
+use goldendict_ng_helper::anki::read_apkg;
+use goldendict_ng_helper::dump::{read_entries, Entry, Policy, TextFormat};
+use goldendict_ng_helper::fsrs::{get_word_ignore_case, sqlite_history::SQLiteHistory};
+use goldendict_ng_helper::import::{detect, kindle, koreader, open_read_only, Source};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Format {
+    Kindle,
+    Koreader,
+    /// Anki `.apkg`, `.colpkg` or `collection.anki2`
+    Apkg,
+    /// written by `export`
+    Csv,
+    Tsv,
+    Jsonl,
+}
+
+/// Import looked up words of Kindle `vocab.db` or KOReader `vocabulary_builder.sqlite3`
+/// The usage sentence and book title are kept as context
+/// Or import an Anki collection, the review log replayed through FSRS
+/// Or import a dump of `export`
+#[derive(Parser)]
+struct Args {
+    path: PathBuf,
+
+    /// detected by default
+    #[arg(long, value_enum)]
+    format: Option<Format>,
+
+    /// Kindle: import the word as looked up, not its stem
+    #[arg(long)]
+    no_stem: bool,
+
+    /// Anki: the field holding the word, the sort field by default
+    #[arg(long)]
+    field: Option<String>,
+
+    /// Anki, csv/tsv/jsonl: words in both history.db and the file
+    #[arg(long, value_enum, default_value_t = Policy::Newer)]
+    policy: Policy,
+
+    /// print the new words only
+    #[arg(long)]
+    dry_run: bool,
+}
+
+async fn import_ereader(args: &Args, source: Option<Source>) -> Result<()> {
+    let pool = open_read_only(&args.path).await?;
+    let source = match source {
+        Some(source) => source,
+        None => detect(&pool).await?,
+    };
+    let lookups = match source {
+        Source::Kindle => kindle(&pool, !args.no_stem).await?,
+        Source::Koreader => koreader(&pool).await?,
+    };
+
+    let mut history = if args.dry_run {
+        SQLiteHistory::without_session().await?
+    } else {
+        SQLiteHistory::default().await
+    };
+    let mut words = 0;
+    let mut contexts = 0;
+    let mut seen = HashSet::new();
+    for lookup in &lookups {
+        let word = lookup.word.trim();
+        if word.is_empty() {
+            continue;
+        }
+        if args.dry_run {
+            if seen.insert(word.to_lowercase())
+                && get_word_ignore_case(&history.conn, word).await.is_err()
+            {
+                println!("+ {word}");
+            }
+            continue;
+        }
+
+        let (word, new) = history.insert_if_absent(word).await?;
+        if new {
+            println!("+ {word}");
+            words += 1;
+        }
+        if history.add_context(&word, lookup, source).await? {
+            contexts += 1;
+        }
+    }
+
+    if !args.dry_run {
+        println!(
+            "{} lookups: {words} new words, {contexts} new sentences",
+            lookups.len()
+        );
+    }
+    Ok(())
+}
+
+async fn import_apkg(args: &Args) -> Result<()> {
+    let entries: Vec<Entry> = read_apkg(&args.path, args.field.as_deref())
+        .await?
+        .into_iter()
+        .map(|(word, card)| Entry::new(word, card))
+        .collect();
+    merge(args, &entries).await
+}
+
+async fn import_text(args: &Args, format: TextFormat) -> Result<()> {
+    let entries = read_entries(&args.path, format)?;
+    merge(args, &entries).await
+}
+
+async fn merge(args: &Args, entries: &[Entry]) -> Result<()> {
+    let mut history = if args.dry_run {
+        SQLiteHistory::without_session().await?
+    } else {
+        SQLiteHistory::default().await
+    };
+    let summary = history
+        .merge_entries(entries, args.policy, args.dry_run)
+        .await?;
+    println!(
+        "{} new words, {} replaced, {} kept",
+        summary.added, summary.replaced, summary.kept
+    );
+    Ok(())
+}
+
+fn is_anki(path: &Path) -> bool {
+    matches!(
+        path.extension().and_then(|x| x.to_str()),
+        Some("apkg" | "colpkg" | "anki2" | "anki21")
+    )
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let args = Args::parse();
+
+    let format = args.format.or_else(|| {
+        if is_anki(&args.path) {
+            return Some(Format::Apkg);
+        }
+        TextFormat::from_path(&args.path).map(|format| match format {
+            TextFormat::Csv => Format::Csv,
+            TextFormat::Tsv => Format::Tsv,
+            TextFormat::Jsonl => Format::Jsonl,
+        })
+    });
+    match format {
+        Some(Format::Kindle) => import_ereader(&args, Some(Source::Kindle)).await,
+        Some(Format::Koreader) => import_ereader(&args, Some(Source::Koreader)).await,
+        // Kindle or KOReader
+        None => import_ereader(&args, None).await,
+        Some(Format::Apkg) => import_apkg(&args).await,
+        Some(Format::Csv) => import_text(&args, TextFormat::Csv).await,
+        Some(Format::Tsv) => import_text(&args, TextFormat::Tsv).await,
+        Some(Format::Jsonl) => import_text(&args, TextFormat::Jsonl).await,
+    }
+}
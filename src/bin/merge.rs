@@ -0,0 +1,35 @@
+use anyhow::Result;
+use clap::Parser;
+use goldendict_ng_helper::fsrs::sqlite_history::SQLiteHistory;
+use goldendict_ng_helper::import::open_read_only;
+use std::path::PathBuf;
+
+/// Merge another history.db into ~/.local/share/goldendict/history.db
+/// Words in both are replayed from both review logs,
+/// or the card reviewed last wins if a log is incomplete
+#[derive(Parser)]
+struct Args {
+    other: PathBuf,
+
+    /// print the changes only
+    #[arg(long)]
+    dry_run: bool,
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let args = Args::parse();
+
+    let other = open_read_only(&args.other).await?;
+    let mut history = if args.dry_run {
+        SQLiteHistory::without_session().await?
+    } else {
+        SQLiteHistory::default().await
+    };
+    let summary = history.merge_from(&other, args.dry_run).await?;
+    println!(
+        "{} new words, {} replayed, {} replaced, {} kept",
+        summary.added, summary.replayed, summary.replaced, summary.kept
+    );
+    Ok(())
+}
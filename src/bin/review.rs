@@ -5,24 +5,39 @@ use cursive::traits::*;
 use cursive::views::Button;
 use cursive::views::Dialog;
 use cursive::views::LinearLayout;
+use cursive::views::NamedView;
 use cursive::views::TextView;
 use cursive::Cursive;
 use cursive::CursiveExt;
 use futures::executor::block_on;
+use goldendict_ng_helper::answer::Backend;
+use goldendict_ng_helper::audio::Audio;
+use goldendict_ng_helper::config::Config;
+use goldendict_ng_helper::confusable::Pair;
 use goldendict_ng_helper::favorite::{
     extract_all_words_from_favorites, extract_words_from_favorites_folder,
 };
+use goldendict_ng_helper::filter::{Collins, Exam, Filter};
 use goldendict_ng_helper::fsrs::sqlite_history::{ExtendStradegy, SQLiteHistory};
+use goldendict_ng_helper::merriam::{RelatedDatabases, Relation};
+use goldendict_ng_helper::spelling::Metric;
+use goldendict_ng_helper::word2vec::cluster::Method;
 use rand::prelude::SliceRandom;
 use rand::rng;
 use rs_fsrs::Rating;
 use shadow_rs::shadow;
-use std::process::Command;
-use urlencoding::encode;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
 
 shadow!(build);
 
 static OCEAN: &str = "ocean";
+static HEADING: &str = "heading";
+static PAIR_SEPARATOR: &str = "  <->  ";
+/// title of the word not played yet in `--listen`
+static HIDDEN: &str = "? ? ?";
+static AUTOPLAY: AtomicBool = AtomicBool::new(false);
+static LISTEN: AtomicBool = AtomicBool::new(false);
 
 #[derive(Parser)]
 struct Args {
@@ -30,7 +45,8 @@ struct Args {
     #[arg(long)]
     start: Option<String>,
 
-    /// folder in ~/.config/goldendict/favorites
+    /// folder in ~/.config/goldendict/favorites, subfolders included
+    /// `Work/Legal` selects a nested folder
     category: Vec<String>,
 
     #[arg(long, default_value_t = false)]
@@ -42,12 +58,49 @@ struct Args {
     #[arg(long, default_value_t = false, conflicts_with = "word2vec")]
     merriam: bool,
 
-    #[arg(long, default_value_t = false, conflicts_with_all = ["word2vec", "merriam"])]
+    /// only extend by these relations, implies `--merriam`
+    /// e.g. `--relation synonym,antonym`
+    #[arg(long, value_enum, value_delimiter = ',', conflicts_with_all = ["word2vec", "no_extend", "random", "clusters"])]
+    relation: Vec<Relation>,
+
+    /// metric of the default extension
+    #[arg(long, value_enum, default_value_t = Metric::Levenshtein)]
+    metric: Metric,
+
+    /// levenshtein and damerau
+    #[arg(long, default_value_t = 2)]
+    max_distance: usize,
+
+    /// jaro-winkler, between 0 and 1
+    #[arg(long, default_value_t = 0.9)]
+    min_similarity: f64,
+
+    /// queue words sounding alike
+    #[arg(long, default_value_t = false, conflicts_with_all = ["word2vec", "merriam", "relation"])]
+    phonetic: bool,
+
+    #[arg(long, default_value_t = false, conflicts_with_all = ["word2vec", "merriam", "phonetic"])]
     no_extend: bool,
 
-    #[arg(long, default_value_t = false, conflicts_with_all = ["word2vec", "merriam", "no-extend"])]
+    #[arg(long, default_value_t = false, conflicts_with_all = ["word2vec", "merriam", "phonetic", "no_extend"])]
     random: bool,
 
+    /// review due words cluster by cluster, grouped by word2vec
+    #[arg(long, default_value_t = false, conflicts_with_all = ["word2vec", "merriam", "phonetic", "no_extend", "random", "category"])]
+    clusters: bool,
+
+    /// number of clusters of `--clusters`
+    #[arg(long, default_value_t = 10)]
+    clusters_k: usize,
+
+    /// how `--clusters` groups the words
+    #[arg(long, value_enum, default_value_t = Method::Kmeans)]
+    cluster_method: Method,
+
+    /// drill confusable pairs side by side, the most lapses first
+    #[arg(long, default_value_t = false, conflicts_with_all = ["clusters", "category", "start"])]
+    confusables: bool,
+
     /// only review last N rows (recently updated rows)
     /// Make no sense if `!category.is_empty()`
     /// 0: unspecified
@@ -57,8 +110,34 @@ struct Args {
 
     /// 10000: frequent word
     /// 30000: word often meet
+    #[arg(long, alias = "frequency")]
+    frequency_max: Option<u32>,
+
+    /// skip words more frequent than this
     #[arg(long)]
-    frequency: Option<u32>,
+    frequency_min: Option<u32>,
+
+    /// only review words of these exams
+    #[arg(long, value_enum)]
+    exam: Vec<Exam>,
+
+    /// collins star: `>=3`, `<2`, `5`
+    #[arg(long)]
+    collins: Option<Collins>,
+
+    /// goldendict, goldendict-ng, ecdict, dictionary or a command like `sdcv -n {word}`
+    /// Default: `answer` of the config, or goldendict
+    #[arg(long)]
+    answer: Option<Backend>,
+
+    /// play the pronunciation when a word is shown, `p` plays it again
+    /// `audio` of the config, or espeak-ng
+    #[arg(long, default_value_t = false)]
+    audio: bool,
+
+    /// listening mode: the word is hidden until its pronunciation is played
+    #[arg(long, default_value_t = false, conflicts_with = "confusables")]
+    listen: bool,
 }
 
 #[tokio::main]
@@ -72,7 +151,10 @@ async fn main() -> Result<()> {
     }
 
     let mut history = SQLiteHistory::default().await;
-    if let Some(frequency) = args.frequency {
+    for frequency in [args.frequency_max, args.frequency_min]
+        .into_iter()
+        .flatten()
+    {
         if frequency > 50000 {
             println!("Every word's freq <= 50000");
             println!("Please give a smaller number");
@@ -84,19 +166,77 @@ async fn main() -> Result<()> {
             println!("For example: 10000 30000");
             return Ok(());
         }
+    }
+    if let (Some(max), Some(min)) = (args.frequency_max, args.frequency_min) {
+        if min > max {
+            println!("--frequency-min should <= --frequency-max");
+            return Ok(());
+        }
+    }
+
+    history.filter = Filter {
+        frequency_max: args.frequency_max,
+        frequency_min: args.frequency_min,
+        exam: args.exam,
+        collins: args.collins,
+    };
+    if !history.filter.is_empty() {
+        history.init_ecdict().await?;
+    }
 
-        history.freq = frequency;
-        history.init_records()?;
+    let config = Config::load()?;
+    let backend = args.answer.unwrap_or_else(|| config.answer.clone());
+    if backend == Backend::Ecdict {
+        history.init_ecdict().await?;
+    }
+    history.answer = backend.open(&history.conn, &config).await?;
+
+    // `p` plays with the `audio` of the config: a broken one only matters with the flags
+    let mut audio_error = None;
+    if args.audio || args.listen {
+        history.audio = Some(Audio::from_config(&config)?);
+    } else if config.audio.is_some() {
+        match Audio::from_config(&config) {
+            Ok(audio) => history.audio = Some(audio),
+            Err(e) => audio_error = Some(format!("audio: {e}")),
+        }
     }
+    AUTOPLAY.store(args.audio || args.listen, Ordering::SeqCst);
+    LISTEN.store(args.listen, Ordering::SeqCst);
+
+    history.metric = args.metric;
+    history.max_distance = args.max_distance;
+    history.min_similarity = args.min_similarity;
 
     if args.word2vec {
+        if !goldendict_ng_helper::word2vec::available() {
+            eprintln!("word2vec is disabled: run `word2vec_index` first");
+        }
         history.extend_stradegy = ExtendStradegy::Word2vec;
-    } else if args.merriam {
+    } else if args.merriam || !args.relation.is_empty() {
         history.extend_stradegy = ExtendStradegy::Merriam;
+        history.relations = args.relation;
+        history.related = Some(RelatedDatabases::open(&Config::load()?).await?);
+    } else if args.phonetic {
+        // without ecdict.csv, Double Metaphone is used
+        if history.init_ecdict().await.is_err() {
+            eprintln!("ecdict.csv not found, fall back to Double Metaphone");
+        }
+        history.extend_stradegy = ExtendStradegy::Phonetic;
     } else if args.no_extend {
         history.extend_stradegy = ExtendStradegy::NoExtend;
     } else if args.random {
         history.extend_stradegy = ExtendStradegy::Random;
+    } else if args.clusters {
+        let clusters = history
+            .clusters(args.clusters_k, args.cluster_method)
+            .await?;
+        if clusters.is_empty() {
+            println!("no words to review");
+            return Ok(());
+        }
+        history.clusters.extend(clusters);
+        history.extend_stradegy = ExtendStradegy::NoExtend;
     }
 
     for category in &args.category {
@@ -110,7 +250,7 @@ async fn main() -> Result<()> {
                 history.queue.extend(v);
             }
             "favourite" => {
-                let favorite_words = extract_all_words_from_favorites().unwrap();
+                let favorite_words = extract_all_words_from_favorites()?;
                 let mut v = Vec::new();
                 for word in favorite_words.into_iter() {
                     if history.should_review(&word).await.is_ok() {
@@ -125,7 +265,7 @@ async fn main() -> Result<()> {
                 history.queue.extend(v);
             }
             folder_name => {
-                let favorite_words = extract_words_from_favorites_folder(folder_name).unwrap();
+                let favorite_words = extract_words_from_favorites_folder(folder_name)?;
                 let mut v = Vec::new();
                 for word in favorite_words.into_iter() {
                     if history.should_review(&word).await.is_ok() {
@@ -149,13 +289,32 @@ async fn main() -> Result<()> {
         history.last_n_row = args.last_n_row;
     }
 
-    let first_word = match args.start {
-        Some(word) => {
+    if args.confusables {
+        for pair in history.confusable_pairs().await? {
+            let [a, b] = &pair.words;
+            if history.should_review(a).await.is_ok() || history.should_review(b).await.is_ok() {
+                history.pairs.push_back(pair);
+            }
+        }
+    }
+    let first_pair = history.pairs.pop_front();
+    if args.confusables && first_pair.is_none() {
+        println!("no confusable pairs to review");
+        return Ok(());
+    }
+
+    let first_word = match (&first_pair, args.start) {
+        (Some(pair), _) => pair_title(pair),
+        (None, Some(word)) => {
             history.middle_history.push(word.clone());
             word
         }
-        None => match history.next_to_review().await {
+        (None, None) => match history.next_to_review().await {
             Ok(word) => word,
+            Err(_) if !history.filter.is_empty() => {
+                println!("nothing due in this band");
+                return Ok(());
+            }
             Err(_) => {
                 println!("no words to review");
                 return Ok(());
@@ -197,15 +356,37 @@ async fn main() -> Result<()> {
         }),
     });
 
+    let heading = audio_error.unwrap_or_else(|| history_heading(&mut siv));
     siv.add_fullscreen_layer(
-        Dialog::around(TextView::new(" ".repeat(200))) // move the title to center
-            .title(first_word)
-            .content(show_answer_layout())
-            .h_align(cursive::align::HAlign::Center)
-            .with_name(OCEAN),
+        LinearLayout::vertical()
+            .child(
+                TextView::new(heading)
+                    .h_align(cursive::align::HAlign::Center)
+                    .with_name(HEADING),
+            )
+            .child(
+                Dialog::around(TextView::new(" ".repeat(200))) // move the title to center
+                    .title(first_word.clone())
+                    .content(if first_pair.is_some() {
+                        show_pair_layout()
+                    } else {
+                        show_answer_layout()
+                    })
+                    .h_align(cursive::align::HAlign::Center)
+                    .with_name(OCEAN),
+            ),
         // .padding(Margins::lrtb(10, 10, 0, 35))
     );
 
+    siv.add_global_callback('p', |s| {
+        if let Some(word) = current_word(s) {
+            play(s, &word);
+        }
+    });
+    if first_pair.is_none() {
+        present(&mut siv, &first_word);
+    }
+
     siv.run();
 
     let history: SQLiteHistory = siv.take_user_data().unwrap();
@@ -215,12 +396,19 @@ async fn main() -> Result<()> {
     Ok(())
 }
 
+/// Inline text of the answer backend, or its error
+fn show_answer(s: &mut Cursive, word: &str) -> Option<String> {
+    s.with_user_data(|history: &mut SQLiteHistory| history.answer.show(word))
+        .and_then(|answer| answer.unwrap_or_else(|e| Some(e.to_string())))
+}
+
 fn show_answer_cb(s: &mut Cursive) {
+    let Some(word) = current_word(s) else {
+        return;
+    };
+    let answer = show_answer(s, &word);
     s.call_on_name(OCEAN, |view: &mut Dialog| {
-        let word = view.get_title().to_owned();
-        let url = format!("goldendict://{}", encode(&word));
-        let _ = Command::new("xdg-open").arg(&url).status();
-
+        view.set_title(word.clone());
         let word_1 = word.clone();
         let word_2 = word.clone();
         let word_3 = word.clone();
@@ -258,7 +446,15 @@ fn show_answer_cb(s: &mut Cursive) {
                 s.quit();
             }));
 
-        view.set_content(buttons_layout);
+        match answer {
+            // the buttons stay on top of a long definition
+            Some(text) => view.set_content(
+                LinearLayout::vertical()
+                    .child(buttons_layout)
+                    .child(TextView::new(text).scrollable()),
+            ),
+            None => view.set_content(buttons_layout),
+        }
     });
 }
 
@@ -267,10 +463,15 @@ fn review_next(s: &mut Cursive) {
         s.with_user_data(|history: &mut SQLiteHistory| block_on(history.next_to_review()));
     match next_word {
         Some(Ok(next_word)) => {
+            let heading = history_heading(s);
+            s.call_on_name(HEADING, |view: &mut TextView| {
+                view.set_content(heading);
+            });
             s.call_on_name(OCEAN, |view: &mut Dialog| {
-                view.set_title(next_word);
+                view.set_title(next_word.clone());
                 view.set_content(show_answer_layout());
             });
+            present(s, &next_word);
         }
         _ => {
             s.quit();
@@ -278,6 +479,75 @@ fn review_next(s: &mut Cursive) {
     }
 }
 
+/// `--listen` hides the word, `--audio` plays it
+fn present(s: &mut Cursive, word: &str) {
+    if LISTEN.load(Ordering::SeqCst) {
+        s.call_on_name(OCEAN, |view: &mut Dialog| view.set_title(HIDDEN));
+    }
+    if AUTOPLAY.load(Ordering::SeqCst) {
+        play(s, word);
+    }
+}
+
+/// The word under review, even if hidden
+fn current_word(s: &mut Cursive) -> Option<String> {
+    let title = s.call_on_name(OCEAN, |view: &mut Dialog| view.get_title().to_owned())?;
+    if title.contains(PAIR_SEPARATOR) {
+        return None;
+    }
+    if title != HIDDEN {
+        return Some(title);
+    }
+    s.with_user_data(|history: &mut SQLiteHistory| history.middle_history.last().cloned())
+        .flatten()
+}
+
+/// Unless another word is under review
+fn reveal(s: &mut Cursive, word: &str) {
+    if current_word(s).as_deref() == Some(word) {
+        s.call_on_name(OCEAN, |view: &mut Dialog| view.set_title(word));
+    }
+}
+
+/// Played in the background, then the word is revealed
+fn play(s: &mut Cursive, word: &str) {
+    let command = s
+        .with_user_data(|history: &mut SQLiteHistory| {
+            history.audio.as_mut().map(|audio| audio.command(word))
+        })
+        .flatten();
+    match command {
+        Some(Ok(Some(playback))) => {
+            let word = word.to_owned();
+            let cb_sink = s.cb_sink().clone();
+            std::thread::spawn(move || {
+                let error = playback.status().err().map(|e| format!("{e}"));
+                let _ = cb_sink.send(Box::new(move |s| {
+                    if let Some(error) = error {
+                        s.call_on_name(HEADING, |view: &mut TextView| view.set_content(error));
+                    }
+                    reveal(s, &word);
+                }));
+            });
+        }
+        Some(Err(e)) => {
+            s.call_on_name(HEADING, |view: &mut TextView| {
+                view.set_content(e.to_string())
+            });
+            reveal(s, word);
+        }
+        // no sound
+        _ => reveal(s, word),
+    }
+}
+
+/// heading of the current cluster in `--clusters`
+fn history_heading(s: &mut Cursive) -> String {
+    s.with_user_data(|history: &mut SQLiteHistory| history.cluster_heading.clone())
+        .flatten()
+        .unwrap_or_default()
+}
+
 fn update_and_review_next(s: &mut Cursive, word: &str, rating: Rating) {
     s.with_user_data(|history: &mut SQLiteHistory| {
         let _ = block_on(history.update(word, rating));
@@ -297,3 +567,88 @@ fn show_answer_layout() -> LinearLayout {
             s.quit();
         }))
 }
+
+fn pair_title(pair: &Pair) -> String {
+    format!("{}{PAIR_SEPARATOR}{}", pair.words[0], pair.words[1])
+}
+
+fn show_pair_layout() -> LinearLayout {
+    LinearLayout::horizontal()
+        .child(Button::new("Skip", review_next_pair))
+        .child(TextView::new(" ".repeat(100)))
+        .child(Button::new("Show answer", show_pair_answer_cb))
+        .child(TextView::new(" ".repeat(100)))
+        .child(Button::new("Quit", |s| {
+            s.quit();
+        }))
+}
+
+fn show_pair_answer_cb(s: &mut Cursive) {
+    let Some(title) = s.call_on_name(OCEAN, |view: &mut Dialog| view.get_title().to_owned()) else {
+        return;
+    };
+    let Some((a, b)) = title.split_once(PAIR_SEPARATOR) else {
+        return;
+    };
+    // `a` opened last, on top
+    let mut answers: Vec<String> = [b, a]
+        .into_iter()
+        .flat_map(|word| show_answer(s, word).map(|text| format!("{word}\n{text}")))
+        .collect();
+    answers.reverse();
+    s.call_on_name(OCEAN, |view: &mut Dialog| {
+        // move to the next pair after both are rated
+        let rated = Arc::new(AtomicUsize::new(0));
+        let mut layout = LinearLayout::vertical()
+            .child(pair_rating_row(a, rated.clone()))
+            .child(pair_rating_row(b, rated))
+            .child(Button::new("Quit", |s| {
+                s.quit();
+            }));
+        if !answers.is_empty() {
+            layout.add_child(TextView::new(answers.join("\n\n")).scrollable());
+        }
+        view.set_content(layout);
+    });
+}
+
+fn pair_rating_row(word: &str, rated: Arc<AtomicUsize>) -> NamedView<LinearLayout> {
+    let name = format!("pair-{word}");
+    let mut row = LinearLayout::horizontal().child(TextView::new(format!("{word:>30}  ")));
+    for rating in [Rating::Again, Rating::Hard, Rating::Good, Rating::Easy] {
+        let word = word.to_owned();
+        let name = name.clone();
+        let rated = rated.clone();
+        row.add_child(Button::new(format!("{rating:?}"), move |s| {
+            s.with_user_data(|history: &mut SQLiteHistory| {
+                let _ = block_on(history.update(&word, rating));
+            });
+            s.call_on_name(&name, |row: &mut LinearLayout| {
+                row.clear();
+                row.add_child(TextView::new(format!("{word:>30}  {rating:?}")));
+            });
+            if rated.fetch_add(1, Ordering::SeqCst) + 1 == 2 {
+                review_next_pair(s);
+            }
+        }));
+        row.add_child(TextView::new(" "));
+    }
+    row.with_name(name)
+}
+
+fn review_next_pair(s: &mut Cursive) {
+    let next_pair = s
+        .with_user_data(|history: &mut SQLiteHistory| history.pairs.pop_front())
+        .flatten();
+    match next_pair {
+        Some(pair) => {
+            s.call_on_name(OCEAN, |view: &mut Dialog| {
+                view.set_title(pair_title(&pair));
+                view.set_content(show_pair_layout());
+            });
+        }
+        None => {
+            s.quit();
+        }
+    }
+}
@@ -0,0 +1,36 @@
+use anyhow::Result;
+use clap::Parser;
+use goldendict_ng_helper::config::Config;
+use goldendict_ng_helper::dictionary::open_dictionaries;
+use goldendict_ng_helper::fsrs::sqlite_history::SQLiteHistory;
+use goldendict_ng_helper::server::serve;
+
+/// HTTP/JSON API of history.db on localhost
+/// For browser extensions, editor plugins and goldendict-ng scripts
+/// Open it in a browser to review
+#[derive(Parser)]
+struct Args {
+    #[arg(long, default_value = "127.0.0.1")]
+    host: String,
+
+    #[arg(long, default_value_t = 8765)]
+    port: u16,
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let args = Args::parse();
+
+    let mut history = SQLiteHistory::default().await;
+    let config = Config::load()?;
+    let mut dictionaries = open_dictionaries(&config, &history.conn).await?;
+    let addr = format!("{}:{}", args.host, args.port);
+    println!("listening on http://{addr}");
+    serve(
+        &mut history,
+        &mut dictionaries,
+        &config.cors_origin_list(),
+        &addr,
+    )
+    .await
+}
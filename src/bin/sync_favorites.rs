@@ -0,0 +1,97 @@
+use anyhow::Result;
+use clap::Parser;
+use goldendict_ng_helper::favorite::{
+    add_headwords, extract_folders, read_favorites, write_favorites,
+};
+use goldendict_ng_helper::fsrs::sqlite_history::SQLiteHistory;
+use std::collections::HashSet;
+
+/// Sync goldendict favorites with history.db
+/// Folders are imported as tags, leeches and learned words are written back as folders
+/// Close goldendict-ng before syncing: it overwrites favorites on exit
+#[derive(Parser)]
+struct Args {
+    /// print the changes only
+    #[arg(long)]
+    dry_run: bool,
+
+    /// don't import folders as tags
+    #[arg(long)]
+    no_import: bool,
+
+    /// write leeches into this folder
+    #[arg(long)]
+    leeches_folder: Option<String>,
+
+    #[arg(long, default_value_t = 8)]
+    leech_lapses: i32,
+
+    /// write learned words into this folder
+    #[arg(long)]
+    learned_folder: Option<String>,
+
+    #[arg(long, default_value_t = 21)]
+    learned_days: i64,
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let args = Args::parse();
+
+    let mut history = if args.dry_run {
+        SQLiteHistory::without_session().await?
+    } else {
+        SQLiteHistory::default().await
+    };
+    let mut xml = read_favorites()?;
+
+    if !args.no_import {
+        let mut new_words = HashSet::new();
+        for (folder, words) in extract_folders(&xml)? {
+            for word in words {
+                let (word, new) = if args.dry_run {
+                    match history.stored_word(&word).await? {
+                        Some(word) => (word, false),
+                        // in more than one folder
+                        None => (word.clone(), new_words.insert(word)),
+                    }
+                } else {
+                    history.insert_if_absent(&word).await?
+                };
+                if new {
+                    println!("+ word: {word}");
+                }
+                if history.has_tag(&word, &folder).await? {
+                    continue;
+                }
+                println!("+ tag {folder}: {word}");
+                if !args.dry_run {
+                    history.add_tag(&word, &folder).await?;
+                }
+            }
+        }
+    }
+
+    let mut export = Vec::new();
+    if let Some(folder) = &args.leeches_folder {
+        export.push((folder, history.leeches(args.leech_lapses).await?));
+    }
+    if let Some(folder) = &args.learned_folder {
+        export.push((folder, history.learned(args.learned_days).await?));
+    }
+
+    let mut changed = false;
+    for (folder, words) in export {
+        let (new_xml, added) = add_headwords(&xml, folder, &words)?;
+        for word in &added {
+            println!("+ {folder}: {word}");
+        }
+        changed |= !added.is_empty();
+        xml = new_xml;
+    }
+
+    if changed && !args.dry_run {
+        write_favorites(&xml)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,94 @@
+use anyhow::Result;
+use clap::Parser;
+use goldendict_ng_helper::fsrs::{get_word_ignore_case, sqlite_history::SQLiteHistory};
+use goldendict_ng_helper::history::{read_history, write_history, Entry, Order};
+use std::collections::HashMap;
+
+/// Import lookups of goldendict history into history.db,
+/// then rewrite the history file from history.db
+#[derive(Parser)]
+struct Args {
+    #[arg(long, value_enum, default_value_t = Order::Recent)]
+    order: Order,
+
+    /// keep at most this many entries
+    #[arg(long)]
+    limit: Option<usize>,
+
+    /// print the imported words only
+    #[arg(long)]
+    dry_run: bool,
+}
+
+async fn import(history: &mut SQLiteHistory, entries: &[Entry], dry_run: bool) -> Result<()> {
+    for entry in entries {
+        if dry_run {
+            if get_word_ignore_case(&history.conn, &entry.word)
+                .await
+                .is_err()
+            {
+                println!("+ {}", entry.word);
+            }
+            continue;
+        }
+        let (word, new) = history.insert_if_absent(&entry.word).await?;
+        if new {
+            println!("+ {word}");
+        }
+    }
+    Ok(())
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let args = Args::parse();
+
+    let mut history = if args.dry_run {
+        SQLiteHistory::without_session().await?
+    } else {
+        SQLiteHistory::default().await
+    };
+    let (entries, modified) = read_history()?;
+    import(&mut history, &entries, args.dry_run).await?;
+    if args.dry_run {
+        return Ok(());
+    }
+
+    // goldendict may have written new lookups meanwhile
+    let (latest, latest_modified) = read_history()?;
+    let fresh: Vec<Entry> = if latest_modified != modified {
+        latest
+            .into_iter()
+            .filter(|entry| !entries.contains(entry))
+            .collect()
+    } else {
+        Vec::new()
+    };
+    import(&mut history, &fresh, false).await?;
+
+    let groups: HashMap<String, u32> = entries
+        .iter()
+        .chain(&fresh)
+        .map(|entry| (entry.word.to_lowercase(), entry.group))
+        .collect();
+
+    let mut v: Vec<Entry> = fresh.clone();
+    for word in history.words_ordered(args.order).await? {
+        if fresh
+            .iter()
+            .any(|entry| entry.word.eq_ignore_ascii_case(&word))
+        {
+            continue;
+        }
+        v.push(Entry {
+            group: groups.get(&word.to_lowercase()).copied().unwrap_or(0),
+            word,
+        });
+    }
+    if let Some(limit) = args.limit {
+        v.truncate(limit);
+    }
+
+    write_history(&v)?;
+    Ok(())
+}
@@ -0,0 +1,22 @@
+use anyhow::Result;
+use goldendict_ng_helper::fsrs::sqlite_history::SQLiteHistory;
+use goldendict_ng_helper::word2vec::{index_path, load_model};
+use std::env::args;
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    if args().nth(1).as_deref() == Some("--help") {
+        println!("build the index used by `review --word2vec`");
+        println!("BIN_PATH=/path/to/model.bin word2vec_index");
+        println!("rerun it after many words are added");
+        println!("https://github.com/lengyijun/goldendict-ng-reviewer");
+        return Ok(());
+    }
+
+    let model = load_model()?;
+    let history = SQLiteHistory::default().await;
+    let index = history.build_word2vec_index(&model).await?;
+    index.save(&index_path())?;
+    println!("{} words indexed in {:?}", index.len(), index_path());
+    Ok(())
+}
@@ -0,0 +1,135 @@
+//! ~/.config/goldendict/helper.json
+//!
+//! ```json
+//! {
+//!     "related": [
+//!         { "mdx": "/path/to/Merriam-Webster.mdx", "selector": ".kud a" },
+//!         {
+//!             "mdx": "/path/to/LDOCE.mdx",
+//!             "selector": ".crossRef a",
+//!             "selectors": [
+//!                 { "selector": ".syn a", "relation": "synonym" },
+//!                 { "selector": ".opp a", "relation": "antonym" }
+//!             ]
+//!         }
+//!     ],
+//!     "related_databases": ["/path/to/merriam.db", "/path/to/ldoce.db"],
+//!     "dictionaries": ["/path/to/Merriam-Webster.mdx", "/path/to/oald.ifo"],
+//!     "answer": "goldendict",
+//!     "audio": { "mdx": "/path/to/LDOCE.mdx" },
+//!     "player": "mpv --really-quiet {file}",
+//!     "sync_dir": "/path/to/Syncthing/goldendict",
+//!     "device": "laptop",
+//!     "cors_origin_list": ["http://localhost", "chrome-extension://<id>"]
+//! }
+//! ```
+
+use anyhow::Context;
+use anyhow::Result;
+use std::fs;
+use std::path::PathBuf;
+
+use crate::answer::Backend;
+use crate::audio::AudioSource;
+use crate::merriam::{merriam_db_path, Relation};
+
+pub fn config_path() -> PathBuf {
+    dirs::config_dir()
+        .unwrap()
+        .join("goldendict")
+        .join("helper.json")
+}
+
+#[derive(Debug, Default, serde::Deserialize)]
+#[serde(default)]
+pub struct Config {
+    /// dictionaries scanned by `build_related`
+    pub related: Vec<RelatedDictionary>,
+    /// searched by `review --merriam`
+    /// Default: merriam.db next to the executable
+    pub related_databases: Vec<PathBuf>,
+    /// `.mdx` or StarDict `.ifo`, shown by `serve` and `review --answer dictionary`
+    pub dictionaries: Vec<PathBuf>,
+    /// how `review` shows the answer, see `answer.rs`
+    pub answer: Backend,
+    /// pronunciation, see `audio.rs`
+    /// Default: espeak-ng
+    pub audio: Option<AudioSource>,
+    /// plays the sound of an mdd, `{file}` is replaced
+    /// Default: mpv
+    pub player: Option<String>,
+    /// shared folder holding one journal per device
+    pub sync_dir: Option<PathBuf>,
+    /// name of the journal in `sync_dir`
+    /// Default: the hostname
+    pub device: Option<String>,
+    /// browser origins allowed to call `serve`, like AnkiConnect's `webCorsOriginList`
+    /// `"*"` allows any origin
+    /// Default: `http://localhost`
+    pub cors_origin_list: Vec<String>,
+}
+
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct RelatedDictionary {
+    pub mdx: PathBuf,
+    /// css selector of the links to related words
+    #[serde(default = "default_selector")]
+    pub selector: String,
+    /// relation of the words found by `selector`
+    #[serde(default)]
+    pub relation: Relation,
+    /// more selectors, e.g. synonyms and antonyms in different sections
+    #[serde(default)]
+    pub selectors: Vec<RelationSelector>,
+}
+
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct RelationSelector {
+    pub selector: String,
+    pub relation: Relation,
+}
+
+/// Merriam-Webster's Collegiate Dictionary and Thesaurus
+pub fn default_selector() -> String {
+    ".kud a".to_owned()
+}
+
+impl RelatedDictionary {
+    pub fn selectors(&self) -> Vec<(Relation, &str)> {
+        let mut v = vec![(self.relation, self.selector.as_str())];
+        v.extend(
+            self.selectors
+                .iter()
+                .map(|x| (x.relation, x.selector.as_str())),
+        );
+        v
+    }
+}
+
+impl Config {
+    /// Default config if the file doesn't exist
+    pub fn load() -> Result<Self> {
+        let path = config_path();
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let s = fs::read_to_string(&path)?;
+        serde_json::from_str(&s).with_context(|| format!("fail to parse {:?}", path))
+    }
+
+    pub fn related_databases(&self) -> Vec<PathBuf> {
+        if self.related_databases.is_empty() {
+            vec![merriam_db_path()]
+        } else {
+            self.related_databases.clone()
+        }
+    }
+
+    pub fn cors_origin_list(&self) -> Vec<String> {
+        if self.cors_origin_list.is_empty() {
+            vec!["http://localhost".to_owned()]
+        } else {
+            self.cors_origin_list.clone()
+        }
+    }
+}
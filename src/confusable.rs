@@ -0,0 +1,167 @@
+//! Pairs of words often mixed up, e.g. affect/effect, complement/compliment
+//! Used by `review --confusables` and `confusables`
+
+use anyhow::Result;
+use sqlx::Row;
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::config::Config;
+use crate::fsrs::sqlite_history::SQLiteHistory;
+use crate::merriam::{RelatedDatabases, Relation};
+use crate::spelling::BkTree;
+
+const MAX_DISTANCE: usize = 2;
+/// Short words are all within `MAX_DISTANCE`
+const MIN_LEN: usize = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Reason {
+    /// edit distance
+    Spelling(usize),
+    Antonym,
+}
+
+impl fmt::Display for Reason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Reason::Spelling(distance) => write!(f, "spelling ({distance})"),
+            Reason::Antonym => write!(f, "antonym"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Pair {
+    pub words: [String; 2],
+    /// sum of both cards
+    pub lapses: i32,
+    pub reason: Reason,
+}
+
+impl SQLiteHistory {
+    /// Pairs with at least one lapsed word, the most lapses first
+    pub async fn confusable_pairs(&self) -> Result<Vec<Pair>> {
+        let related = RelatedDatabases::open(&Config::load()?).await?;
+        self.confusable_pairs_with(&related).await
+    }
+
+    async fn confusable_pairs_with(&self, related: &RelatedDatabases) -> Result<Vec<Pair>> {
+        let rows: Vec<(String, i32)> = sqlx::query("SELECT word, lapses FROM fsrs;")
+            .fetch_all(&self.conn)
+            .await?
+            .into_iter()
+            .map(|sqlite_row| (sqlite_row.get(0), sqlite_row.get(1)))
+            .collect();
+        // lowercase -> (the spelling with the most lapses, lapses of all spellings)
+        // case variants are one word, or they would pair at distance 0
+        let mut words: BTreeMap<String, (&str, i32, i32)> = BTreeMap::new();
+        for (word, l) in &rows {
+            let entry = words.entry(word.to_lowercase()).or_insert((word, *l, 0));
+            if *l > entry.1 {
+                (entry.0, entry.1) = (word, *l);
+            }
+            entry.2 += l;
+        }
+        let words: Vec<(String, &str, i32)> = words
+            .into_iter()
+            .map(|(lowercase, (word, _, lapses))| (lowercase, word, lapses))
+            .collect();
+        let index: HashMap<&str, usize> = words
+            .iter()
+            .enumerate()
+            .map(|(i, (lowercase, _, _))| (lowercase.as_str(), i))
+            .collect();
+        let mut tree = BkTree::new(strsim::levenshtein);
+        for (lowercase, _, _) in &words {
+            if lowercase.chars().count() >= MIN_LEN {
+                tree.insert(lowercase.clone());
+            }
+        }
+
+        let mut pairs: BTreeMap<(usize, usize), Reason> = BTreeMap::new();
+        for (i, (lowercase, _, lapses)) in words.iter().enumerate() {
+            if *lapses == 0 {
+                continue;
+            }
+
+            if lowercase.chars().count() >= MIN_LEN {
+                for (distance, other) in tree.find(lowercase, MAX_DISTANCE) {
+                    let j = index[other];
+                    if i != j {
+                        pairs
+                            .entry((i.min(j), i.max(j)))
+                            .or_insert(Reason::Spelling(distance));
+                    }
+                }
+            }
+
+            for antonym in related
+                .related_words(lowercase, &[Relation::Antonym])
+                .await?
+            {
+                match index.get(antonym.to_lowercase().as_str()) {
+                    Some(&j) if j != i => {
+                        pairs.entry((i.min(j), i.max(j))).or_insert(Reason::Antonym);
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        let mut v: Vec<Pair> = pairs
+            .into_iter()
+            .map(|((i, j), reason)| Pair {
+                words: [words[i].1.to_owned(), words[j].1.to_owned()],
+                lapses: words[i].2 + words[j].2,
+                reason,
+            })
+            .collect();
+        v.sort_by_key(|pair| std::cmp::Reverse(pair.lapses));
+        Ok(v)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::merriam::RelatedWords;
+    use rs_fsrs::Card;
+
+    #[tokio::test]
+    async fn case_variants_are_one_word() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut history = SQLiteHistory::temp(dir.path()).await;
+        for (word, lapses) in [
+            ("Affect", 2),
+            ("affect", 0),
+            ("effect", 1),
+            ("apple", 0),
+            ("big", 1),
+            ("small", 0),
+        ] {
+            let mut card = Card::new();
+            card.lapses = lapses;
+            history.write_card(word, card).await.unwrap();
+        }
+
+        let related_db = dir.path().join("related.db");
+        let mut related = RelatedWords::default();
+        related.insert("big", "Small", Relation::Antonym, "test");
+        related.write(related_db.to_str().unwrap()).await.unwrap();
+        let config = Config {
+            related_databases: vec![related_db],
+            ..Default::default()
+        };
+        let related = RelatedDatabases::open(&config).await.unwrap();
+
+        let pairs = history.confusable_pairs_with(&related).await.unwrap();
+        assert_eq!(pairs.len(), 2);
+        assert_eq!(pairs[0].words, ["Affect", "effect"]);
+        assert_eq!(pairs[0].lapses, 3);
+        assert_eq!(pairs[0].reason, Reason::Spelling(1));
+        assert_eq!(pairs[1].words, ["big", "small"]);
+        assert_eq!(pairs[1].reason, Reason::Antonym);
+    }
+}
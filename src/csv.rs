@@ -1,9 +1,11 @@
 use crate::dictionary_dir;
 use crate::fsrs::sqlite_history::SQLiteHistory;
+use anyhow::Context;
 use anyhow::Result;
+use sqlx::sqlite::SqlitePool;
+use sqlx::Row;
 use std::fs::File;
-use std::ops::Deref;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 /// https://github.com/skywind3000/ECDICT/blob/master/ecdict.csv
 fn ecdict_path() -> PathBuf {
@@ -13,41 +15,231 @@ fn ecdict_path() -> PathBuf {
 #[derive(Debug, serde::Deserialize)]
 pub struct Record {
     pub word: String,
+    pub phonetic: String,
+    pub definition: String,
+    pub translation: String,
+    pub pos: String,
+    /// collins star, 0 ~ 5
+    #[serde(deserialize_with = "csv::invalid_option")]
+    pub collins: Option<u32>,
+    /// 1: in oxford 3000
+    #[serde(deserialize_with = "csv::invalid_option")]
+    pub oxford: Option<u32>,
+    /// space separated exams, e.g. `cet4 cet6 gre`
+    pub tag: String,
     pub bnc: u32,
     pub frq: u32,
+    pub exchange: String,
 }
 
-fn get_records() -> Result<Vec<Record>> {
-    let file = File::open(ecdict_path())?;
-    let mut rdr = csv::Reader::from_reader(file);
-    let mut v = Vec::new();
-    v.reserve(770612);
-    for result in rdr.deserialize() {
-        // Notice that we need to provide a type hint for automatic
-        // deserialization.
-        let record: Record = result?;
-        v.push(record);
-    }
-    Ok(v)
+/// Kept out of history.db, which `merge`, `export` and sync copy
+/// Attached as `ecdict` to every connection of history.db
+pub fn ecdict_db_path(history: &Path) -> PathBuf {
+    history.with_file_name("ecdict.db")
+}
+
+/// Case variants are distinct rows (`polish`, `Polish`): the exact case first
+pub(crate) const ECDICT_WORD: &str = "word = $1 COLLATE NOCASE ORDER BY word = $1 DESC LIMIT 1";
+
+/// Whether `init_ecdict` created the table
+pub(crate) async fn has_ecdict(pool: &SqlitePool) -> Result<bool> {
+    let row = sqlx::query(
+        "SELECT COUNT(*) FROM ecdict.sqlite_master WHERE type = 'table' AND name = 'ecdict';",
+    )
+    .fetch_one(pool)
+    .await?;
+    Ok(row.get::<i64, _>(0) > 0)
 }
 
 impl SQLiteHistory {
-    pub fn init_records(&mut self) -> Result<()> {
-        self.records = get_records()?;
+    /// Load ecdict.csv into the `ecdict` table
+    /// Only done once: skipped if the table is already filled
+    pub async fn init_ecdict(&mut self) -> Result<()> {
+        self.import_ecdict(&ecdict_path()).await
+    }
+
+    pub(crate) async fn import_ecdict(&mut self, csv_path: &Path) -> Result<()> {
+        sqlx::raw_sql(
+            "CREATE TABLE IF NOT EXISTS ecdict.ecdict (
+    word TEXT PRIMARY KEY,
+    phonetic TEXT NOT NULL,
+    definition TEXT NOT NULL,
+    translation TEXT NOT NULL,
+    pos TEXT NOT NULL,
+    collins INTEGER NOT NULL,
+    oxford INTEGER NOT NULL,
+    tag TEXT NOT NULL,
+    bnc INTEGER NOT NULL,
+    frq INTEGER NOT NULL,
+    exchange TEXT NOT NULL
+) STRICT;
+CREATE INDEX IF NOT EXISTS ecdict.ecdict_nocase ON ecdict (word COLLATE NOCASE);",
+        )
+        .execute(&self.conn)
+        .await?;
+
+        let count: i64 = sqlx::query("SELECT COUNT(*) FROM ecdict;")
+            .fetch_one(&self.conn)
+            .await?
+            .get(0);
+        if count > 0 {
+            return Ok(());
+        }
+
+        eprintln!("importing {:?}, only done once", csv_path);
+        let file = File::open(csv_path)
+            .with_context(|| format!("Please download ecdict.csv to {:?}", csv_path))?;
+        let mut rdr = csv::Reader::from_reader(file);
+
+        let mut tx = self.conn.begin().await?;
+        for result in rdr.deserialize() {
+            // Notice that we need to provide a type hint for automatic
+            // deserialization.
+            let record: Record = result?;
+            sqlx::query("INSERT OR IGNORE INTO ecdict (word, phonetic, definition, translation, pos, collins, oxford, tag, bnc, frq, exchange) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11);")
+                .bind(&record.word)
+                .bind(&record.phonetic)
+                .bind(&record.definition)
+                .bind(&record.translation)
+                .bind(&record.pos)
+                .bind(record.collins.unwrap_or(0))
+                .bind(record.oxford.unwrap_or(0))
+                .bind(&record.tag)
+                .bind(record.bnc)
+                .bind(record.frq)
+                .bind(&record.exchange)
+                .execute(&mut *tx)
+                .await?;
+        }
+        tx.commit().await?;
         Ok(())
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dictionary::{lookup_ecdict, Dictionary};
+    use crate::filter::{Collins, Comparison, Filter};
+    use rs_fsrs::Card;
+
+    const CSV: &str = "\
+word,phonetic,definition,translation,pos,collins,oxford,tag,bnc,frq,exchange,detail,audio
+polish,'pɒlɪʃ,v. make smooth,v. 擦亮,,3,1,cet4,5000,4000,,,
+Polish,'pəʊlɪʃ,a. of Poland,a. 波兰的,,1,,,9000,8000,,,
+zeal,ziːl,n. enthusiasm,n. 热心,,2,,gre,20000,18000,,,
+";
+
+    async fn history_with_ecdict(dir: &Path) -> SQLiteHistory {
+        let csv_path = dir.join("ecdict.csv");
+        std::fs::write(&csv_path, CSV).unwrap();
+        let mut history = SQLiteHistory::temp(dir).await;
+        history.import_ecdict(&csv_path).await.unwrap();
+        history
+    }
+
+    #[tokio::test]
+    async fn ecdict_is_not_in_history_db() {
+        let dir = tempfile::tempdir().unwrap();
+        let history = history_with_ecdict(dir.path()).await;
+        assert!(has_ecdict(&history.conn).await.unwrap());
+        let in_main: i64 = sqlx::query(
+            "SELECT COUNT(*) FROM main.sqlite_master WHERE type = 'table' AND name = 'ecdict';",
+        )
+        .fetch_one(&history.conn)
+        .await
+        .unwrap()
+        .get(0);
+        assert_eq!(in_main, 0);
+        assert!(ecdict_db_path(&dir.path().join("history.db")).exists());
+    }
 
-    pub fn binary_search(&self, word: &str) -> Option<&Record> {
-        let word = word.to_lowercase();
+    #[tokio::test]
+    async fn old_table_is_dropped() {
+        let dir = tempfile::tempdir().unwrap();
+        let history = SQLiteHistory::temp(dir.path()).await;
+        sqlx::raw_sql("CREATE TABLE main.ecdict (word TEXT); PRAGMA user_version = 5;")
+            .execute(&history.conn)
+            .await
+            .unwrap();
+        history.conn.close().await;
+        let history = SQLiteHistory::temp(dir.path()).await;
+        let in_main: i64 =
+            sqlx::query("SELECT COUNT(*) FROM main.sqlite_master WHERE name = 'ecdict';")
+                .fetch_one(&history.conn)
+                .await
+                .unwrap()
+                .get(0);
+        assert_eq!(in_main, 0);
+    }
+
+    #[tokio::test]
+    async fn case_variants_are_kept() {
+        let dir = tempfile::tempdir().unwrap();
+        let history = history_with_ecdict(dir.path()).await;
+        let polish = lookup_ecdict(&history.conn, "polish")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(polish.translation, "v. 擦亮");
+        let poland = lookup_ecdict(&history.conn, "Polish")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(poland.translation, "a. 波兰的");
+        // no exact match: any case
+        let zeal = lookup_ecdict(&history.conn, "ZEAL").await.unwrap().unwrap();
+        assert_eq!(zeal.translation, "n. 热心");
+        assert!(lookup_ecdict(&history.conn, "absent")
+            .await
+            .unwrap()
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn ecdict_dictionary_is_awaited() {
+        let dir = tempfile::tempdir().unwrap();
+        let history = history_with_ecdict(dir.path()).await;
+        let mut ecdict = Dictionary::ecdict(&history.conn);
+        let html = ecdict.lookup("zeal").await.unwrap().unwrap();
+        assert!(html.contains("n. 热心"), "{html}");
+        assert!(ecdict.lookup("absent").await.unwrap().is_none());
+    }
 
-        let i = self
-            .records
-            .binary_search_by(|record| record.word.to_lowercase().deref().cmp(&word))
-            .ok()?;
-        Some(&self.records[i])
+    #[tokio::test]
+    async fn import_is_done_once() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut history = history_with_ecdict(dir.path()).await;
+        // the csv is not read again
+        history
+            .import_ecdict(&dir.path().join("missing.csv"))
+            .await
+            .unwrap();
     }
 
-    pub fn qualify(&self, record: &Record) -> bool {
-        (record.bnc != 0 && record.bnc <= self.freq) || (record.frq != 0 && record.frq <= self.freq)
+    #[tokio::test]
+    async fn filter_matches_any_case() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut history = history_with_ecdict(dir.path()).await;
+        for word in ["Zeal", "polish", "unknown"] {
+            history.insert_or_replace(word, Card::new()).await.unwrap();
+        }
+        let filter = Filter {
+            collins: Some(Collins {
+                comparison: Comparison::Ge,
+                star: 2,
+            }),
+            ..Filter::default()
+        };
+        let mut words: Vec<String> =
+            sqlx::query(&format!("SELECT word FROM fsrs WHERE 1{};", filter.sql()))
+                .fetch_all(&history.conn)
+                .await
+                .unwrap()
+                .iter()
+                .map(|row| row.get(0))
+                .collect();
+        words.sort();
+        assert_eq!(words, ["Zeal", "polish"]);
     }
 }
@@ -0,0 +1,289 @@
+//! Local dictionaries: ECDICT and the mdx or StarDict of `dictionaries` in the config
+//! Looked up the same way, see `Dictionary::lookup`
+
+use anyhow::anyhow;
+use anyhow::Result;
+use futures::executor::block_on;
+use scraper::{ElementRef, Html};
+use sqlx::sqlite::SqlitePool;
+use sqlx::Row;
+use std::path::Path;
+
+use crate::config::Config;
+use crate::csv::{has_ecdict, ECDICT_WORD};
+use crate::mdict::Mdx;
+use crate::stardict::StarDict;
+
+pub enum Source {
+    /// imported into history.db
+    Ecdict(SqlitePool),
+    Mdx(Mdx),
+    StarDict(StarDict),
+}
+
+pub struct Dictionary {
+    pub name: String,
+    pub source: Source,
+}
+
+pub(crate) fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('\n', "<br>")
+}
+
+impl Dictionary {
+    pub fn ecdict(conn: &SqlitePool) -> Self {
+        Self {
+            name: "ECDICT".to_owned(),
+            source: Source::Ecdict(conn.clone()),
+        }
+    }
+
+    /// `.mdx` or `.ifo`
+    pub fn open(path: &Path) -> Result<Self> {
+        let stem = path
+            .file_stem()
+            .map(|x| x.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        match path.extension().and_then(|x| x.to_str()) {
+            Some("mdx") => Ok(Self {
+                name: stem,
+                source: Source::Mdx(Mdx::open(path)?),
+            }),
+            Some("ifo") => {
+                let stardict = StarDict::open(path)?;
+                Ok(Self {
+                    name: Some(stardict.name.clone())
+                        .filter(|x| !x.is_empty())
+                        .unwrap_or(stem),
+                    source: Source::StarDict(stardict),
+                })
+            }
+            _ => Err(anyhow!("{:?}: only .mdx and .ifo are supported", path)),
+        }
+    }
+
+    /// HTML of the entry, case insensitive
+    pub async fn lookup(&mut self, word: &str) -> Result<Option<String>> {
+        match &mut self.source {
+            Source::Ecdict(conn) => Ok(lookup_ecdict(conn, word).await?.map(|x| x.html())),
+            Source::Mdx(mdx) => mdx.lookup(word),
+            Source::StarDict(stardict) => Ok(stardict.lookup(word)?.map(|fields| {
+                fields
+                    .into_iter()
+                    .map(|(t, text)| match t {
+                        'h' | 'g' | 'x' => text,
+                        't' => format!("<p>/{}/</p>", escape(&text)),
+                        _ => format!("<p>{}</p>", escape(&text)),
+                    })
+                    .collect()
+            })),
+        }
+    }
+
+    /// Blocks on ECDICT: for the callbacks of `review`, not the async server
+    pub fn lookup_text(&mut self, word: &str) -> Result<Option<String>> {
+        if let Source::Ecdict(conn) = &self.source {
+            return Ok(block_on(lookup_ecdict(conn, word))?.map(|x| x.text()));
+        }
+        Ok(block_on(self.lookup(word))?.map(|html| html_to_text(&html)))
+    }
+}
+
+fn is_block(tag: &str) -> bool {
+    matches!(
+        tag,
+        "p" | "div"
+            | "br"
+            | "li"
+            | "ul"
+            | "ol"
+            | "dl"
+            | "dt"
+            | "dd"
+            | "tr"
+            | "table"
+            | "section"
+            | "blockquote"
+            | "h1"
+            | "h2"
+            | "h3"
+            | "h4"
+            | "h5"
+            | "h6"
+            | "hr"
+    )
+}
+
+fn push_text(text: &str, out: &mut String) {
+    // collapse whitespace like a browser
+    if text.starts_with(char::is_whitespace)
+        && !out.is_empty()
+        && !out.ends_with(char::is_whitespace)
+    {
+        out.push(' ');
+    }
+    let words: Vec<&str> = text.split_whitespace().collect();
+    out.push_str(&words.join(" "));
+    if text.ends_with(char::is_whitespace) && !words.is_empty() {
+        out.push(' ');
+    }
+}
+
+fn push_element(element: ElementRef, out: &mut String) {
+    let tag = element.value().name();
+    if matches!(tag, "script" | "style" | "head" | "title") {
+        return;
+    }
+    let block = is_block(tag);
+    if block {
+        out.push('\n');
+    }
+    if tag == "li" {
+        out.push_str("• ");
+    }
+    for child in element.children() {
+        if let Some(element) = ElementRef::wrap(child) {
+            push_element(element, out);
+        } else if let Some(text) = child.value().as_text() {
+            push_text(text, out);
+        }
+    }
+    if block {
+        out.push('\n');
+    }
+}
+
+/// Block elements on their own lines
+pub fn html_to_text(html: &str) -> String {
+    let mut out = String::new();
+    push_element(Html::parse_fragment(html).root_element(), &mut out);
+    out.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// ECDICT first if imported, the ones failing to open are skipped
+pub async fn open_dictionaries(config: &Config, conn: &SqlitePool) -> Result<Vec<Dictionary>> {
+    let mut v = Vec::new();
+    if has_ecdict(conn).await? {
+        v.push(Dictionary::ecdict(conn));
+    }
+    for path in &config.dictionaries {
+        match Dictionary::open(path) {
+            Ok(dictionary) => v.push(dictionary),
+            Err(e) => eprintln!("{e}"),
+        }
+    }
+    Ok(v)
+}
+
+#[derive(Debug, Clone)]
+pub struct EcdictEntry {
+    pub phonetic: String,
+    /// English
+    pub definition: String,
+    /// Chinese
+    pub translation: String,
+}
+
+impl EcdictEntry {
+    pub fn text(&self) -> String {
+        let mut lines = Vec::new();
+        if !self.phonetic.is_empty() {
+            lines.push(format!("/{}/", self.phonetic));
+        }
+        for s in [&self.definition, &self.translation] {
+            if !s.trim().is_empty() {
+                lines.push(s.trim().to_owned());
+            }
+        }
+        lines.join("\n")
+    }
+
+    pub fn html(&self) -> String {
+        let mut html = String::new();
+        if !self.phonetic.is_empty() {
+            html.push_str(&format!("<p>/{}/</p>", escape(&self.phonetic)));
+        }
+        for s in [&self.definition, &self.translation] {
+            if !s.trim().is_empty() {
+                html.push_str(&format!("<p>{}</p>", escape(s.trim())));
+            }
+        }
+        html
+    }
+}
+
+/// `None` if ECDICT is not imported, see `SQLiteHistory::init_ecdict`
+pub async fn lookup_ecdict(pool: &SqlitePool, word: &str) -> Result<Option<EcdictEntry>> {
+    if !has_ecdict(pool).await? {
+        return Ok(None);
+    }
+    let entry = sqlx::query(&format!(
+        "SELECT phonetic, definition, translation FROM ecdict WHERE {ECDICT_WORD};"
+    ))
+    .bind(word)
+    .fetch_optional(pool)
+    .await?
+    .map(|sqlite_row| {
+        // ECDICT stores line breaks as "\n"
+        let get = |i| sqlite_row.get::<&str, _>(i).replace("\\n", "\n");
+        EcdictEntry {
+            phonetic: get(0),
+            definition: get(1),
+            translation: get(2),
+        }
+    });
+    Ok(entry)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn blocks_on_their_own_lines() {
+        let html = r#"<div><b>run</b>  <i>v.</i>
+            <ol><li>to move <a href="x">fast</a></li><li>to manage</li></ol>
+            <script>alert(1)</script><style>p {}</style>
+            <p>ran, run<br>running</p></div>"#;
+        assert_eq!(
+            html_to_text(html),
+            "run v.\n• to move fast\n• to manage\nran, run\nrunning"
+        );
+        assert_eq!(html_to_text("a&amp;b &lt;c&gt;"), "a&b <c>");
+        assert_eq!(html_to_text(""), "");
+    }
+
+    #[test]
+    fn ecdict_entry() {
+        let entry = EcdictEntry {
+            phonetic: "ruːn".to_owned(),
+            definition: " n. <ruin>\nv. destroy ".to_owned(),
+            translation: String::new(),
+        };
+        assert_eq!(entry.text(), "/ruːn/\nn. <ruin>\nv. destroy");
+        assert_eq!(
+            entry.html(),
+            "<p>/ruːn/</p><p>n. &lt;ruin&gt;<br>v. destroy</p>"
+        );
+        assert_eq!(html_to_text(&entry.html()), entry.text());
+    }
+
+    #[test]
+    fn open_by_extension() {
+        let testdata = Path::new(env!("CARGO_MANIFEST_DIR")).join("testdata");
+        let mut tiny = Dictionary::open(&testdata.join("tiny.mdx")).unwrap();
+        assert_eq!(tiny.name, "tiny");
+        assert_eq!(
+            tiny.lookup_text("apples").unwrap().as_deref(),
+            Some("apple play")
+        );
+        assert!(Dictionary::open(&testdata.join("mdx.py")).is_err());
+    }
+}
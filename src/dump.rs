@@ -0,0 +1,444 @@
+//! Plain text dumps of history.db: CSV, TSV and JSON Lines
+//!
+//! One card per line with its tags and contexts, round-trippable by `import`
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use rs_fsrs::{Card, State};
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+
+use crate::fsrs::sqlite_history::SQLiteHistory;
+use crate::fsrs::{get_all_cards, get_card, get_word_ignore_case};
+use crate::import::Context;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum TextFormat {
+    Csv,
+    Tsv,
+    Jsonl,
+}
+
+impl TextFormat {
+    pub fn from_path(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()? {
+            "csv" => Some(TextFormat::Csv),
+            "tsv" => Some(TextFormat::Tsv),
+            "jsonl" | "ndjson" => Some(TextFormat::Jsonl),
+            _ => None,
+        }
+    }
+
+    fn delimiter(self) -> u8 {
+        match self {
+            TextFormat::Tsv => b'\t',
+            _ => b',',
+        }
+    }
+}
+
+/// What to do with a word in both history.db and the file
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum Policy {
+    /// the card reviewed last wins
+    #[default]
+    Newer,
+    /// keep history.db
+    Mine,
+    /// take the file
+    Theirs,
+}
+
+impl Policy {
+    pub fn should_replace(self, mine: &Card, theirs: &Card) -> bool {
+        match self {
+            Policy::Newer => theirs.last_review > mine.last_review,
+            Policy::Mine => false,
+            Policy::Theirs => true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct Entry {
+    pub word: String,
+    pub due: DateTime<Utc>,
+    pub stability: f64,
+    pub difficulty: f64,
+    pub elapsed_days: i64,
+    pub scheduled_days: i64,
+    pub reps: i32,
+    pub lapses: i32,
+    pub state: State,
+    pub last_review: DateTime<Utc>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub contexts: Vec<Context>,
+}
+
+/// CSV has no nesting: tags and contexts are JSON strings
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+struct Record {
+    word: String,
+    due: DateTime<Utc>,
+    stability: f64,
+    difficulty: f64,
+    elapsed_days: i64,
+    scheduled_days: i64,
+    reps: i32,
+    lapses: i32,
+    state: State,
+    last_review: DateTime<Utc>,
+    #[serde(default)]
+    tags: String,
+    #[serde(default)]
+    contexts: String,
+}
+
+/// An empty cell is an empty list
+fn from_json_or_empty<T: serde::de::DeserializeOwned>(s: &str) -> Result<Vec<T>> {
+    if s.trim().is_empty() {
+        Ok(Vec::new())
+    } else {
+        Ok(serde_json::from_str(s)?)
+    }
+}
+
+impl Entry {
+    pub fn new(word: String, card: Card) -> Self {
+        Self {
+            word,
+            due: card.due,
+            stability: card.stability,
+            difficulty: card.difficulty,
+            elapsed_days: card.elapsed_days,
+            scheduled_days: card.scheduled_days,
+            reps: card.reps,
+            lapses: card.lapses,
+            state: card.state,
+            last_review: card.last_review,
+            tags: Vec::new(),
+            contexts: Vec::new(),
+        }
+    }
+
+    pub fn card(&self) -> Card {
+        Card {
+            due: self.due,
+            stability: self.stability,
+            difficulty: self.difficulty,
+            elapsed_days: self.elapsed_days,
+            scheduled_days: self.scheduled_days,
+            reps: self.reps,
+            lapses: self.lapses,
+            state: self.state,
+            last_review: self.last_review,
+        }
+    }
+
+    fn to_record(&self) -> Result<Record> {
+        Ok(Record {
+            word: self.word.clone(),
+            due: self.due,
+            stability: self.stability,
+            difficulty: self.difficulty,
+            elapsed_days: self.elapsed_days,
+            scheduled_days: self.scheduled_days,
+            reps: self.reps,
+            lapses: self.lapses,
+            state: self.state,
+            last_review: self.last_review,
+            tags: serde_json::to_string(&self.tags)?,
+            contexts: serde_json::to_string(&self.contexts)?,
+        })
+    }
+
+    fn from_record(record: Record) -> Result<Self> {
+        Ok(Self {
+            word: record.word,
+            due: record.due,
+            stability: record.stability,
+            difficulty: record.difficulty,
+            elapsed_days: record.elapsed_days,
+            scheduled_days: record.scheduled_days,
+            reps: record.reps,
+            lapses: record.lapses,
+            state: record.state,
+            last_review: record.last_review,
+            tags: from_json_or_empty(&record.tags)?,
+            contexts: from_json_or_empty(&record.contexts)?,
+        })
+    }
+}
+
+pub fn write_entries(path: &Path, format: TextFormat, entries: &[Entry]) -> Result<()> {
+    match format {
+        TextFormat::Jsonl => {
+            let mut w = BufWriter::new(File::create(path)?);
+            for entry in entries {
+                serde_json::to_writer(&mut w, entry)?;
+                w.write_all(b"\n")?;
+            }
+            w.flush()?;
+        }
+        TextFormat::Csv | TextFormat::Tsv => {
+            let mut w = csv::WriterBuilder::new()
+                .delimiter(format.delimiter())
+                .from_path(path)?;
+            for entry in entries {
+                w.serialize(entry.to_record()?)?;
+            }
+            w.flush()?;
+        }
+    }
+    Ok(())
+}
+
+pub fn read_entries(path: &Path, format: TextFormat) -> Result<Vec<Entry>> {
+    match format {
+        TextFormat::Jsonl => BufReader::new(File::open(path)?)
+            .lines()
+            .filter(|line| !matches!(line, Ok(line) if line.trim().is_empty()))
+            .map(|line| Ok(serde_json::from_str(&line?)?))
+            .collect(),
+        TextFormat::Csv | TextFormat::Tsv => csv::ReaderBuilder::new()
+            .delimiter(format.delimiter())
+            .from_path(path)?
+            .deserialize()
+            .map(|record| Entry::from_record(record?))
+            .collect(),
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Summary {
+    pub added: usize,
+    pub replaced: usize,
+    pub kept: usize,
+}
+
+impl SQLiteHistory {
+    pub async fn entries(&self) -> Result<Vec<Entry>> {
+        let mut v = Vec::new();
+        for (word, card) in get_all_cards(&self.conn).await? {
+            let mut entry = Entry::new(word, card);
+            entry.tags = self.tags(&entry.word).await?;
+            entry.contexts = self.contexts(&entry.word).await?;
+            v.push(entry);
+        }
+        Ok(v)
+    }
+
+    /// Tags and contexts are always merged, cards follow `policy`
+    pub async fn merge_entries(
+        &mut self,
+        entries: &[Entry],
+        policy: Policy,
+        dry_run: bool,
+    ) -> Result<Summary> {
+        let mut summary = Summary::default();
+        for entry in entries {
+            let word = match get_word_ignore_case(&self.conn, &entry.word).await {
+                Ok(word) => {
+                    let mine = get_card(&self.conn, &word).await?;
+                    if policy.should_replace(&mine, &entry.card()) {
+                        println!("~ {word}");
+                        summary.replaced += 1;
+                        if !dry_run {
+                            self.insert_or_replace(&word, entry.card()).await?;
+                        }
+                    } else {
+                        summary.kept += 1;
+                    }
+                    word
+                }
+                Err(_) => {
+                    println!("+ {}", entry.word);
+                    summary.added += 1;
+                    if !dry_run {
+                        self.insert_or_replace(&entry.word, entry.card()).await?;
+                    }
+                    entry.word.clone()
+                }
+            };
+            if dry_run {
+                continue;
+            }
+            for tag in &entry.tags {
+                self.add_tag(&word, tag).await?;
+            }
+            for context in &entry.contexts {
+                self.insert_context(&word, context).await?;
+            }
+        }
+        Ok(summary)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+
+    /// Reviewed `days_ago` before a fixed day, so that entries built twice compare equal
+    fn entry(word: &str, days_ago: i64) -> Entry {
+        let mut card = Card::new();
+        card.state = State::Review;
+        card.stability = 10.5;
+        card.reps = 3;
+        card.last_review =
+            DateTime::from_timestamp(1_700_000_000, 0).unwrap() - Duration::days(days_ago);
+        card.due = card.last_review + Duration::days(10);
+        Entry::new(word.to_owned(), card)
+    }
+
+    fn sample() -> Vec<Entry> {
+        let mut quoted = entry("quay", 2);
+        quoted.tags = vec!["leech".to_owned(), "a, b".to_owned()];
+        quoted.contexts = vec![Context {
+            sentence: "He said \"quay\",\tthen left.\nAgain".to_owned(),
+            book: Some("Moby Dick".to_owned()),
+            source: "kindle".to_owned(),
+            timestamp: Some(2460000.5),
+        }];
+        vec![quoted, entry("brine", 5)]
+    }
+
+    #[test]
+    fn round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        for (name, format) in [
+            ("a.csv", TextFormat::Csv),
+            ("a.tsv", TextFormat::Tsv),
+            ("a.jsonl", TextFormat::Jsonl),
+        ] {
+            let path = dir.path().join(name);
+            assert_eq!(TextFormat::from_path(&path), Some(format));
+            let entries = sample();
+            write_entries(&path, format, &entries).unwrap();
+            let read = read_entries(&path, format).unwrap();
+            assert_eq!(read.len(), entries.len(), "{name}");
+            for (a, b) in read.iter().zip(&entries) {
+                assert_eq!(a.word, b.word, "{name}");
+                assert_eq!(a.due, b.due, "{name}");
+                assert_eq!(a.last_review, b.last_review, "{name}");
+                assert_eq!(a.stability, b.stability, "{name}");
+                assert_eq!(a.state, b.state, "{name}");
+                assert_eq!(a.tags, b.tags, "{name}");
+                assert_eq!(a.contexts, b.contexts, "{name}");
+            }
+        }
+        assert_eq!(TextFormat::from_path(Path::new("a.txt")), None);
+    }
+
+    #[test]
+    fn missing_tags_and_contexts() {
+        let dir = tempfile::tempdir().unwrap();
+        let entry = entry("quay", 1);
+        let card = serde_json::to_value(entry.card()).unwrap();
+
+        let path = dir.path().join("a.csv");
+        let mut w = csv::Writer::from_path(&path).unwrap();
+        w.write_record([
+            "word",
+            "due",
+            "stability",
+            "difficulty",
+            "elapsed_days",
+            "scheduled_days",
+            "reps",
+            "lapses",
+            "state",
+            "last_review",
+            "tags",
+            "contexts",
+        ])
+        .unwrap();
+        let cell = |key: &str| match &card[key] {
+            serde_json::Value::String(s) => s.clone(),
+            v => v.to_string(),
+        };
+        w.write_record([
+            "quay".to_owned(),
+            cell("due"),
+            cell("stability"),
+            cell("difficulty"),
+            cell("elapsed_days"),
+            cell("scheduled_days"),
+            cell("reps"),
+            cell("lapses"),
+            cell("state"),
+            cell("last_review"),
+            String::new(),
+            String::new(),
+        ])
+        .unwrap();
+        w.flush().unwrap();
+        let read = read_entries(&path, TextFormat::Csv).unwrap();
+        assert!(read[0].tags.is_empty() && read[0].contexts.is_empty());
+
+        // blank lines are skipped, the keys default
+        let path = dir.path().join("a.jsonl");
+        let mut line = card;
+        line["word"] = "quay".into();
+        std::fs::write(&path, format!("{line}\n\n")).unwrap();
+        let read = read_entries(&path, TextFormat::Jsonl).unwrap();
+        assert_eq!(read.len(), 1);
+        assert!(read[0].tags.is_empty() && read[0].contexts.is_empty());
+    }
+
+    #[test]
+    fn policies() {
+        let older = entry("quay", 5).card();
+        let newer = entry("quay", 1).card();
+        assert!(Policy::Newer.should_replace(&older, &newer));
+        assert!(!Policy::Newer.should_replace(&newer, &older));
+        assert!(!Policy::Mine.should_replace(&older, &newer));
+        assert!(Policy::Theirs.should_replace(&newer, &older));
+    }
+
+    #[tokio::test]
+    async fn merge_by_policy() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut history = SQLiteHistory::temp(dir.path()).await;
+        history
+            .write_card("Quay", entry("Quay", 3).card())
+            .await
+            .unwrap();
+
+        // nothing is written on a dry run
+        let summary = history
+            .merge_entries(&sample(), Policy::Newer, true)
+            .await
+            .unwrap();
+        assert_eq!((summary.added, summary.replaced, summary.kept), (1, 1, 0));
+        assert!(history.stored_word("brine").await.unwrap().is_none());
+        assert!(history.tags("Quay").await.unwrap().is_empty());
+
+        let summary = history
+            .merge_entries(&sample(), Policy::Mine, false)
+            .await
+            .unwrap();
+        assert_eq!((summary.added, summary.replaced, summary.kept), (1, 0, 1));
+        let mine = get_card(&history.conn, "Quay").await.unwrap();
+        assert_eq!(mine.last_review, entry("Quay", 3).last_review);
+        // tags and contexts are merged whatever the policy, under the stored spelling
+        assert_eq!(history.tags("Quay").await.unwrap().len(), 2);
+        assert_eq!(history.contexts("Quay").await.unwrap().len(), 1);
+
+        let summary = history
+            .merge_entries(&sample(), Policy::Newer, false)
+            .await
+            .unwrap();
+        assert_eq!((summary.added, summary.replaced, summary.kept), (0, 1, 1));
+        let theirs = get_card(&history.conn, "Quay").await.unwrap();
+        assert_eq!(theirs.last_review, entry("quay", 2).last_review);
+        assert_eq!(
+            history.stored_word("quay").await.unwrap().as_deref(),
+            Some("Quay")
+        );
+        assert_eq!(history.contexts("Quay").await.unwrap().len(), 1);
+    }
+}
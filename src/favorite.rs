@@ -1,3 +1,4 @@
+use anyhow::anyhow;
 use anyhow::Result;
 use std::fs;
 use std::path::PathBuf;
@@ -9,37 +10,388 @@ fn favourites_path() -> PathBuf {
         .join("favorites")
 }
 
+/// A folder of goldendict-ng favorites
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Folder {
+    /// empty for the root
+    pub name: String,
+    pub expanded: bool,
+    pub folders: Vec<Folder>,
+    pub headwords: Vec<String>,
+}
+
+impl Folder {
+    fn from_node(node: roxmltree::Node) -> Self {
+        let mut folder = Folder {
+            name: node.attribute("name").unwrap_or_default().to_owned(),
+            expanded: node.attribute("expanded") == Some("1"),
+            ..Default::default()
+        };
+        for child in node.children() {
+            if child.has_tag_name("folder") {
+                folder.folders.push(Folder::from_node(child));
+            } else if child.has_tag_name("headword") {
+                if let Some(word) = child.text().map(str::trim).filter(|s| !s.is_empty()) {
+                    folder.headwords.push(word.to_owned());
+                }
+            }
+        }
+        folder
+    }
+
+    /// Headwords of this folder and all subfolders
+    pub fn words(&self) -> Vec<String> {
+        let mut v = self.headwords.clone();
+        for folder in &self.folders {
+            v.extend(folder.words());
+        }
+        v
+    }
+
+    /// (path, folder) of all subfolders, depth first
+    pub fn walk(&self) -> Vec<(String, &Folder)> {
+        let mut v = Vec::new();
+        for folder in &self.folders {
+            v.push((folder.name.clone(), folder));
+            v.extend(
+                folder
+                    .walk()
+                    .into_iter()
+                    .map(|(path, f)| (format!("{}/{path}", folder.name), f)),
+            );
+        }
+        v
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Favorites {
+    pub root: Folder,
+}
+
+impl Favorites {
+    pub fn parse(xml: &str) -> Result<Self> {
+        let doc = roxmltree::Document::parse(xml)?;
+        Ok(Self {
+            root: Folder::from_node(doc.root_element()),
+        })
+    }
+
+    pub fn load() -> Result<Self> {
+        Self::parse(&read_favorites()?)
+    }
+
+    /// `Work/Legal`, or a folder name unique in the whole tree
+    pub fn find(&self, path: &str) -> Result<&Folder> {
+        let mut folder = Some(&self.root);
+        for name in path.split('/').filter(|s| !s.is_empty()) {
+            folder = folder.and_then(|f| f.folders.iter().find(|x| x.name == name));
+        }
+        if let Some(folder) = folder.filter(|f| !std::ptr::eq(*f, &self.root)) {
+            return Ok(folder);
+        }
+
+        let all = self.root.walk();
+        let matched: Vec<&(String, &Folder)> = all.iter().filter(|(_, f)| f.name == path).collect();
+        match matched.as_slice() {
+            [(_, folder)] => Ok(folder),
+            [] => Err(anyhow!(
+                "folder {path} not found in favorites, available folders:\n{}",
+                self.paths().join("\n")
+            )),
+            v => Err(anyhow!(
+                "folder {path} is ambiguous, use one of:\n{}",
+                v.iter()
+                    .map(|(p, _)| p.as_str())
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            )),
+        }
+    }
+
+    /// Paths of all folders
+    pub fn paths(&self) -> Vec<String> {
+        self.root.walk().into_iter().map(|(path, _)| path).collect()
+    }
+}
+
+/// Words in the folder and its subfolders
 pub fn extract_words_from_favorites_folder(folder_name: &str) -> Result<Vec<String>> {
-    let s = fs::read_to_string(favourites_path()).unwrap();
-    let doc = roxmltree::Document::parse(&s).unwrap();
+    Ok(Favorites::load()?.find(folder_name)?.words())
+}
 
-    let folder: roxmltree::Node<'_, '_> = doc
-        .descendants()
-        .find(|n| n.attribute("name") == Some(folder_name))
-        .unwrap();
+pub fn extract_all_words_from_favorites() -> Result<Vec<String>> {
+    Ok(Favorites::load()?.root.words())
+}
 
-    let v: Vec<_> = folder
-        .children()
-        .flat_map(|x| x.children())
-        .flat_map(|x| x.text())
-        .map(str::to_owned)
-        .collect();
+pub fn read_favorites() -> Result<String> {
+    fs::read_to_string(favourites_path())
+        .map_err(|e| anyhow!("fail to read {:?}: {e}", favourites_path()))
+}
 
+/// Close goldendict-ng first: it overwrites the file on exit
+pub fn write_favorites(xml: &str) -> Result<()> {
+    fs::write(favourites_path(), xml)?;
+    Ok(())
+}
+
+/// (folder path, headwords) of every folder, subfolders excluded
+pub fn extract_folders(xml: &str) -> Result<Vec<(String, Vec<String>)>> {
+    let favorites = Favorites::parse(xml)?;
+    let v = favorites
+        .root
+        .walk()
+        .into_iter()
+        .map(|(path, folder)| (path, folder.headwords.clone()))
+        .collect();
     Ok(v)
 }
 
-pub fn extract_all_words_from_favorites() -> Result<Vec<String>> {
-    let s = fs::read_to_string(favourites_path()).unwrap();
-    let doc = roxmltree::Document::parse(&s).unwrap();
-
-    let v: Vec<_> = doc
-        .descendants()
-        .flat_map(|x| x.children())
-        .flat_map(|x| x.children())
-        .flat_map(|x| x.text())
-        .filter(|s| !s.chars().all(|c| c.is_whitespace()))
-        .map(str::to_owned)
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Indentation of one level, guessed from the document
+fn indent_unit(doc: &roxmltree::Document) -> String {
+    doc.root_element()
+        .children()
+        .filter(|n| n.is_text())
+        .flat_map(|n| n.text())
+        .find(|s| s.contains('\n'))
+        .and_then(|s| s.rsplit('\n').next())
+        .filter(|s| !s.is_empty() && s.chars().all(|c| c == ' ' || c == '\t'))
+        .unwrap_or(" ")
+        .to_owned()
+}
+
+/// Insert `children` (already indented) at the end of the element at `range`
+fn append_children(
+    xml: &str,
+    range: std::ops::Range<usize>,
+    tag: &str,
+    indent: &str,
+    children: &str,
+) -> String {
+    let element = &xml[range.clone()];
+    let (insert_at, replacement) = if let Some(open) = element.strip_suffix("/>") {
+        // `<folder name="x"/>` -> `<folder name="x">...</folder>`
+        let open = open.trim_end();
+        (range, format!("{open}>{children}\n{indent}</{tag}>"))
+    } else {
+        let close = range.start + element.rfind("</").unwrap();
+        let end = xml[..close].trim_end().len();
+        (end..end, children.to_owned())
+    };
+
+    let mut res = xml.to_owned();
+    res.replace_range(insert_at, &replacement);
+    res
+}
+
+fn child_folder<'a, 'input>(
+    node: roxmltree::Node<'a, 'input>,
+    name: &str,
+) -> Option<roxmltree::Node<'a, 'input>> {
+    node.children()
+        .find(|n| n.has_tag_name("folder") && n.attribute("name") == Some(name))
+}
+
+/// Add headwords to the folder at `path`, creating the last folder if missing
+/// The rest of the document is kept as is
+/// Returns the new document and the words really added
+pub fn add_headwords(xml: &str, path: &str, words: &[String]) -> Result<(String, Vec<String>)> {
+    let doc = roxmltree::Document::parse(xml)?;
+    let unit = indent_unit(&doc);
+
+    let names: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
+    let Some((name, parents)) = names.split_last() else {
+        return Err(anyhow!("empty folder path"));
+    };
+    let mut parent = doc.root_element();
+    for x in parents {
+        parent = child_folder(parent, x).ok_or_else(|| {
+            anyhow!(
+                "folder {path} not found in favorites, available folders:\n{}",
+                Favorites::parse(xml)
+                    .map(|f| f.paths().join("\n"))
+                    .unwrap_or_default()
+            )
+        })?;
+    }
+    let node = child_folder(parent, name);
+
+    let existed: Vec<&str> = node
+        .iter()
+        .flat_map(|n| n.children())
+        .filter(|n| n.has_tag_name("headword"))
+        .flat_map(|n| n.text())
         .collect();
+    let mut added: Vec<String> = Vec::new();
+    for word in words {
+        if !existed.contains(&word.as_str()) && !added.contains(word) {
+            added.push(word.clone());
+        }
+    }
+    if added.is_empty() {
+        return Ok((xml.to_owned(), added));
+    }
 
-    Ok(v)
+    let headwords = |depth: usize| -> String {
+        added
+            .iter()
+            .map(|w| format!("\n{}<headword>{}</headword>", unit.repeat(depth), escape(w)))
+            .collect()
+    };
+
+    let res = match node {
+        Some(node) => {
+            let depth = node.ancestors().filter(|n| n.is_element()).count();
+            append_children(
+                xml,
+                node.range(),
+                "folder",
+                &unit.repeat(depth - 1),
+                &headwords(depth),
+            )
+        }
+        None => {
+            let depth = parent.ancestors().filter(|n| n.is_element()).count();
+            let indent = unit.repeat(depth);
+            let folder = format!(
+                "\n{indent}<folder name=\"{}\" expanded=\"0\">{}\n{indent}</folder>",
+                escape(name),
+                headwords(depth + 1),
+            );
+            append_children(
+                xml,
+                parent.range(),
+                parent.tag_name().name(),
+                &unit.repeat(depth - 1),
+                &folder,
+            )
+        }
+    };
+    Ok((res, added))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const XML: &str = r#"<root>
+  <folder name="Work" expanded="1">
+    <headword>contract</headword>
+    <folder name="Legal" expanded="0"/>
+  </folder>
+  <headword>loose</headword>
+</root>
+"#;
+
+    fn words(v: &[&str]) -> Vec<String> {
+        v.iter().map(|w| w.to_string()).collect()
+    }
+
+    const TREE: &str = r#"<root>
+  <folder name="Work" expanded="1">
+    <headword>contract</headword>
+    <folder name="Legal">
+      <headword>tort</headword>
+      <headword> </headword>
+    </folder>
+    <folder name="Notes"/>
+  </folder>
+  <folder name="Home">
+    <folder name="Notes">
+      <headword>kettle</headword>
+    </folder>
+  </folder>
+</root>
+"#;
+
+    #[test]
+    fn parse_tree() {
+        let favorites = Favorites::parse(TREE).unwrap();
+        let work = &favorites.root.folders[0];
+        assert!(work.expanded);
+        assert!(!favorites.root.folders[1].expanded);
+        // blank headwords are skipped
+        assert_eq!(work.folders[0].headwords, ["tort"]);
+        assert_eq!(work.words(), ["contract", "tort"]);
+        assert_eq!(favorites.root.words(), ["contract", "tort", "kettle"]);
+        assert_eq!(
+            favorites.paths(),
+            ["Work", "Work/Legal", "Work/Notes", "Home", "Home/Notes"]
+        );
+    }
+
+    #[test]
+    fn find_by_path_or_unique_name() {
+        let favorites = Favorites::parse(TREE).unwrap();
+        assert_eq!(favorites.find("Work/Legal").unwrap().headwords, ["tort"]);
+        assert_eq!(
+            favorites.find("/Home/Notes/").unwrap().headwords,
+            ["kettle"]
+        );
+        assert_eq!(favorites.find("Legal").unwrap().headwords, ["tort"]);
+
+        let ambiguous = favorites.find("Notes").unwrap_err().to_string();
+        assert!(ambiguous.contains("Work/Notes") && ambiguous.contains("Home/Notes"));
+        assert!(favorites.find("Missing").is_err());
+        assert!(favorites.find("").is_err());
+    }
+
+    #[test]
+    fn folders_without_subfolders() {
+        assert_eq!(
+            extract_folders(XML).unwrap(),
+            [
+                ("Work".to_owned(), words(&["contract"])),
+                ("Work/Legal".to_owned(), Vec::new()),
+            ]
+        );
+    }
+
+    #[test]
+    fn add_to_existing_folder() {
+        let (xml, added) =
+            add_headwords(XML, "Work", &words(&["contract", "tort", "tort"])).unwrap();
+        assert_eq!(added, ["tort"]);
+        assert!(xml.contains(
+            "    <headword>contract</headword>\n    <folder name=\"Legal\" expanded=\"0\"/>\n    <headword>tort</headword>\n  </folder>"
+        ));
+        assert_eq!(extract_folders(&xml).unwrap()[0].1, ["contract", "tort"]);
+    }
+
+    #[test]
+    fn add_to_self_closing_folder() {
+        let (xml, _) = add_headwords(XML, "Work/Legal", &words(&["a < b"])).unwrap();
+        assert!(xml.contains(
+            "    <folder name=\"Legal\" expanded=\"0\">\n      <headword>a &lt; b</headword>\n    </folder>"
+        ));
+        assert_eq!(extract_folders(&xml).unwrap()[1].1, ["a < b"]);
+    }
+
+    #[test]
+    fn create_missing_folder() {
+        let (xml, added) = add_headwords(XML, "Leeches", &words(&["affect"])).unwrap();
+        assert_eq!(added, ["affect"]);
+        assert!(xml.ends_with(
+            "  <headword>loose</headword>\n  <folder name=\"Leeches\" expanded=\"0\">\n    <headword>affect</headword>\n  </folder>\n</root>\n"
+        ));
+        // the rest is kept as is
+        assert!(xml.starts_with(&XML[..XML.find("</root>").unwrap() - 1]));
+
+        assert!(add_headwords(XML, "Missing/Leeches", &words(&["affect"])).is_err());
+        assert!(add_headwords(XML, "", &words(&["affect"])).is_err());
+    }
+
+    #[test]
+    fn nothing_to_add() {
+        let (xml, added) = add_headwords(XML, "Work", &words(&["contract"])).unwrap();
+        assert!(added.is_empty());
+        assert_eq!(xml, XML);
+    }
 }
@@ -0,0 +1,183 @@
+//! Restrict the due words by data from ECDICT
+//! Evaluated inside the sql which selects due words, see `SQLiteHistory::init_ecdict`
+
+use anyhow::anyhow;
+use anyhow::Error;
+use std::fmt;
+use std::str::FromStr;
+
+/// Exams listed in the `tag` column of ECDICT
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Exam {
+    /// 中考
+    Zk,
+    /// 高考
+    Gk,
+    Cet4,
+    Cet6,
+    /// 考研
+    Ky,
+    Toefl,
+    Ielts,
+    Gre,
+}
+
+impl Exam {
+    fn tag(self) -> &'static str {
+        match self {
+            Exam::Zk => "zk",
+            Exam::Gk => "gk",
+            Exam::Cet4 => "cet4",
+            Exam::Cet6 => "cet6",
+            Exam::Ky => "ky",
+            Exam::Toefl => "toefl",
+            Exam::Ielts => "ielts",
+            Exam::Gre => "gre",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Comparison {
+    Lt,
+    Le,
+    Eq,
+    Ge,
+    Gt,
+}
+
+impl fmt::Display for Comparison {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            Comparison::Lt => "<",
+            Comparison::Le => "<=",
+            Comparison::Eq => "=",
+            Comparison::Ge => ">=",
+            Comparison::Gt => ">",
+        };
+        write!(f, "{s}")
+    }
+}
+
+/// `>=3`, `<2`, `=5` or `4` (same as `=4`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Collins {
+    pub comparison: Comparison,
+    pub star: u32,
+}
+
+impl FromStr for Collins {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let (comparison, star) = if let Some(x) = s.strip_prefix(">=") {
+            (Comparison::Ge, x)
+        } else if let Some(x) = s.strip_prefix("<=") {
+            (Comparison::Le, x)
+        } else if let Some(x) = s.strip_prefix('>') {
+            (Comparison::Gt, x)
+        } else if let Some(x) = s.strip_prefix('<') {
+            (Comparison::Lt, x)
+        } else if let Some(x) = s.strip_prefix('=') {
+            (Comparison::Eq, x)
+        } else {
+            (Comparison::Eq, s)
+        };
+        let star: u32 = star
+            .trim()
+            .parse()
+            .map_err(|_| anyhow!("expect collins star like `>=3`, found `{s}`"))?;
+        if star > 5 {
+            return Err(anyhow!("collins star is between 0 and 5"));
+        }
+        Ok(Collins { comparison, star })
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Filter {
+    /// 10000: frequent word
+    /// 30000: word often meet
+    pub frequency_max: Option<u32>,
+    /// skip the most frequent words
+    pub frequency_min: Option<u32>,
+    /// any of the exams
+    pub exam: Vec<Exam>,
+    pub collins: Option<Collins>,
+}
+
+impl Filter {
+    pub fn is_empty(&self) -> bool {
+        self.frequency_max.is_none()
+            && self.frequency_min.is_none()
+            && self.exam.is_empty()
+            && self.collins.is_none()
+    }
+
+    /// Appended to the `WHERE` clause of a query on `fsrs`
+    /// Empty if no filter
+    pub fn sql(&self) -> String {
+        if self.is_empty() {
+            return String::new();
+        }
+
+        let mut conditions = Vec::new();
+
+        if self.frequency_max.is_some() || self.frequency_min.is_some() {
+            let min = self.frequency_min.unwrap_or(1);
+            let max = self.frequency_max.unwrap_or(u32::MAX);
+            // bnc and frq are 0 when unknown
+            conditions.push(format!(
+                "((bnc != 0 AND bnc BETWEEN {min} AND {max}) OR (frq != 0 AND frq BETWEEN {min} AND {max}))"
+            ));
+        }
+
+        if !self.exam.is_empty() {
+            let exams: Vec<String> = self
+                .exam
+                .iter()
+                .map(|exam| format!("(' ' || tag || ' ') LIKE '% {} %'", exam.tag()))
+                .collect();
+            conditions.push(format!("({})", exams.join(" OR ")));
+        }
+
+        if let Some(Collins { comparison, star }) = self.collins {
+            conditions.push(format!("collins {comparison} {star}"));
+        }
+
+        format!(
+            " AND EXISTS (SELECT 1 FROM ecdict WHERE ecdict.word = fsrs.word COLLATE NOCASE AND {})",
+            conditions.join(" AND ")
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_collins() {
+        let collins: Collins = ">=3".parse().unwrap();
+        assert_eq!(collins.comparison, Comparison::Ge);
+        assert_eq!(collins.star, 3);
+        let collins: Collins = " 4 ".parse().unwrap();
+        assert_eq!(collins.comparison, Comparison::Eq);
+        assert_eq!(collins.star, 4);
+        assert!("6".parse::<Collins>().is_err());
+        assert!(">=x".parse::<Collins>().is_err());
+    }
+
+    #[test]
+    fn empty_filter_is_no_sql() {
+        assert_eq!(Filter::default().sql(), "");
+        let filter = Filter {
+            exam: vec![Exam::Cet4, Exam::Gre],
+            ..Filter::default()
+        };
+        let sql = filter.sql();
+        assert!(sql.contains("'% cet4 %'"));
+        assert!(sql.contains("'% gre %'"));
+    }
+}
@@ -1,10 +1,27 @@
 use anyhow::Result;
 use rs_fsrs::Card;
+use sqlx::sqlite::SqliteRow;
 use sqlx::Row;
 use sqlx::SqlitePool;
 
 pub mod sqlite_history;
 
+/// columns: due, stability, difficulty, elapsed_days, scheduled_days, reps, lapses, state, last_review
+fn card_from_row(sqlite_row: &SqliteRow, offset: usize) -> Result<Card> {
+    let card: Card = Card {
+        due: serde_json::from_str(sqlite_row.get(offset))?,
+        stability: sqlite_row.get(offset + 1),
+        difficulty: sqlite_row.get(offset + 2),
+        elapsed_days: sqlite_row.get(offset + 3),
+        scheduled_days: sqlite_row.get(offset + 4),
+        reps: sqlite_row.get(offset + 5),
+        lapses: sqlite_row.get(offset + 6),
+        state: serde_json::from_str(sqlite_row.get(offset + 7))?,
+        last_review: serde_json::from_str(sqlite_row.get(offset + 8))?,
+    };
+    Ok(card)
+}
+
 pub async fn get_card(pool: &SqlitePool, word: &str) -> Result<Card> {
     let sqlite_row = sqlx::query("SELECT due, stability, difficulty, elapsed_days, scheduled_days, reps, lapses, state, last_review
     FROM fsrs WHERE word = $1")
@@ -12,18 +29,18 @@ pub async fn get_card(pool: &SqlitePool, word: &str) -> Result<Card> {
         .fetch_one(pool)
         .await?;
 
-    let card: Card = Card {
-        due: serde_json::from_str(sqlite_row.get(0))?,
-        stability: sqlite_row.get(1),
-        difficulty: sqlite_row.get(2),
-        elapsed_days: sqlite_row.get(3),
-        scheduled_days: sqlite_row.get(4),
-        reps: sqlite_row.get(5),
-        lapses: sqlite_row.get(6),
-        state: serde_json::from_str(sqlite_row.get(7))?,
-        last_review: serde_json::from_str(sqlite_row.get(8))?,
-    };
-    Ok(card)
+    card_from_row(&sqlite_row, 0)
+}
+
+/// (word, card) ordered by rowid
+pub async fn get_all_cards(pool: &SqlitePool) -> Result<Vec<(String, Card)>> {
+    sqlx::query("SELECT word, due, stability, difficulty, elapsed_days, scheduled_days, reps, lapses, state, last_review
+    FROM fsrs ORDER BY rowid")
+        .fetch_all(pool)
+        .await?
+        .iter()
+        .map(|sqlite_row| Ok((sqlite_row.get(0), card_from_row(sqlite_row, 1)?)))
+        .collect()
 }
 
 pub async fn get_word_ignore_case(pool: &SqlitePool, word: &str) -> Result<String> {
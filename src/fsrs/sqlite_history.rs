@@ -1,8 +1,17 @@
 //! <https://github.com/kkawakam/rustyline/blob/master/src/sqlite_history.rs>
 //! History impl. based on SQLite
 
-use crate::csv::Record;
+use crate::answer::{Answer, GoldendictUrl};
+use crate::audio::Audio;
+use crate::confusable::Pair;
+use crate::csv::ecdict_db_path;
 use crate::db_path;
+use crate::filter::Filter;
+use crate::journal::{Journal, Op};
+use crate::merriam::{RelatedDatabases, Relation};
+use crate::revlog::rating_to_i64;
+use crate::spelling::{Metric, SpellingIndex};
+use crate::word2vec::cluster::Cluster;
 use anyhow::anyhow;
 use anyhow::Context;
 use anyhow::Result;
@@ -19,11 +28,11 @@ use sqlx::Row;
 use sqlx::Sqlite;
 use std::collections::VecDeque;
 use std::future::Future;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::pin::Pin;
 use std::str::FromStr;
 
-use super::get_card;
+use super::{get_card, get_word_ignore_case};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum ExtendStradegy {
@@ -31,25 +40,29 @@ pub enum ExtendStradegy {
     Levenshtein,
     Word2vec,
     Merriam,
+    /// sound alike: IPA of ECDICT, or Double Metaphone
+    Phonetic,
     /// 在 sqlite 中随机选择下一个
     Random,
     /// Used in only review a folder
     NoExtend,
 }
 
+/// Queues the words related to a word
+type ExtendFn = Box<
+    dyn for<'a> Fn(
+        &'a mut SQLiteHistory,
+        &'a str,
+    ) -> Pin<Box<dyn Future<Output = Result<()>> + 'a>>,
+>;
+
 impl ExtendStradegy {
-    fn get_fn(
-        self,
-    ) -> Box<
-        dyn for<'a> Fn(
-            &'a mut SQLiteHistory,
-            &'a str,
-        ) -> Pin<Box<dyn Future<Output = Result<()>> + 'a>>,
-    > {
+    fn get_fn(self) -> ExtendFn {
         match self {
             ExtendStradegy::Levenshtein => Box::new(|sh, word| Box::pin(sh.leven(word))),
             ExtendStradegy::Word2vec => Box::new(|sh, word| Box::pin(sh.extend_by_word2vec(word))),
             ExtendStradegy::Merriam => Box::new(|sh, word| Box::pin(sh.extend_by_merriam(word))),
+            ExtendStradegy::Phonetic => Box::new(|sh, word| Box::pin(sh.extend_by_phonetic(word))),
             ExtendStradegy::NoExtend | ExtendStradegy::Random => {
                 Box::new(|_, _| Box::pin(async { Ok(()) }))
             }
@@ -73,10 +86,9 @@ pub struct SQLiteHistory {
     pub bottom_history: Vec<String>,
     pub middle_history: Vec<String>,
     pub queue: VecDeque<String>,
-    /// The loaded csv
-    /// Ordered
-    pub records: Vec<Record>,
-    pub freq: u32,
+    /// Restrict due words by ECDICT
+    /// `init_ecdict` is required if not empty
+    pub filter: Filter,
 
     /// only review last N rows (recently updated rows)
     /// Make no sense if `!category.is_empty()`
@@ -86,6 +98,34 @@ pub struct SQLiteHistory {
 
     /// extend by `levenshtein` or `word2vec`
     pub extend_stradegy: ExtendStradegy,
+    /// used by `ExtendStradegy::Levenshtein`
+    pub metric: Metric,
+    pub max_distance: usize,
+    /// Jaro-Winkler only
+    pub min_similarity: f64,
+    /// built on the first extension
+    pub spelling_index: Option<SpellingIndex>,
+    /// used by `ExtendStradegy::Merriam`
+    /// empty means all relations
+    pub relations: Vec<Relation>,
+    /// opened once by `review`, or on the first extension
+    pub related: Option<RelatedDatabases>,
+
+    /// `review --clusters`: reviewed cluster by cluster
+    pub clusters: VecDeque<Cluster>,
+    /// heading of the cluster under review
+    pub cluster_heading: Option<String>,
+
+    /// `review --confusables`: drilled pair by pair
+    pub pairs: VecDeque<Pair>,
+
+    /// changes are appended to it, see `journal.rs`
+    pub journal: Option<Journal>,
+
+    /// shows the answer in `review`
+    pub answer: Box<dyn Answer>,
+    /// `review --audio`, `review --listen`
+    pub audio: Option<Audio>,
 }
 
 /*
@@ -105,11 +145,28 @@ impl SQLiteHistory {
     }
 
     async fn new(path: PathBuf) -> Result<Self> {
+        Self::with_journal(path, Journal::from_config()).await
+    }
+
+    /// No session row and no journal, for `--dry-run`
+    pub async fn without_session() -> Result<Self> {
+        Self::open(db_path(), None).await
+    }
+
+    /// Replay the journals of other devices on startup
+    pub async fn with_journal(path: PathBuf, journal: Option<Journal>) -> Result<Self> {
+        let mut sh = Self::open(path, journal).await?;
+        sh.create_session().await?;
+        sh.replay_journals().await?;
+        Ok(sh)
+    }
+
+    async fn open(path: PathBuf, journal: Option<Journal>) -> Result<Self> {
         let path = path.to_str().unwrap();
         if !Sqlite::database_exists(path).await? {
             Sqlite::create_database(path).await?;
         }
-        let conn = conn(path).await?;
+        let conn = history_conn(path).await?;
         let mut sh = Self {
             // not strictly consecutive...
             ignore_dups: true,
@@ -120,15 +177,25 @@ impl SQLiteHistory {
             bottom_history: Vec::new(),
             middle_history: Vec::new(),
             queue: VecDeque::new(),
-            records: Vec::new(),
-            freq: 0,
+            filter: Filter::default(),
             last_n_row: 0,
             // By default: review words looks similar
             extend_stradegy: ExtendStradegy::Levenshtein,
             // extend_stradegy: Box::new(|_sh, _word| Box::pin((async || Ok(()))())),
+            metric: Metric::Levenshtein,
+            max_distance: 2,
+            min_similarity: 0.9,
+            spelling_index: None,
+            relations: Vec::new(),
+            related: None,
+            clusters: VecDeque::new(),
+            cluster_heading: None,
+            pairs: VecDeque::new(),
+            journal,
+            answer: Box::new(GoldendictUrl),
+            audio: None,
         };
         sh.check_schema().await?;
-        sh.create_session().await?;
         Ok(sh)
     }
 
@@ -184,6 +251,90 @@ COMMIT;
             .execute(&self.conn)
             .await?;
         }
+        if user_version < 2 {
+            // tags are kept when a card is replaced: no foreign key
+            sqlx::raw_sql(
+                "
+BEGIN EXCLUSIVE;
+CREATE TABLE IF NOT EXISTS tag (
+    word TEXT NOT NULL,
+    tag TEXT NOT NULL,
+    PRIMARY KEY (word, tag)
+) STRICT;
+PRAGMA user_version = 2;
+COMMIT;
+                 ",
+            )
+            .execute(&self.conn)
+            .await?;
+        }
+        if user_version < 3 {
+            sqlx::raw_sql(
+                "
+BEGIN EXCLUSIVE;
+CREATE TABLE IF NOT EXISTS context (
+    word TEXT NOT NULL,
+    sentence TEXT NOT NULL,
+    book TEXT,
+    source TEXT NOT NULL,
+    timestamp REAL,
+    PRIMARY KEY (word, sentence)
+) STRICT;
+PRAGMA user_version = 3;
+COMMIT;
+                 ",
+            )
+            .execute(&self.conn)
+            .await?;
+        }
+        if user_version < 4 {
+            // timestamp: milliseconds since the unix epoch
+            sqlx::raw_sql(
+                "
+BEGIN EXCLUSIVE;
+CREATE TABLE IF NOT EXISTS review (
+    word TEXT NOT NULL,
+    rating INTEGER NOT NULL,
+    timestamp INTEGER NOT NULL,
+    PRIMARY KEY (word, timestamp)
+) STRICT;
+PRAGMA user_version = 4;
+COMMIT;
+                 ",
+            )
+            .execute(&self.conn)
+            .await?;
+        }
+        if user_version < 5 {
+            // lines of other devices' journals already replayed
+            sqlx::raw_sql(
+                "
+BEGIN EXCLUSIVE;
+CREATE TABLE IF NOT EXISTS journal (
+    device TEXT PRIMARY KEY,
+    line INTEGER NOT NULL
+) STRICT;
+PRAGMA user_version = 5;
+COMMIT;
+                 ",
+            )
+            .execute(&self.conn)
+            .await?;
+        }
+        if user_version < 6 {
+            // ECDICT moved to ecdict.db, imported again on first use
+            sqlx::raw_sql(
+                "
+BEGIN EXCLUSIVE;
+DROP TABLE IF EXISTS main.ecdict;
+PRAGMA user_version = 6;
+COMMIT;
+PRAGMA incremental_vacuum;
+                 ",
+            )
+            .execute(&self.conn)
+            .await?;
+        }
         sqlx::query("pragma foreign_keys = 1;")
             .execute(&self.conn)
             .await?;
@@ -220,6 +371,12 @@ COMMIT;
     }
 
     pub async fn insert_or_replace(&mut self, word: &str, card: Card) -> Result<()> {
+        self.write_card(word, card.clone()).await?;
+        self.journal_append(word, Op::Card { card }, Utc::now())
+    }
+
+    /// Not journaled
+    pub(crate) async fn write_card(&mut self, word: &str, card: Card) -> Result<()> {
         // ignore SQLITE_CONSTRAINT_UNIQUE
 
         let _sqlite_query_result = sqlx::query("INSERT OR REPLACE INTO fsrs (session_id, word, due, stability, difficulty, elapsed_days, scheduled_days, reps, lapses, state, last_review) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11) RETURNING rowid;")
@@ -239,42 +396,32 @@ COMMIT;
         Ok(())
     }
 
-    async fn leven(&mut self, word: &str) -> Result<()> {
-        let similar_words =
-            sqlx::query("SELECT word FROM fsrs where word != $1 AND session_id != $2;")
-                .bind(word)
-                .bind(self.session_id)
-                .fetch_all(&self.conn)
-                .await?
-                .into_iter()
-                .map(|sqlite_row| sqlite_row.get(0))
-                .filter(|a: &String| strsim::levenshtein(a, word) <= 2);
+    /// The word as stored, case insensitive
+    pub async fn stored_word(&self, word: &str) -> Result<Option<String>> {
+        Ok(get_word_ignore_case(&self.conn, word).await.ok())
+    }
 
-        self.queue.extend(similar_words);
-        Ok(())
+    /// Returns the word as stored, and whether it's new
+    pub async fn insert_if_absent(&mut self, word: &str) -> Result<(String, bool)> {
+        match self.stored_word(word).await? {
+            Some(word) => Ok((word, false)),
+            None => {
+                self.insert_or_replace(word, Card::new()).await?;
+                Ok((word.to_owned(), true))
+            }
+        }
     }
 
     async fn next_to_review_db(&mut self) -> Result<String> {
-        if self.freq == 0 {
-            self.next_to_review_db_inner().await
-        } else {
-            loop {
-                let word = self.next_to_review_db_inner().await?;
-                match self.binary_search(&word) {
-                    Some(record) => {
-                        if self.qualify(record) {
-                            return Ok(word);
-                        }
-                    }
-                    None => {}
-                }
-            }
+        match self.next_to_review_db_inner().await {
+            Err(e) if !self.filter.is_empty() => Err(e.context("No words due in this band")),
+            x => x,
         }
     }
 
     async fn next_to_review_db_inner(&mut self) -> Result<String> {
         let row = if self.last_n_row == 0 {
-            match sqlx::query("SELECT rowid, word FROM fsrs WHERE timediff('now', substr(due, 2, length(due) - 2)) LIKE '+%' AND session_id < $1 AND rowid > $2 ORDER BY RANDOM() LIMIT 1;")
+            match sqlx::query(&format!("SELECT rowid, word FROM fsrs WHERE timediff('now', substr(due, 2, length(due) - 2)) LIKE '+%' AND session_id < $1 AND rowid > $2{} ORDER BY RANDOM() LIMIT 1;", self.filter.sql()))
                 .bind(self.session_id)
                 .bind(self.row_id)
                 .fetch_one(&self.conn)
@@ -284,14 +431,14 @@ COMMIT;
                     }
                     Err(_) => {
                         // search from start
-                         sqlx::query("SELECT rowid, word FROM fsrs WHERE timediff('now', substr(due, 2, length(due) - 2)) LIKE '+%' AND session_id < $1 ORDER BY RANDOM() LIMIT 1;")
+                         sqlx::query(&format!("SELECT rowid, word FROM fsrs WHERE timediff('now', substr(due, 2, length(due) - 2)) LIKE '+%' AND session_id < $1{} ORDER BY RANDOM() LIMIT 1;", self.filter.sql()))
                             .bind(self.session_id)
                             .fetch_one(&self.conn)
                             .await?
                     }
                 }
         } else {
-            match sqlx::query(&format!("SELECT rowid, word FROM fsrs WHERE timediff('now', substr(due, 2, length(due) - 2)) LIKE '+%' AND session_id < $1 AND rowid > $2 AND rowid > (SELECT MAX(rowid) - {} FROM fsrs) ORDER BY RANDOM() LIMIT 1;", self.last_n_row))
+            match sqlx::query(&format!("SELECT rowid, word FROM fsrs WHERE timediff('now', substr(due, 2, length(due) - 2)) LIKE '+%' AND session_id < $1 AND rowid > $2 AND rowid > (SELECT MAX(rowid) - {} FROM fsrs){} ORDER BY RANDOM() LIMIT 1;", self.last_n_row, self.filter.sql()))
                 .bind(self.session_id)
                 .bind(self.row_id)
                 .fetch_one(&self.conn)
@@ -301,7 +448,7 @@ COMMIT;
                     }
                     Err(_) => {
                         // search from start
-                         sqlx::query(&format!("SELECT rowid, word FROM fsrs WHERE timediff('now', substr(due, 2, length(due) - 2)) LIKE '+%' AND session_id < $1 AND rowid > (SELECT MAX(rowid) - {} FROM fsrs) ORDER BY RANDOM() LIMIT 1;", self.last_n_row))
+                         sqlx::query(&format!("SELECT rowid, word FROM fsrs WHERE timediff('now', substr(due, 2, length(due) - 2)) LIKE '+%' AND session_id < $1 AND rowid > (SELECT MAX(rowid) - {} FROM fsrs){} ORDER BY RANDOM() LIMIT 1;", self.last_n_row, self.filter.sql()))
                             .bind(self.session_id)
                             .fetch_one(&self.conn)
                             .await?
@@ -325,6 +472,20 @@ COMMIT;
             }
         }
 
+        while let Some(cluster) = self.clusters.pop_front() {
+            self.cluster_heading = Some(cluster.heading);
+            self.queue.extend(cluster.words);
+
+            while let Some(word) = self.queue.pop_front() {
+                if !self.bottom_history.contains(&word) || !self.middle_history.contains(&word) {
+                    return Ok(word);
+                }
+            }
+        }
+
+        // extensions are not part of the last cluster
+        self.cluster_heading = None;
+
         while let Some(word) = self.middle_history.pop() {
             let extend = self.extend_stradegy.get_fn();
             let _ = extend(self, &word).await;
@@ -355,15 +516,44 @@ COMMIT;
         let old_card = get_card(&self.conn, question)
             .await
             .context("get old card fail")?;
-        let scheduling_info = self.fsrs.next(old_card, Utc::now(), rating);
-        self.insert_or_replace(question, scheduling_info.card).await
+        let now = Utc::now();
+        let card = self.fsrs.next(old_card, now, rating).card;
+        self.write_card(question, card.clone()).await?;
+        self.log_review(question, rating, now.timestamp_millis())
+            .await?;
+        self.journal_append(
+            question,
+            Op::Review {
+                rating: rating_to_i64(rating),
+                card,
+            },
+            now,
+        )
     }
 
     pub async fn delete(&self, question: &str) -> Result<()> {
+        self.delete_rows(question).await?;
+        self.journal_append(question, Op::Delete, Utc::now())
+    }
+
+    /// Not journaled
+    pub(crate) async fn delete_rows(&self, question: &str) -> Result<()> {
         sqlx::query("DELETE FROM fsrs WHERE word = $1")
             .bind(question)
             .execute(&self.conn)
             .await?;
+        sqlx::query("DELETE FROM tag WHERE word = $1")
+            .bind(question)
+            .execute(&self.conn)
+            .await?;
+        sqlx::query("DELETE FROM context WHERE word = $1")
+            .bind(question)
+            .execute(&self.conn)
+            .await?;
+        sqlx::query("DELETE FROM review WHERE word = $1")
+            .bind(question)
+            .execute(&self.conn)
+            .await?;
         Ok(())
     }
 
@@ -387,6 +577,16 @@ COMMIT;
         Ok(phrases)
     }
 
+    pub async fn all_words(&self) -> Result<Vec<String>> {
+        let words: Vec<String> = sqlx::query("SELECT word FROM fsrs;")
+            .fetch_all(&self.conn)
+            .await?
+            .into_iter()
+            .map(|sqlite_row| sqlite_row.get(0))
+            .collect();
+        Ok(words)
+    }
+
     pub async fn all_words_need_review(&self) -> Result<Vec<String>> {
         let words: Vec<String> = sqlx::query("SELECT word FROM fsrs WHERE timediff('now', substr(due, 2, length(due) - 2)) LIKE '+%' AND session_id != $1;")
                 .bind(self.session_id)
@@ -399,7 +599,69 @@ COMMIT;
     }
 }
 
+#[cfg(test)]
+impl SQLiteHistory {
+    /// history.db in `dir`, no journal
+    pub(crate) async fn temp(dir: &Path) -> Self {
+        Self::with_journal(dir.join("history.db"), None)
+            .await
+            .unwrap()
+    }
+}
+
 pub async fn conn(path: &str) -> sqlx::Result<SqlitePool> {
     let options = SqliteConnectOptions::from_str(path)?.with_regexp();
     SqlitePoolOptions::new().connect_with(options).await
 }
+
+/// `conn` with `ecdict.db` attached as `ecdict`, created if missing
+async fn history_conn(path: &str) -> sqlx::Result<SqlitePool> {
+    let options = SqliteConnectOptions::from_str(path)?
+        .with_regexp()
+        .create_if_missing(true);
+    let ecdict = ecdict_db_path(Path::new(path))
+        .to_string_lossy()
+        .into_owned();
+    SqlitePoolOptions::new()
+        .after_connect(move |conn, _meta| {
+            let ecdict = ecdict.clone();
+            Box::pin(async move {
+                sqlx::query("ATTACH DATABASE $1 AS ecdict;")
+                    .bind(ecdict)
+                    .execute(conn)
+                    .await?;
+                Ok(())
+            })
+        })
+        .connect_with(options)
+        .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn open_without_session() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("history.db");
+        let history = SQLiteHistory::open(path.clone(), None).await.unwrap();
+        assert_eq!(history.session_id, 0);
+        let sessions: i64 = sqlx::query("SELECT COUNT(*) FROM session;")
+            .fetch_one(&history.conn)
+            .await
+            .unwrap()
+            .get(0);
+        assert_eq!(sessions, 0);
+        assert_eq!(history.stored_word("Apple").await.unwrap(), None);
+        drop(history);
+
+        let mut history = SQLiteHistory::with_journal(path, None).await.unwrap();
+        assert_eq!(history.session_id, 1);
+        history.insert_if_absent("apple").await.unwrap();
+        assert_eq!(
+            history.stored_word("APPLE").await.unwrap().as_deref(),
+            Some("apple")
+        );
+    }
+}
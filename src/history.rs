@@ -0,0 +1,143 @@
+//! goldendict-ng history file: `~/.local/share/goldendict/history`
+//!
+//! One lookup per line, the newest first: `<group id> <word>`
+
+use anyhow::Result;
+use sqlx::Row;
+use std::fs;
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+use crate::dictionary_dir;
+use crate::fsrs::sqlite_history::SQLiteHistory;
+
+pub fn history_path() -> PathBuf {
+    dictionary_dir().join("history")
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    pub group: u32,
+    pub word: String,
+}
+
+pub fn parse(s: &str) -> Vec<Entry> {
+    s.lines()
+        .flat_map(|line| {
+            let (group, word) = line.split_once(' ')?;
+            let word = word.trim();
+            if word.is_empty() {
+                return None;
+            }
+            Some(Entry {
+                group: group.parse().ok()?,
+                word: word.to_owned(),
+            })
+        })
+        .collect()
+}
+
+pub fn format(entries: &[Entry]) -> String {
+    entries
+        .iter()
+        .map(|entry| format!("{} {}\n", entry.group, entry.word))
+        .collect()
+}
+
+/// Entries and the modification time, empty if the file doesn't exist
+pub fn read_history() -> Result<(Vec<Entry>, Option<SystemTime>)> {
+    let path = history_path();
+    if !path.exists() {
+        return Ok((Vec::new(), None));
+    }
+    let modified = fs::metadata(&path)?.modified().ok();
+    Ok((parse(&fs::read_to_string(&path)?), modified))
+}
+
+/// Write to a temporary file then rename,
+/// so goldendict never reads a half written file
+pub fn write_history(entries: &[Entry]) -> Result<()> {
+    let path = history_path();
+    let tmp = path.with_extension("tmp");
+    fs::write(&tmp, format(entries))?;
+    fs::rename(tmp, path)?;
+    Ok(())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum Order {
+    /// the earliest due first
+    Due,
+    /// the latest added first
+    #[default]
+    Recent,
+}
+
+impl SQLiteHistory {
+    pub async fn words_ordered(&self, order: Order) -> Result<Vec<String>> {
+        let sql = match order {
+            // `due` is a JSON string, its text doesn't sort by time
+            Order::Due => {
+                "SELECT word FROM fsrs ORDER BY julianday(substr(due, 2, length(due) - 2));"
+            }
+            Order::Recent => "SELECT word FROM fsrs ORDER BY rowid DESC;",
+        };
+        let words: Vec<String> = sqlx::query(sql)
+            .fetch_all(&self.conn)
+            .await?
+            .into_iter()
+            .map(|sqlite_row| sqlite_row.get(0))
+            .collect();
+        Ok(words)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{DateTime, Utc};
+    use rs_fsrs::Card;
+
+    #[test]
+    fn parse_and_format() {
+        let entries = parse("1 apple\n\n2 ice cream \nx pear\n3 \n");
+        assert_eq!(
+            entries,
+            [
+                Entry {
+                    group: 1,
+                    word: "apple".to_owned()
+                },
+                Entry {
+                    group: 2,
+                    word: "ice cream".to_owned()
+                },
+            ]
+        );
+        assert_eq!(format(&entries), "1 apple\n2 ice cream\n");
+    }
+
+    #[tokio::test]
+    async fn order_by_due_time() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut history = SQLiteHistory::temp(dir.path()).await;
+        // as text, `.5Z` sorts before `Z`
+        for (word, due) in [
+            ("later", "2026-01-01T00:00:00.5Z"),
+            ("earlier", "2026-01-01T00:00:00Z"),
+            ("latest", "2026-01-02T00:00:00Z"),
+        ] {
+            let mut card = Card::new();
+            card.due = due.parse::<DateTime<Utc>>().unwrap();
+            history.write_card(word, card).await.unwrap();
+        }
+        assert_eq!(
+            history.words_ordered(Order::Due).await.unwrap(),
+            ["earlier", "later", "latest"]
+        );
+        assert_eq!(
+            history.words_ordered(Order::Recent).await.unwrap(),
+            ["latest", "earlier", "later"]
+        );
+    }
+}
@@ -0,0 +1,309 @@
+//! Looked up words of e-readers
+//!
+//! Kindle: `system/vocabulary/vocab.db`
+//! KOReader: `settings/vocabulary_builder.sqlite3`
+
+use anyhow::anyhow;
+use anyhow::Result;
+use sqlx::sqlite::SqliteConnectOptions;
+use sqlx::sqlite::SqlitePool;
+use sqlx::sqlite::SqlitePoolOptions;
+use sqlx::Row;
+use std::path::Path;
+
+use crate::fsrs::sqlite_history::SQLiteHistory;
+use crate::merriam::has_table;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Source {
+    Kindle,
+    Koreader,
+}
+
+impl Source {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Source::Kindle => "kindle",
+            Source::Koreader => "koreader",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Lookup {
+    pub word: String,
+    pub sentence: Option<String>,
+    pub book: Option<String>,
+    /// seconds since the unix epoch
+    pub timestamp: Option<f64>,
+}
+
+pub async fn open_read_only(path: &Path) -> Result<SqlitePool> {
+    let options = SqliteConnectOptions::new().filename(path).read_only(true);
+    Ok(SqlitePoolOptions::new().connect_with(options).await?)
+}
+
+pub async fn detect(pool: &SqlitePool) -> Result<Source> {
+    if has_table(pool, "WORDS").await? && has_table(pool, "LOOKUPS").await? {
+        Ok(Source::Kindle)
+    } else if has_table(pool, "vocabulary").await? {
+        Ok(Source::Koreader)
+    } else {
+        Err(anyhow!(
+            "neither Kindle vocab.db nor KOReader vocabulary builder"
+        ))
+    }
+}
+
+/// `stem`: the dictionary form instead of the inflected word
+pub async fn kindle(pool: &SqlitePool, stem: bool) -> Result<Vec<Lookup>> {
+    let word = if stem {
+        "COALESCE(NULLIF(WORDS.stem, ''), WORDS.word)"
+    } else {
+        "WORDS.word"
+    };
+    let v = sqlx::query(&format!(
+        "SELECT {word}, LOOKUPS.usage, BOOK_INFO.title, LOOKUPS.timestamp FROM LOOKUPS
+        JOIN WORDS ON WORDS.id = LOOKUPS.word_key
+        LEFT JOIN BOOK_INFO ON BOOK_INFO.id = LOOKUPS.book_key
+        ORDER BY LOOKUPS.timestamp;"
+    ))
+    .fetch_all(pool)
+    .await?
+    .into_iter()
+    .map(|sqlite_row| Lookup {
+        word: sqlite_row.get(0),
+        sentence: sqlite_row.get(1),
+        book: sqlite_row.get(2),
+        // milliseconds
+        timestamp: sqlite_row
+            .get::<Option<i64>, _>(3)
+            .map(|ms| ms as f64 / 1000.0),
+    })
+    .collect();
+    Ok(v)
+}
+
+pub async fn koreader(pool: &SqlitePool) -> Result<Vec<Lookup>> {
+    let has_title = has_table(pool, "title").await?;
+    let sql = if has_title {
+        "SELECT vocabulary.word, vocabulary.prev_context, vocabulary.next_context, title.name, vocabulary.create_time FROM vocabulary
+        LEFT JOIN title ON title.id = vocabulary.title_id
+        ORDER BY vocabulary.create_time;"
+    } else {
+        "SELECT word, prev_context, next_context, NULL, create_time FROM vocabulary ORDER BY create_time;"
+    };
+    let v = sqlx::query(sql)
+        .fetch_all(pool)
+        .await?
+        .into_iter()
+        .map(|sqlite_row| {
+            let word: String = sqlite_row.get(0);
+            let prev: Option<String> = sqlite_row.get(1);
+            let next: Option<String> = sqlite_row.get(2);
+            let sentence = (prev.is_some() || next.is_some()).then(|| {
+                format!(
+                    "{}{word}{}",
+                    prev.unwrap_or_default(),
+                    next.unwrap_or_default()
+                )
+                .trim()
+                .to_owned()
+            });
+            Lookup {
+                word,
+                sentence,
+                book: sqlite_row.get(3),
+                timestamp: sqlite_row.get::<Option<i64>, _>(4).map(|s| s as f64),
+            }
+        })
+        .collect();
+    Ok(v)
+}
+
+/// Seconds since the unix epoch to julianday, as `julianday('now')` of SQLite
+pub fn julianday(seconds: f64) -> f64 {
+    seconds / 86400.0 + 2440587.5
+}
+
+/// A sentence the word was met in
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct Context {
+    pub sentence: String,
+    #[serde(default)]
+    pub book: Option<String>,
+    pub source: String,
+    /// julianday
+    #[serde(default)]
+    pub timestamp: Option<f64>,
+}
+
+impl SQLiteHistory {
+    /// Returns false if the sentence is already kept
+    pub async fn add_context(&self, word: &str, lookup: &Lookup, source: Source) -> Result<bool> {
+        let Some(sentence) = lookup.sentence.as_deref().filter(|s| !s.is_empty()) else {
+            return Ok(false);
+        };
+        self.insert_context(
+            word,
+            &Context {
+                sentence: sentence.to_owned(),
+                book: lookup.book.clone(),
+                source: source.as_str().to_owned(),
+                timestamp: lookup.timestamp.map(julianday),
+            },
+        )
+        .await
+    }
+
+    /// Returns false if the sentence is already kept
+    pub async fn insert_context(&self, word: &str, context: &Context) -> Result<bool> {
+        let res = sqlx::query("INSERT OR IGNORE INTO context (word, sentence, book, source, timestamp) VALUES ($1, $2, $3, $4, $5);")
+            .bind(word)
+            .bind(&context.sentence)
+            .bind(&context.book)
+            .bind(&context.source)
+            .bind(context.timestamp)
+            .execute(&self.conn)
+            .await?;
+        Ok(res.rows_affected() > 0)
+    }
+
+    /// The latest first
+    pub async fn contexts(&self, word: &str) -> Result<Vec<Context>> {
+        let v = sqlx::query(
+            "SELECT sentence, book, source, timestamp FROM context WHERE word = $1 COLLATE NOCASE ORDER BY timestamp DESC;",
+        )
+        .bind(word)
+        .fetch_all(&self.conn)
+        .await?
+        .into_iter()
+        .map(|sqlite_row| Context {
+            sentence: sqlite_row.get(0),
+            book: sqlite_row.get(1),
+            source: sqlite_row.get(2),
+            timestamp: sqlite_row.get(3),
+        })
+        .collect();
+        Ok(v)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A database made of `sql`, opened read only
+    async fn fixture(dir: &Path, name: &str, sql: &str) -> SqlitePool {
+        let path = dir.join(name);
+        let options = SqliteConnectOptions::new()
+            .filename(&path)
+            .create_if_missing(true);
+        let pool = SqlitePoolOptions::new()
+            .connect_with(options)
+            .await
+            .unwrap();
+        sqlx::raw_sql(sql).execute(&pool).await.unwrap();
+        pool.close().await;
+        open_read_only(&path).await.unwrap()
+    }
+
+    async fn kindle_db(dir: &Path) -> SqlitePool {
+        fixture(
+            dir,
+            "vocab.db",
+            "
+CREATE TABLE WORDS (id TEXT PRIMARY KEY, word TEXT, stem TEXT);
+CREATE TABLE LOOKUPS (id TEXT PRIMARY KEY, word_key TEXT, book_key TEXT, usage TEXT, timestamp INTEGER);
+CREATE TABLE BOOK_INFO (id TEXT PRIMARY KEY, title TEXT);
+INSERT INTO WORDS VALUES ('en:running', 'running', 'run'), ('en:apples', 'apples', '');
+INSERT INTO BOOK_INFO VALUES ('b1', 'Walden');
+INSERT INTO LOOKUPS VALUES
+    ('1', 'en:apples', NULL, NULL, 2000),
+    ('2', 'en:running', 'b1', 'He was running late.', 1000);
+",
+        )
+        .await
+    }
+
+    #[tokio::test]
+    async fn kindle_lookups() {
+        let dir = tempfile::tempdir().unwrap();
+        let pool = kindle_db(dir.path()).await;
+        assert_eq!(detect(&pool).await.unwrap(), Source::Kindle);
+
+        let v = kindle(&pool, false).await.unwrap();
+        assert_eq!(v.len(), 2);
+        assert_eq!(v[0].word, "running");
+        assert_eq!(v[0].sentence.as_deref(), Some("He was running late."));
+        assert_eq!(v[0].book.as_deref(), Some("Walden"));
+        assert_eq!(v[0].timestamp, Some(1.0));
+        assert_eq!(v[1].book, None);
+
+        let words: Vec<String> = kindle(&pool, true)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|x| x.word)
+            .collect();
+        // an empty stem falls back to the word
+        assert_eq!(words, ["run", "apples"]);
+    }
+
+    #[tokio::test]
+    async fn koreader_lookups() {
+        let dir = tempfile::tempdir().unwrap();
+        let sql = "
+CREATE TABLE vocabulary (word TEXT PRIMARY KEY, title_id INTEGER, create_time INTEGER, prev_context TEXT, next_context TEXT);
+INSERT INTO vocabulary VALUES ('quay', 1, 20, 'down to the ', ' at dawn '), ('brine', NULL, 10, NULL, NULL);
+";
+        let pool = fixture(dir.path(), "old.sqlite3", sql).await;
+        assert_eq!(detect(&pool).await.unwrap(), Source::Koreader);
+        let v = koreader(&pool).await.unwrap();
+        assert_eq!(v[0].word, "brine");
+        assert_eq!(v[0].sentence, None);
+        assert_eq!(v[1].sentence.as_deref(), Some("down to the quay at dawn"));
+        assert_eq!(v[1].book, None);
+
+        let sql = format!("{sql}CREATE TABLE title (id INTEGER PRIMARY KEY, name TEXT); INSERT INTO title VALUES (1, 'Moby Dick');");
+        let pool = fixture(dir.path(), "vocabulary_builder.sqlite3", &sql).await;
+        let v = koreader(&pool).await.unwrap();
+        assert_eq!(v[1].book.as_deref(), Some("Moby Dick"));
+        assert_eq!(v[1].timestamp, Some(20.0));
+
+        let pool = fixture(dir.path(), "other.db", "CREATE TABLE x (y);").await;
+        assert!(detect(&pool).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn contexts_are_kept_once() {
+        let dir = tempfile::tempdir().unwrap();
+        let history = SQLiteHistory::temp(dir.path()).await;
+        let pool = kindle_db(dir.path()).await;
+        for lookup in kindle(&pool, true).await.unwrap() {
+            let added = history
+                .add_context(&lookup.word, &lookup, Source::Kindle)
+                .await
+                .unwrap();
+            // without a sentence
+            assert_eq!(added, lookup.word == "run");
+        }
+        let lookup = &kindle(&pool, true).await.unwrap()[0];
+        assert!(!history
+            .add_context("run", lookup, Source::Kindle)
+            .await
+            .unwrap());
+
+        let contexts = history.contexts("RUN").await.unwrap();
+        assert_eq!(
+            contexts,
+            [Context {
+                sentence: "He was running late.".to_owned(),
+                book: Some("Walden".to_owned()),
+                source: "kindle".to_owned(),
+                timestamp: Some(julianday(1.0)),
+            }]
+        );
+        assert_eq!(julianday(0.0), 2440587.5);
+    }
+}
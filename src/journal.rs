@@ -0,0 +1,314 @@
+//! Sync through a shared folder (Syncthing, NFS, USB stick)
+//!
+//! Each device appends its card changes to `<sync_dir>/<device>.jsonl`
+//! and replays the journals of the other devices on startup
+//! A change is applied if it's newer than the local card
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use rs_fsrs::Card;
+use sqlx::Row;
+use std::fs;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use crate::config::Config;
+use crate::fsrs::sqlite_history::SQLiteHistory;
+use crate::fsrs::{get_card, get_word_ignore_case};
+use crate::revlog::rating_from_i64;
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(tag = "op", rename_all = "lowercase")]
+pub enum Op {
+    /// added or replaced
+    Card {
+        card: Card,
+    },
+    /// `rating`: 1 ~ 4
+    Review {
+        rating: i64,
+        card: Card,
+    },
+    Delete,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct Change {
+    /// milliseconds since the unix epoch
+    pub timestamp: i64,
+    pub word: String,
+    #[serde(flatten)]
+    pub op: Op,
+}
+
+#[derive(Debug, Clone)]
+pub struct Journal {
+    pub dir: PathBuf,
+    pub device: String,
+}
+
+fn hostname() -> String {
+    std::env::var("HOSTNAME")
+        .ok()
+        .or_else(|| fs::read_to_string("/etc/hostname").ok())
+        .map(|s| s.trim().to_owned())
+        .filter(|s| !s.is_empty())
+        .unwrap_or_else(|| "default".to_owned())
+}
+
+impl Journal {
+    pub fn new(dir: PathBuf, device: String) -> Self {
+        Self { dir, device }
+    }
+
+    /// `None` if `sync_dir` is not configured
+    /// A broken config only disables sync, every binary opens history.db through this
+    pub fn from_config() -> Option<Self> {
+        let config = match Config::load() {
+            Ok(config) => config,
+            Err(e) => {
+                eprintln!("{e:#}, sync disabled");
+                return None;
+            }
+        };
+        config
+            .sync_dir
+            .map(|dir| Self::new(dir, config.device.unwrap_or_else(hostname)))
+    }
+
+    fn path(&self) -> PathBuf {
+        self.dir.join(format!("{}.jsonl", self.device))
+    }
+
+    pub fn append(&self, change: &Change) -> Result<()> {
+        fs::create_dir_all(&self.dir)?;
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.path())?;
+        let mut line = serde_json::to_string(change)?;
+        line.push('\n');
+        file.write_all(line.as_bytes())?;
+        Ok(())
+    }
+
+    /// (device, path) of the other journals
+    /// Conflict copies and temporary files of the sync tool are not journals
+    fn others(&self) -> Result<Vec<(String, PathBuf)>> {
+        if !self.dir.exists() {
+            return Ok(Vec::new());
+        }
+        let mut v = Vec::new();
+        for entry in fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+            if path.extension().and_then(|x| x.to_str()) != Some("jsonl") {
+                continue;
+            }
+            let Some(device) = path.file_stem().and_then(|x| x.to_str()) else {
+                continue;
+            };
+            if device.contains(".sync-conflict-") || device.contains('~') {
+                continue;
+            }
+            if device != self.device {
+                v.push((device.to_owned(), path));
+            }
+        }
+        v.sort();
+        Ok(v)
+    }
+}
+
+/// Complete lines only: the last one may still be syncing
+fn read_lines(path: &Path) -> Result<Vec<String>> {
+    let s = fs::read_to_string(path)?;
+    let mut lines: Vec<String> = s.split('\n').map(str::to_owned).collect();
+    // the part after the last '\n'
+    lines.pop();
+    Ok(lines)
+}
+
+impl SQLiteHistory {
+    /// `at`: time of the change, the review time for [Op::Review]
+    pub(crate) fn journal_append(&self, word: &str, op: Op, at: DateTime<Utc>) -> Result<()> {
+        let Some(journal) = &self.journal else {
+            return Ok(());
+        };
+        journal.append(&Change {
+            timestamp: at.timestamp_millis(),
+            word: word.to_owned(),
+            op,
+        })
+    }
+
+    async fn apply(&mut self, change: Change) -> Result<bool> {
+        let mine = match get_word_ignore_case(&self.conn, &change.word).await {
+            Ok(word) => Some((get_card(&self.conn, &word).await?, word)),
+            Err(_) => None,
+        };
+        let word = mine
+            .as_ref()
+            .map(|(_, word)| word.clone())
+            .unwrap_or(change.word);
+        // a new card never replaces a reviewed one
+        let newer = |card: &Card| match &mine {
+            None => true,
+            Some((mine, _)) => {
+                !(card.reps == 0 && mine.reps > 0) && card.last_review > mine.last_review
+            }
+        };
+
+        match change.op {
+            Op::Card { card } => {
+                if !newer(&card) {
+                    return Ok(false);
+                }
+                self.write_card(&word, card).await?;
+            }
+            Op::Review { rating, card } => {
+                if let Some(rating) = rating_from_i64(rating) {
+                    self.log_review(&word, rating, change.timestamp).await?;
+                }
+                if !newer(&card) {
+                    return Ok(false);
+                }
+                self.write_card(&word, card).await?;
+            }
+            Op::Delete => {
+                let Some((mine, _)) = mine else {
+                    return Ok(false);
+                };
+                let deleted_at = DateTime::from_timestamp_millis(change.timestamp);
+                if deleted_at.is_none_or(|t| t < mine.last_review) {
+                    return Ok(false);
+                }
+                self.delete_rows(&word).await?;
+            }
+        }
+        Ok(true)
+    }
+
+    /// Apply the unseen changes of the other devices
+    /// Returns the number of applied changes
+    pub async fn replay_journals(&mut self) -> Result<usize> {
+        let Some(journal) = self.journal.clone() else {
+            return Ok(0);
+        };
+
+        let mut applied = 0;
+        for (device, path) in journal.others()? {
+            let seen: i64 = sqlx::query("SELECT line FROM journal WHERE device = $1;")
+                .bind(&device)
+                .fetch_optional(&self.conn)
+                .await?
+                .map(|sqlite_row| sqlite_row.get(0))
+                .unwrap_or(0);
+
+            let lines = read_lines(&path)?;
+            if lines.len() as i64 <= seen {
+                continue;
+            }
+            for line in &lines[seen as usize..] {
+                if line.trim().is_empty() {
+                    continue;
+                }
+                // skip what a newer version wrote
+                let Ok(change) = serde_json::from_str::<Change>(line) else {
+                    continue;
+                };
+                if self.apply(change).await? {
+                    applied += 1;
+                }
+            }
+
+            sqlx::query("INSERT OR REPLACE INTO journal (device, line) VALUES ($1, $2);")
+                .bind(&device)
+                .bind(lines.len() as i64)
+                .execute(&self.conn)
+                .await?;
+        }
+        Ok(applied)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rs_fsrs::Rating;
+    use std::time::Duration;
+
+    async fn open(dir: &Path, device: &str, sync_dir: &Path) -> SQLiteHistory {
+        let journal = Journal::new(sync_dir.to_owned(), device.to_owned());
+        fs::create_dir_all(dir.join(device)).unwrap();
+        SQLiteHistory::with_journal(dir.join(device).join("history.db"), Some(journal))
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn two_devices() {
+        let dir = tempfile::tempdir().unwrap();
+        let sync_dir = dir.path().join("sync");
+        let mut a = open(dir.path(), "a", &sync_dir).await;
+        a.insert_or_replace("quay", Card::new()).await.unwrap();
+
+        let mut b = open(dir.path(), "b", &sync_dir).await;
+        assert_eq!(
+            b.stored_word("QUAY").await.unwrap().as_deref(),
+            Some("quay")
+        );
+
+        // both review before seeing the other: the later review wins on both
+        b.update("quay", Rating::Good).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        a.update("quay", Rating::Again).await.unwrap();
+        let newer = get_card(&a.conn, "quay").await.unwrap();
+
+        let a = open(dir.path(), "a", &sync_dir).await;
+        let mut b = open(dir.path(), "b", &sync_dir).await;
+        for history in [&a, &b] {
+            let card = get_card(&history.conn, "quay").await.unwrap();
+            assert_eq!(card.last_review, newer.last_review);
+            assert_eq!(card.state, newer.state);
+        }
+        // both reviews are logged at the time they were made
+        let reviews = a.reviews("quay").await.unwrap();
+        assert_eq!(reviews.len(), 2);
+        assert_eq!(b.reviews("quay").await.unwrap(), reviews);
+
+        // nothing new to replay
+        assert_eq!(b.replay_journals().await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn conflict_copies_are_skipped() {
+        let dir = tempfile::tempdir().unwrap();
+        let journal = Journal::new(dir.path().to_owned(), "a".to_owned());
+        for name in [
+            "a.jsonl",
+            "b.jsonl",
+            "b.sync-conflict-20240101-120000-ABCDEFG.jsonl",
+            "~b.jsonl",
+            "b.jsonl~",
+            "b.tmp",
+        ] {
+            fs::write(dir.path().join(name), "").unwrap();
+        }
+        let devices: Vec<String> = journal
+            .others()
+            .unwrap()
+            .into_iter()
+            .map(|(device, _)| device)
+            .collect();
+        assert_eq!(devices, ["b"]);
+    }
+
+    #[test]
+    fn incomplete_last_line() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("b.jsonl");
+        fs::write(&path, "{}\n{\"tim").unwrap();
+        assert_eq!(read_lines(&path).unwrap(), ["{}"]);
+    }
+}
@@ -1,7 +1,26 @@
+pub mod anki;
+pub mod answer;
+pub mod audio;
+pub mod config;
+pub mod confusable;
 pub mod csv;
+pub mod dictionary;
+pub mod dump;
 pub mod favorite;
+pub mod filter;
 pub mod fsrs;
+pub mod history;
+pub mod import;
+pub mod journal;
+pub mod mdict;
+pub mod merge;
 pub mod merriam;
+pub mod phonetic;
+pub mod revlog;
+pub mod server;
+pub mod spelling;
+pub mod stardict;
+pub mod tag;
 pub mod utils;
 pub mod word2vec;
 
@@ -0,0 +1,523 @@
+//! MDict (.mdx, .mdd) reader
+//!
+//! Format: <https://github.com/zhansliu/writemdict/blob/master/fileformat.md>
+//!
+//! Only zlib compressed blocks, UTF-8 and UTF-16 dictionaries are supported
+
+use anyhow::anyhow;
+use anyhow::Context;
+use anyhow::Result;
+use miniz_oxide::inflate::decompress_to_vec_zlib;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::BufReader;
+use std::io::Read;
+use std::io::Seek;
+use std::io::SeekFrom;
+use std::path::Path;
+
+mod ripemd128;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Encoding {
+    Utf8,
+    Utf16,
+}
+
+impl Encoding {
+    /// bytes of a code unit, also the length of the terminator
+    fn unit(self) -> usize {
+        match self {
+            Encoding::Utf8 => 1,
+            Encoding::Utf16 => 2,
+        }
+    }
+
+    fn decode(self, bytes: &[u8]) -> String {
+        match self {
+            Encoding::Utf8 => String::from_utf8_lossy(bytes).into_owned(),
+            Encoding::Utf16 => decode_utf16le(bytes),
+        }
+    }
+}
+
+fn decode_utf16le(bytes: &[u8]) -> String {
+    let units: Vec<u16> = bytes
+        .chunks_exact(2)
+        .map(|b| u16::from_le_bytes([b[0], b[1]]))
+        .collect();
+    String::from_utf16_lossy(&units)
+}
+
+/// `name="value"` pairs of the header
+fn parse_attributes(header: &str) -> HashMap<String, String> {
+    let mut attributes = HashMap::new();
+    let mut rest = header;
+    while let Some(eq) = rest.find("=\"") {
+        let name = rest[..eq]
+            .rsplit(|c: char| c.is_whitespace() || c == '<')
+            .next()
+            .unwrap_or_default();
+        let value_start = eq + 2;
+        let Some(len) = rest[value_start..].find('"') else {
+            break;
+        };
+        attributes.insert(
+            name.to_owned(),
+            rest[value_start..value_start + len].to_owned(),
+        );
+        rest = &rest[value_start + len + 1..];
+    }
+    attributes
+}
+
+/// Encrypted key block info
+fn decrypt(block: &[u8]) -> Result<Vec<u8>> {
+    if block.len() < 8 {
+        return Err(anyhow!("truncated key block info"));
+    }
+    let mut key_source = block[4..8].to_vec();
+    key_source.extend_from_slice(&0x3695u32.to_le_bytes());
+    let key = ripemd128::ripemd128(&key_source);
+
+    let mut res = block[..8].to_vec();
+    let mut previous = 0x36u8;
+    for (i, &b) in block[8..].iter().enumerate() {
+        let t = b.rotate_left(4) ^ previous ^ (i as u8) ^ key[i % key.len()];
+        previous = b;
+        res.push(t);
+    }
+    Ok(res)
+}
+
+/// compression type (4 bytes), adler32 checksum (4 bytes), data
+fn decompress(block: &[u8]) -> Result<Vec<u8>> {
+    if block.len() < 8 {
+        return Err(anyhow!("truncated block"));
+    }
+    match block[0] {
+        0 => Ok(block[8..].to_vec()),
+        1 => Err(anyhow!("lzo compressed mdx is not supported")),
+        2 => decompress_to_vec_zlib(&block[8..]).map_err(|e| anyhow!("zlib: {e:?}")),
+        x => Err(anyhow!("unknown compression type {x}")),
+    }
+}
+
+/// Big endian numbers: u32 before version 2.0, u64 since 2.0
+struct Cursor<'a> {
+    data: &'a [u8],
+    pos: usize,
+    number_width: usize,
+}
+
+impl Cursor<'_> {
+    fn take(&mut self, n: usize) -> Result<&[u8]> {
+        let end = self
+            .pos
+            .checked_add(n)
+            .filter(|end| *end <= self.data.len())
+            .ok_or_else(|| anyhow!("unexpected end of mdx"))?;
+        let res = &self.data[self.pos..end];
+        self.pos = end;
+        Ok(res)
+    }
+
+    fn number(&mut self) -> Result<u64> {
+        let width = self.number_width;
+        let bytes = self.take(width)?;
+        Ok(bytes.iter().fold(0u64, |acc, b| (acc << 8) | *b as u64))
+    }
+
+    fn u16(&mut self) -> Result<u16> {
+        let b = self.take(2)?;
+        Ok(u16::from_be_bytes([b[0], b[1]]))
+    }
+
+    fn u8(&mut self) -> Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn is_empty(&self) -> bool {
+        self.pos >= self.data.len()
+    }
+}
+
+fn read_bytes(r: &mut impl Read, n: usize) -> Result<Vec<u8>> {
+    let mut buf = vec![0u8; n];
+    r.read_exact(&mut buf)?;
+    Ok(buf)
+}
+
+/// [read_bytes] with a size from the file, checked against the rest of the file
+fn read_section(r: &mut (impl Read + Seek), n: u64, file_len: u64) -> Result<Vec<u8>> {
+    let pos = r.stream_position()?;
+    if n > file_len.saturating_sub(pos) {
+        return Err(anyhow!("section of {n} bytes at {pos} exceeds the file"));
+    }
+    read_bytes(r, n as usize)
+}
+
+struct RecordBlock {
+    comp_size: u64,
+    decomp_size: u64,
+    /// start of the block in the file
+    file_offset: u64,
+    /// start of the block in the concatenated records
+    decomp_offset: u64,
+}
+
+pub struct Mdx {
+    file: BufReader<File>,
+    encoding: Encoding,
+    pub title: String,
+    /// (offset of the record, headword), in file order
+    keys: Vec<(u64, String)>,
+    /// lowercase headword -> index of `keys`
+    index: HashMap<String, usize>,
+    record_blocks: Vec<RecordBlock>,
+    /// last decompressed record block
+    cache: Option<(usize, Vec<u8>)>,
+}
+
+impl Mdx {
+    pub fn open(path: &Path) -> Result<Self> {
+        let file = File::open(path).with_context(|| format!("fail to open {:?}", path))?;
+        let file_len = file.metadata()?.len();
+        let mut file = BufReader::new(file);
+
+        let mut header_len = [0u8; 4];
+        file.read_exact(&mut header_len)?;
+        let header = read_section(&mut file, u32::from_be_bytes(header_len) as u64, file_len)?;
+        let _adler32 = read_bytes(&mut file, 4)?;
+        let attributes = parse_attributes(decode_utf16le(&header).trim_end_matches('\0'));
+
+        let version: f32 = attributes
+            .get("GeneratedByEngineVersion")
+            .and_then(|x| x.trim().parse().ok())
+            .unwrap_or(2.0);
+        let encrypted: u8 = match attributes.get("Encrypted").map(|x| x.trim()) {
+            None | Some("") | Some("No") => 0,
+            Some("Yes") => 1,
+            Some(x) => x.parse().unwrap_or(0),
+        };
+        if encrypted & 1 != 0 {
+            return Err(anyhow!("{:?} requires a registration code", path));
+        }
+        let encoding = match attributes
+            .get("Encoding")
+            .map(|x| x.to_uppercase())
+            .as_deref()
+        {
+            // keys of .mdd are UTF-16 without `Encoding`
+            None | Some("") if path.extension().is_some_and(|x| x == "mdd") => Encoding::Utf16,
+            None | Some("") | Some("UTF-8") | Some("UTF8") => Encoding::Utf8,
+            Some("UTF-16") | Some("UTF16") => Encoding::Utf16,
+            Some(x) => return Err(anyhow!("encoding {x} of {:?} is not supported", path)),
+        };
+        let v2 = version >= 2.0;
+        let number_width = if v2 { 8 } else { 4 };
+
+        // keyword section
+        let key_header = read_bytes(&mut file, number_width * if v2 { 5 } else { 4 })?;
+        if v2 {
+            let _adler32 = read_bytes(&mut file, 4)?;
+        }
+        let mut cursor = Cursor {
+            data: &key_header,
+            pos: 0,
+            number_width,
+        };
+        let num_key_blocks = cursor.number()?;
+        let _num_entries = cursor.number()?;
+        if v2 {
+            let _key_block_info_decomp_size = cursor.number()?;
+        }
+        let key_block_info_size = cursor.number()?;
+        let key_blocks_size = cursor.number()?;
+
+        let key_block_info = read_section(&mut file, key_block_info_size, file_len)?;
+        let key_block_info = if v2 {
+            let key_block_info = if encrypted & 2 != 0 {
+                decrypt(&key_block_info)?
+            } else {
+                key_block_info
+            };
+            decompress(&key_block_info)?
+        } else {
+            key_block_info
+        };
+
+        // (compressed size, decompressed size) of each key block
+        let mut key_block_sizes = Vec::new();
+        let mut cursor = Cursor {
+            data: &key_block_info,
+            pos: 0,
+            number_width,
+        };
+        for _ in 0..num_key_blocks {
+            let _num_entries = cursor.number()?;
+            for _first_and_last_key in 0..2 {
+                let len = if v2 {
+                    cursor.u16()? as usize + 1
+                } else {
+                    cursor.u8()? as usize
+                };
+                cursor.take(len * encoding.unit())?;
+            }
+            let comp_size = cursor.number()? as usize;
+            let _decomp_size = cursor.number()?;
+            key_block_sizes.push(comp_size);
+        }
+
+        let key_blocks = read_section(&mut file, key_blocks_size, file_len)?;
+        let mut keys = Vec::new();
+        let mut start = 0usize;
+        for comp_size in key_block_sizes {
+            let block = start
+                .checked_add(comp_size)
+                .and_then(|end| key_blocks.get(start..end))
+                .ok_or_else(|| anyhow!("truncated key block"))?;
+            start += comp_size;
+            let block = decompress(block)?;
+            let mut cursor = Cursor {
+                data: &block,
+                pos: 0,
+                number_width,
+            };
+            while !cursor.is_empty() {
+                let offset = cursor.number()?;
+                let unit = encoding.unit();
+                let rest = &cursor.data[cursor.pos..];
+                let len = rest
+                    .chunks(unit)
+                    .position(|c| c.iter().all(|b| *b == 0))
+                    .map(|i| i * unit)
+                    .unwrap_or(rest.len());
+                let key = encoding.decode(cursor.take(len)?);
+                cursor.take(unit.min(cursor.data.len() - cursor.pos))?;
+                keys.push((offset, key));
+            }
+        }
+
+        // record section
+        let record_header = read_bytes(&mut file, number_width * 4)?;
+        let mut cursor = Cursor {
+            data: &record_header,
+            pos: 0,
+            number_width,
+        };
+        let num_record_blocks = cursor.number()?;
+        let _num_entries = cursor.number()?;
+        let record_block_info_size = cursor.number()?;
+        let _record_blocks_size = cursor.number()?;
+
+        let record_block_info = read_section(&mut file, record_block_info_size, file_len)?;
+        let mut cursor = Cursor {
+            data: &record_block_info,
+            pos: 0,
+            number_width,
+        };
+        let mut file_offset = file.stream_position()?;
+        let mut decomp_offset = 0;
+        let mut record_blocks = Vec::new();
+        for _ in 0..num_record_blocks {
+            let comp_size = cursor.number()?;
+            let decomp_size = cursor.number()?;
+            record_blocks.push(RecordBlock {
+                comp_size,
+                decomp_size,
+                file_offset,
+                decomp_offset,
+            });
+            file_offset = file_offset
+                .checked_add(comp_size)
+                .filter(|end| *end <= file_len)
+                .ok_or_else(|| anyhow!("record block exceeds the file"))?;
+            decomp_offset = decomp_offset
+                .checked_add(decomp_size)
+                .ok_or_else(|| anyhow!("corrupted record block info"))?;
+        }
+
+        let mut index = HashMap::new();
+        for (i, (_, key)) in keys.iter().enumerate() {
+            index.entry(key.to_lowercase()).or_insert(i);
+        }
+
+        Ok(Self {
+            file,
+            encoding,
+            title: attributes.get("Title").cloned().unwrap_or_default(),
+            keys,
+            index,
+            record_blocks,
+            cache: None,
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = &str> {
+        self.keys.iter().map(|(_, key)| key.as_str())
+    }
+
+    fn record_block(&mut self, i: usize) -> Result<&[u8]> {
+        if self.cache.as_ref().map(|(j, _)| *j) != Some(i) {
+            let block = &self.record_blocks[i];
+            self.file.seek(SeekFrom::Start(block.file_offset))?;
+            let data = read_bytes(&mut self.file, block.comp_size as usize)?;
+            self.cache = Some((i, decompress(&data)?));
+        }
+        Ok(&self.cache.as_ref().unwrap().1)
+    }
+
+    /// The raw record of the i-th key
+    fn record_bytes(&mut self, i: usize) -> Result<Vec<u8>> {
+        let start = self.keys[i].0;
+        let end = self.keys.get(i + 1).map(|(offset, _)| *offset);
+
+        let block_index = self
+            .record_blocks
+            .partition_point(|b| b.decomp_offset + b.decomp_size <= start);
+        let block = self
+            .record_blocks
+            .get(block_index)
+            .ok_or_else(|| anyhow!("record of {} is out of range", self.keys[i].1))?;
+        let block_start = block.decomp_offset;
+        let block_end = block.decomp_offset + block.decomp_size;
+        let end = end.unwrap_or(block_end).min(block_end);
+
+        let data = self.record_block(block_index)?;
+        let bytes = start
+            .checked_sub(block_start)
+            .zip(end.checked_sub(block_start))
+            .and_then(|(start, end)| data.get(start as usize..end as usize))
+            .ok_or_else(|| anyhow!("corrupted record block"))?;
+        Ok(bytes.to_vec())
+    }
+
+    /// The record of the i-th key
+    fn record(&mut self, i: usize) -> Result<String> {
+        let bytes = self.record_bytes(i)?;
+        Ok(self
+            .encoding
+            .decode(&bytes)
+            .trim_end_matches(['\0', '\r', '\n'])
+            .to_owned())
+    }
+
+    /// File of a .mdd, e.g. `\us\apple.mp3` or `us/apple.mp3`, case insensitive
+    pub fn resource(&mut self, path: &str) -> Result<Option<Vec<u8>>> {
+        let key = format!(
+            "\\{}",
+            path.trim_start_matches(['/', '\\']).replace('/', "\\")
+        );
+        match self.index.get(&key.to_lowercase()) {
+            Some(&i) => Ok(Some(self.record_bytes(i)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Case insensitive, follows `@@@LINK=`
+    pub fn lookup(&mut self, word: &str) -> Result<Option<String>> {
+        let mut word = word.to_owned();
+        // avoid endless redirection
+        for _ in 0..5 {
+            let Some(&i) = self.index.get(&word.to_lowercase()) else {
+                return Ok(None);
+            };
+            let record = self.record(i)?;
+            match record.strip_prefix("@@@LINK=") {
+                Some(target) => word = target.trim().to_owned(),
+                None => return Ok(Some(record)),
+            }
+        }
+        Ok(None)
+    }
+
+    /// Visit every (headword, record) in file order, redirections are skipped
+    pub fn for_each(&mut self, mut f: impl FnMut(&str, &str) -> Result<()>) -> Result<()> {
+        for i in 0..self.keys.len() {
+            let record = self.record(i)?;
+            if record.starts_with("@@@LINK=") {
+                continue;
+            }
+            f(&self.keys[i].1, &record)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tiny() -> Mdx {
+        Mdx::open(&Path::new(env!("CARGO_MANIFEST_DIR")).join("testdata/tiny.mdx")).unwrap()
+    }
+
+    #[test]
+    fn parse_header() {
+        let attributes = parse_attributes(r#"<Dictionary Encrypted="2" Title="a b" Encoding=""/>"#);
+        assert_eq!(attributes["Encrypted"], "2");
+        assert_eq!(attributes["Title"], "a b");
+        assert_eq!(attributes["Encoding"], "");
+    }
+
+    #[test]
+    fn encrypted_key_block_info() {
+        let mdx = tiny();
+        assert_eq!(mdx.title, "tiny");
+        assert_eq!(mdx.keys().collect::<Vec<_>>(), ["Apple", "apples", "pear"]);
+    }
+
+    #[test]
+    fn lookup() {
+        let mut mdx = tiny();
+        let apple = r#"<div>apple <a href="sound://us/apple.mp3">play</a></div>"#;
+        assert_eq!(mdx.lookup("apple").unwrap().as_deref(), Some(apple));
+        assert_eq!(mdx.lookup("PEAR").unwrap().as_deref(), Some("<p>pear</p>"));
+        assert_eq!(mdx.lookup("apples").unwrap().as_deref(), Some(apple));
+        assert_eq!(mdx.lookup("banana").unwrap(), None);
+    }
+
+    #[test]
+    fn truncated() {
+        let bytes =
+            std::fs::read(Path::new(env!("CARGO_MANIFEST_DIR")).join("testdata/tiny.mdx")).unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("truncated.mdx");
+        for len in 0..bytes.len() {
+            std::fs::write(&path, &bytes[..len]).unwrap();
+            assert!(Mdx::open(&path).is_err(), "truncated at {len}");
+        }
+    }
+
+    #[test]
+    fn corrupt_sizes() {
+        let mut bytes =
+            std::fs::read(Path::new(env!("CARGO_MANIFEST_DIR")).join("testdata/tiny.mdx")).unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("corrupt.mdx");
+        bytes[..4].copy_from_slice(&u32::MAX.to_be_bytes());
+        std::fs::write(&path, &bytes).unwrap();
+        assert!(Mdx::open(&path).is_err());
+    }
+
+    #[test]
+    fn for_each_skips_links() {
+        let mut mdx = tiny();
+        let mut words = Vec::new();
+        mdx.for_each(|word, _| {
+            words.push(word.to_owned());
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(words, ["Apple", "pear"]);
+    }
+}
@@ -0,0 +1,126 @@
+//! RIPEMD-128, only used to decrypt the key block info of MDX
+//! <https://homes.esat.kuleuven.be/~bosselae/ripemd160.html>
+
+const R: [usize; 64] = [
+    0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, //
+    7, 4, 13, 1, 10, 6, 15, 3, 12, 0, 9, 5, 2, 14, 11, 8, //
+    3, 10, 14, 4, 9, 15, 8, 1, 2, 7, 0, 6, 13, 11, 5, 12, //
+    1, 9, 11, 10, 0, 8, 12, 4, 13, 3, 7, 15, 14, 5, 6, 2,
+];
+
+const RP: [usize; 64] = [
+    5, 14, 7, 0, 9, 2, 11, 4, 13, 6, 15, 8, 1, 10, 3, 12, //
+    6, 11, 3, 7, 0, 13, 5, 10, 14, 15, 8, 12, 4, 9, 1, 2, //
+    15, 5, 1, 3, 7, 14, 6, 9, 11, 8, 12, 2, 10, 0, 4, 13, //
+    8, 6, 4, 1, 3, 11, 15, 0, 5, 12, 2, 13, 9, 7, 10, 14,
+];
+
+const S: [u32; 64] = [
+    11, 14, 15, 12, 5, 8, 7, 9, 11, 13, 14, 15, 6, 7, 9, 8, //
+    7, 6, 8, 13, 11, 9, 7, 15, 7, 12, 15, 9, 11, 7, 13, 12, //
+    11, 13, 6, 7, 14, 9, 13, 15, 14, 8, 13, 6, 5, 12, 7, 5, //
+    11, 12, 14, 15, 14, 15, 9, 8, 9, 14, 5, 6, 8, 6, 5, 12,
+];
+
+const SP: [u32; 64] = [
+    8, 9, 9, 11, 13, 15, 15, 5, 7, 7, 8, 11, 14, 14, 12, 6, //
+    9, 13, 15, 7, 12, 8, 9, 11, 7, 7, 12, 7, 6, 15, 13, 11, //
+    9, 7, 15, 11, 8, 6, 6, 14, 12, 13, 5, 14, 13, 13, 7, 5, //
+    15, 5, 8, 11, 14, 14, 6, 14, 6, 9, 12, 9, 12, 5, 15, 8,
+];
+
+fn f(j: usize, x: u32, y: u32, z: u32) -> u32 {
+    match j {
+        0..16 => x ^ y ^ z,
+        16..32 => (x & y) | (!x & z),
+        32..48 => (x | !y) ^ z,
+        _ => (x & z) | (y & !z),
+    }
+}
+
+fn k(j: usize) -> u32 {
+    match j {
+        0..16 => 0x00000000,
+        16..32 => 0x5a827999,
+        32..48 => 0x6ed9eba1,
+        _ => 0x8f1bbcdc,
+    }
+}
+
+fn kp(j: usize) -> u32 {
+    match j {
+        0..16 => 0x50a28be6,
+        16..32 => 0x5c4dd124,
+        32..48 => 0x6d703ef3,
+        _ => 0x00000000,
+    }
+}
+
+pub fn ripemd128(message: &[u8]) -> [u8; 16] {
+    let mut h: [u32; 4] = [0x67452301, 0xefcdab89, 0x98badcfe, 0x10325476];
+
+    let mut padded = message.to_vec();
+    padded.push(0x80);
+    while padded.len() % 64 != 56 {
+        padded.push(0);
+    }
+    padded.extend_from_slice(&((message.len() as u64) * 8).to_le_bytes());
+
+    for chunk in padded.chunks_exact(64) {
+        let x: Vec<u32> = chunk
+            .chunks_exact(4)
+            .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+            .collect();
+
+        let [mut a, mut b, mut c, mut d] = h;
+        let [mut ap, mut bp, mut cp, mut dp] = h;
+        for j in 0..64 {
+            let t = a
+                .wrapping_add(f(j, b, c, d))
+                .wrapping_add(x[R[j]])
+                .wrapping_add(k(j))
+                .rotate_left(S[j]);
+            (a, d, c, b) = (d, c, b, t);
+
+            let t = ap
+                .wrapping_add(f(63 - j, bp, cp, dp))
+                .wrapping_add(x[RP[j]])
+                .wrapping_add(kp(j))
+                .rotate_left(SP[j]);
+            (ap, dp, cp, bp) = (dp, cp, bp, t);
+        }
+
+        let t = h[1].wrapping_add(c).wrapping_add(dp);
+        h[1] = h[2].wrapping_add(d).wrapping_add(ap);
+        h[2] = h[3].wrapping_add(a).wrapping_add(bp);
+        h[3] = h[0].wrapping_add(b).wrapping_add(cp);
+        h[0] = t;
+    }
+
+    let mut digest = [0u8; 16];
+    for (i, x) in h.iter().enumerate() {
+        digest[i * 4..i * 4 + 4].copy_from_slice(&x.to_le_bytes());
+    }
+    digest
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hex(digest: [u8; 16]) -> String {
+        digest.iter().map(|b| format!("{b:02x}")).collect()
+    }
+
+    #[test]
+    fn known_answers() {
+        assert_eq!(hex(ripemd128(b"")), "cdf26213a150dc3ecb610f18f6b38b46");
+        assert_eq!(hex(ripemd128(b"abc")), "c14a12199c66e4ba84636b0f69144c77");
+        assert_eq!(
+            hex(ripemd128(
+                b"12345678901234567890123456789012345678901234567890123456789012345678901234567890"
+            )),
+            "3f45ef194732c2dbb2c4a2c769795fa3"
+        );
+    }
+}
@@ -0,0 +1,213 @@
+//! Merge another history.db, e.g. of another machine
+//!
+//! A word in both is replayed from both review logs if they are complete,
+//! otherwise the card reviewed last wins
+
+use anyhow::Result;
+use rs_fsrs::{Card, Rating};
+use sqlx::sqlite::SqlitePool;
+use sqlx::Row;
+
+use crate::fsrs::sqlite_history::SQLiteHistory;
+use crate::fsrs::{get_all_cards, get_card, get_word_ignore_case};
+use crate::import::Context;
+use crate::merriam::has_table;
+use crate::revlog::{get_reviews, replay};
+
+#[derive(Debug, Default)]
+pub struct MergeSummary {
+    pub added: usize,
+    pub replayed: usize,
+    pub replaced: usize,
+    pub kept: usize,
+}
+
+/// Every review of the card is logged
+fn is_complete(card: &Card, reviews: &[(i64, Rating)]) -> bool {
+    reviews.len() == card.reps as usize
+}
+
+/// Union in chronological order, the same review once
+fn union(a: &[(i64, Rating)], b: &[(i64, Rating)]) -> Vec<(i64, Rating)> {
+    let mut v: Vec<(i64, Rating)> = a.iter().chain(b).copied().collect();
+    v.sort_by_key(|(ms, _)| *ms);
+    v.dedup_by_key(|(ms, _)| *ms);
+    v
+}
+
+async fn get_tags(pool: &SqlitePool, word: &str) -> Result<Vec<String>> {
+    if !has_table(pool, "tag").await? {
+        return Ok(Vec::new());
+    }
+    let v = sqlx::query("SELECT tag FROM tag WHERE word = $1;")
+        .bind(word)
+        .fetch_all(pool)
+        .await?
+        .into_iter()
+        .map(|sqlite_row| sqlite_row.get(0))
+        .collect();
+    Ok(v)
+}
+
+async fn get_contexts(pool: &SqlitePool, word: &str) -> Result<Vec<Context>> {
+    if !has_table(pool, "context").await? {
+        return Ok(Vec::new());
+    }
+    let v = sqlx::query("SELECT sentence, book, source, timestamp FROM context WHERE word = $1;")
+        .bind(word)
+        .fetch_all(pool)
+        .await?
+        .into_iter()
+        .map(|sqlite_row| Context {
+            sentence: sqlite_row.get(0),
+            book: sqlite_row.get(1),
+            source: sqlite_row.get(2),
+            timestamp: sqlite_row.get(3),
+        })
+        .collect();
+    Ok(v)
+}
+
+impl SQLiteHistory {
+    pub async fn merge_from(&mut self, other: &SqlitePool, dry_run: bool) -> Result<MergeSummary> {
+        let mut summary = MergeSummary::default();
+        for (their_word, theirs) in get_all_cards(other).await? {
+            let their_reviews = get_reviews(other, &their_word).await?;
+
+            let (word, card) = match get_word_ignore_case(&self.conn, &their_word).await {
+                Err(_) => {
+                    println!("+ {their_word}");
+                    summary.added += 1;
+                    (their_word.clone(), Some(theirs))
+                }
+                Ok(word) => {
+                    let mine = get_card(&self.conn, &word).await?;
+                    let my_reviews = self.reviews(&word).await?;
+                    if is_complete(&mine, &my_reviews) && is_complete(&theirs, &their_reviews) {
+                        let reviews = union(&my_reviews, &their_reviews);
+                        if reviews.len() == my_reviews.len() {
+                            summary.kept += 1;
+                            (word, None)
+                        } else {
+                            println!("= {word}: {} reviews replayed", reviews.len());
+                            summary.replayed += 1;
+                            (word, Some(replay(&self.fsrs, &reviews)))
+                        }
+                    } else if theirs.last_review > mine.last_review {
+                        println!("~ {word}");
+                        summary.replaced += 1;
+                        (word, Some(theirs))
+                    } else {
+                        summary.kept += 1;
+                        (word, None)
+                    }
+                }
+            };
+            if dry_run {
+                continue;
+            }
+
+            if let Some(card) = card {
+                self.insert_or_replace(&word, card).await?;
+            }
+            for (ms, rating) in their_reviews {
+                self.log_review(&word, rating, ms).await?;
+            }
+            for tag in get_tags(other, &their_word).await? {
+                self.add_tag(&word, &tag).await?;
+            }
+            for context in get_contexts(other, &their_word).await? {
+                self.insert_context(&word, &context).await?;
+            }
+        }
+        Ok(summary)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rs_fsrs::{Parameters, FSRS};
+
+    const DAY: i64 = 86_400_000;
+    const T0: i64 = 1_700_000_000_000;
+
+    /// A card replayed from `reviews`, logged or not
+    async fn review(history: &mut SQLiteHistory, word: &str, reviews: &[(i64, Rating)], log: bool) {
+        let card = replay(&FSRS::new(Parameters::default()), reviews);
+        history.write_card(word, card).await.unwrap();
+        if log {
+            for (ms, rating) in reviews {
+                history.log_review(word, *rating, *ms).await.unwrap();
+            }
+        }
+    }
+
+    #[test]
+    fn union_dedups_by_time() {
+        let a = [(T0, Rating::Good), (T0 + 2 * DAY, Rating::Again)];
+        let b = [(T0 + DAY, Rating::Easy), (T0, Rating::Good)];
+        assert_eq!(
+            union(&a, &b),
+            [
+                (T0, Rating::Good),
+                (T0 + DAY, Rating::Easy),
+                (T0 + 2 * DAY, Rating::Again)
+            ]
+        );
+        let card = replay(&FSRS::new(Parameters::default()), &a);
+        assert!(is_complete(&card, &a));
+        assert!(!is_complete(&card, &a[..1]));
+    }
+
+    #[tokio::test]
+    async fn merge_two_histories() {
+        let mine_dir = tempfile::tempdir().unwrap();
+        let their_dir = tempfile::tempdir().unwrap();
+        let mut mine = SQLiteHistory::temp(mine_dir.path()).await;
+        let mut theirs = SQLiteHistory::temp(their_dir.path()).await;
+
+        // both logs complete: replayed together
+        review(&mut mine, "quay", &[(T0, Rating::Good)], true).await;
+        review(&mut theirs, "Quay", &[(T0 + DAY, Rating::Hard)], true).await;
+        // their log is incomplete: the card reviewed last wins
+        review(&mut mine, "brine", &[(T0 + DAY, Rating::Good)], true).await;
+        review(&mut theirs, "brine", &[(T0, Rating::Good)], false).await;
+        review(&mut mine, "keel", &[(T0, Rating::Good)], false).await;
+        review(&mut theirs, "keel", &[(T0 + DAY, Rating::Again)], false).await;
+        // only theirs
+        review(&mut theirs, "gull", &[(T0, Rating::Easy)], true).await;
+        theirs.add_tag("gull", "birds").await.unwrap();
+
+        let summary = mine.merge_from(&theirs.conn, true).await.unwrap();
+        assert_eq!(
+            (
+                summary.added,
+                summary.replayed,
+                summary.replaced,
+                summary.kept
+            ),
+            (1, 1, 1, 1)
+        );
+        assert!(mine.stored_word("gull").await.unwrap().is_none());
+
+        mine.merge_from(&theirs.conn, false).await.unwrap();
+        let quay = get_card(&mine.conn, "quay").await.unwrap();
+        assert_eq!(quay.reps, 2);
+        assert_eq!(quay.last_review.timestamp_millis(), T0 + DAY);
+        assert_eq!(mine.reviews("quay").await.unwrap().len(), 2);
+
+        let brine = get_card(&mine.conn, "brine").await.unwrap();
+        assert_eq!(brine.last_review.timestamp_millis(), T0 + DAY);
+        let keel = get_card(&mine.conn, "keel").await.unwrap();
+        assert_eq!(keel.last_review.timestamp_millis(), T0 + DAY);
+
+        assert_eq!(get_card(&mine.conn, "gull").await.unwrap().reps, 1);
+        assert_eq!(mine.tags("gull").await.unwrap(), ["birds"]);
+
+        // merging again changes nothing
+        let summary = mine.merge_from(&theirs.conn, false).await.unwrap();
+        assert_eq!((summary.added, summary.replayed), (0, 0));
+        assert_eq!(mine.reviews("quay").await.unwrap().len(), 2);
+    }
+}
@@ -1,24 +1,419 @@
+//! Related words from dictionaries
+//!
+//! Stored in the `related` table, one row per (word, related word, relation, source dictionary)
+//! Older databases with only the `merriam` table are read as `see-also`
+
+use anyhow::anyhow;
 use anyhow::Result;
+use scraper::{Html, Selector};
+use sqlx::migrate::MigrateDatabase;
+use sqlx::sqlite::SqlitePool;
 use sqlx::Row;
+use sqlx::Sqlite;
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+use std::collections::HashMap;
 use std::env;
+use std::fmt;
+use std::path::PathBuf;
 
+use crate::config::Config;
 use crate::fsrs::sqlite_history::{conn, SQLiteHistory};
+use crate::mdict::Mdx;
 
-impl SQLiteHistory {
-    pub async fn extend_by_merriam(&mut self, word: &str) -> Result<()> {
-        let mut p = env::current_exe().unwrap();
-        p.pop();
-        p.push("merriam.db");
+/// merriam.db next to the executable
+pub fn merriam_db_path() -> PathBuf {
+    let mut p = env::current_exe().unwrap();
+    p.pop();
+    p.push("merriam.db");
+    p
+}
+
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    Default,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+    serde::Deserialize,
+    serde::Serialize,
+    clap::ValueEnum,
+)]
+#[serde(rename_all = "kebab-case")]
+pub enum Relation {
+    Synonym,
+    Antonym,
+    #[default]
+    SeeAlso,
+    Derived,
+}
+
+impl Relation {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Relation::Synonym => "synonym",
+            Relation::Antonym => "antonym",
+            Relation::SeeAlso => "see-also",
+            Relation::Derived => "derived",
+        }
+    }
+}
+
+impl fmt::Display for Relation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
 
-        let merriam = conn(p.to_str().unwrap()).await?;
-        let related_word: String =
+pub(crate) async fn has_table(pool: &SqlitePool, table: &str) -> Result<bool> {
+    let row = sqlx::query("SELECT COUNT(*) FROM sqlite_master WHERE type = 'table' AND name = $1;")
+        .bind(table)
+        .fetch_one(pool)
+        .await?;
+    Ok(row.get::<i64, _>(0) > 0)
+}
+
+/// related word -> weight
+/// `relations`: empty means all
+async fn query_related(
+    pool: &SqlitePool,
+    word: &str,
+    relations: &[Relation],
+) -> Result<Vec<(String, f64)>> {
+    if has_table(pool, "related").await? {
+        // relations are from a closed set, safe to format
+        let filter = if relations.is_empty() {
+            String::new()
+        } else {
+            let v: Vec<String> = relations.iter().map(|r| format!("'{r}'")).collect();
+            format!(" AND relation IN ({})", v.join(", "))
+        };
+        let v = sqlx::query(&format!(
+            "SELECT related, SUM(weight) FROM related WHERE word = $1{filter} GROUP BY related;"
+        ))
+        .bind(word)
+        .fetch_all(pool)
+        .await?
+        .into_iter()
+        .map(|row| (row.get(0), row.get(1)))
+        .collect();
+        Ok(v)
+    } else if has_table(pool, "merriam").await?
+        && (relations.is_empty() || relations.contains(&Relation::SeeAlso))
+    {
+        let related_words: Vec<String> =
             sqlx::query("SELECT related_words FROM merriam where word = $1;")
                 .bind(word)
-                .fetch_one(&merriam)
+                .fetch_all(pool)
                 .await?
-                .get(0);
-        self.queue
-            .extend(related_word.split(',').map(ToOwned::to_owned));
+                .into_iter()
+                .map(|row| row.get(0))
+                .collect();
+        Ok(related_words
+            .iter()
+            .flat_map(|x| x.split(','))
+            .map(|x| (x.to_owned(), 1.0))
+            .collect())
+    } else {
+        Ok(Vec::new())
+    }
+}
+
+/// The configured databases, opened once
+pub struct RelatedDatabases(Vec<SqlitePool>);
+
+impl RelatedDatabases {
+    /// Missing databases are skipped
+    pub async fn open(config: &Config) -> Result<Self> {
+        let mut pools = Vec::new();
+        for p in config.related_databases() {
+            if p.exists() {
+                pools.push(conn(p.to_str().unwrap()).await?);
+            }
+        }
+        Ok(Self(pools))
+    }
+
+    /// Search every database, the heaviest first
+    /// `relations`: empty means all
+    pub async fn related_words(&self, word: &str, relations: &[Relation]) -> Result<Vec<String>> {
+        let mut weights: HashMap<String, f64> = HashMap::new();
+        for pool in &self.0 {
+            for (related, weight) in query_related(pool, word, relations).await? {
+                *weights.entry(related).or_default() += weight;
+            }
+        }
+
+        let mut v: Vec<(String, f64)> = weights.into_iter().collect();
+        v.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(Ordering::Equal));
+        Ok(v.into_iter().map(|(w, _)| w).collect())
+    }
+}
+
+impl SQLiteHistory {
+    pub async fn extend_by_merriam(&mut self, word: &str) -> Result<()> {
+        if self.related.is_none() {
+            self.related = Some(RelatedDatabases::open(&Config::load()?).await?);
+        }
+        let related = self.related.as_ref().unwrap();
+        let v = related.related_words(word, &self.relations).await?;
+        self.queue.extend(v);
+        Ok(())
+    }
+}
+
+/// `entry://word#sense` or `bword://word` -> `word`
+fn link_target(href: &str) -> Option<String> {
+    let (_scheme, target) = href.split_once("://")?;
+    let target = target.split('#').next()?.trim_end_matches('/');
+    let target = urlencoding::decode(target).ok()?;
+    let target = target.trim();
+    (!target.is_empty()).then(|| target.to_owned())
+}
+
+/// Headwords linked from the elements matched by `selector`
+/// The links inside are used if the element is not a link
+pub fn extract_related(doc: &Html, selector: &Selector) -> BTreeSet<String> {
+    let a = Selector::parse("a[href]").unwrap();
+
+    let mut res = BTreeSet::new();
+    for element in doc.select(selector) {
+        let links: Vec<_> = if element.value().name() == "a" {
+            vec![element]
+        } else {
+            element.select(&a).collect()
+        };
+        for link in links {
+            if let Some(word) = link.value().attr("href").and_then(link_target) {
+                res.insert(word);
+            }
+        }
+    }
+    res
+}
+
+/// (word, related word, relation, source) -> weight
+#[derive(Debug, Default)]
+pub struct RelatedWords(BTreeMap<(String, String, Relation, String), f64>);
+
+impl RelatedWords {
+    /// Scan every entry of `mdx`
+    /// The weight is the times a word is linked in the entry
+    pub fn add_mdx(
+        &mut self,
+        mdx: &mut Mdx,
+        source: &str,
+        selectors: &[(Relation, &str)],
+    ) -> Result<()> {
+        let selectors = selectors
+            .iter()
+            .map(|(relation, selector)| {
+                Selector::parse(selector)
+                    .map(|s| (*relation, s))
+                    .map_err(|e| anyhow!("invalid selector {selector}: {e}"))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        mdx.for_each(|key, html| {
+            let doc = Html::parse_fragment(html);
+            for (relation, selector) in &selectors {
+                for related in extract_related(&doc, selector) {
+                    if related == key {
+                        continue;
+                    }
+                    self.insert(key, &related, *relation, source);
+                }
+            }
+            Ok(())
+        })
+    }
+
+    /// Adds 1 to the weight
+    pub fn insert(&mut self, word: &str, related: &str, relation: Relation, source: &str) {
+        *self
+            .0
+            .entry((
+                word.to_owned(),
+                related.to_owned(),
+                relation,
+                source.to_owned(),
+            ))
+            .or_default() += 1.0;
+    }
+
+    /// rows
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Replace the `related` table in `path`
+    pub async fn write(&self, path: &str) -> Result<()> {
+        if !Sqlite::database_exists(path).await? {
+            Sqlite::create_database(path).await?;
+        }
+        let pool = conn(path).await?;
+        let mut tx = pool.begin().await?;
+        sqlx::raw_sql(
+            "
+DROP TABLE IF EXISTS related;
+CREATE TABLE related (
+    word TEXT NOT NULL COLLATE NOCASE,
+    related TEXT NOT NULL,
+    relation TEXT NOT NULL,
+    source TEXT NOT NULL,
+    weight REAL NOT NULL DEFAULT 1,
+    PRIMARY KEY (word, related, relation, source)
+);
+",
+        )
+        .execute(&mut *tx)
+        .await?;
+        for ((word, related, relation, source), weight) in &self.0 {
+            sqlx::query("INSERT OR REPLACE INTO related (word, related, relation, source, weight) VALUES ($1, $2, $3, $4, $5);")
+                .bind(word)
+                .bind(related)
+                .bind(relation.as_str())
+                .bind(source)
+                .bind(weight)
+                .execute(&mut *tx)
+                .await?;
+        }
+        tx.commit().await?;
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn link_targets() {
+        assert_eq!(link_target("entry://apple#n1").as_deref(), Some("apple"));
+        assert_eq!(
+            link_target("bword://ice%20cream/").as_deref(),
+            Some("ice cream")
+        );
+        assert_eq!(link_target("entry://"), None);
+        assert_eq!(link_target("apple"), None);
+    }
+
+    #[test]
+    fn links_inside_or_the_link_itself() {
+        let doc = Html::parse_fragment(
+            r#"<div class="syn"><a href="entry://big">big</a>, <a href="entry://large">large</a></div>
+<a class="ant" href="bword://small">small</a>
+<a href="entry://other">other</a>"#,
+        );
+        let syn = Selector::parse(".syn").unwrap();
+        let ant = Selector::parse("a.ant").unwrap();
+        assert_eq!(
+            extract_related(&doc, &syn).into_iter().collect::<Vec<_>>(),
+            ["big", "large"]
+        );
+        assert_eq!(
+            extract_related(&doc, &ant).into_iter().collect::<Vec<_>>(),
+            ["small"]
+        );
+    }
+
+    #[tokio::test]
+    async fn write_then_query() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("related.db");
+        let path = path.to_str().unwrap();
+
+        let mut related = RelatedWords::default();
+        for (word, other, relation, source, weight) in [
+            ("huge", "big", Relation::Synonym, "a", 1.0),
+            ("huge", "big", Relation::Synonym, "b", 2.0),
+            ("huge", "large", Relation::Synonym, "a", 1.0),
+            ("huge", "tiny", Relation::Antonym, "a", 1.0),
+        ] {
+            related
+                .0
+                .insert((word.into(), other.into(), relation, source.into()), weight);
+        }
+        related.write(path).await.unwrap();
+        // replaced, not appended
+        related.write(path).await.unwrap();
+
+        let pool = conn(path).await.unwrap();
+        let mut all = query_related(&pool, "HUGE", &[]).await.unwrap();
+        all.sort_by(|a, b| a.0.cmp(&b.0));
+        assert_eq!(
+            all,
+            [
+                ("big".to_owned(), 3.0),
+                ("large".to_owned(), 1.0),
+                ("tiny".to_owned(), 1.0)
+            ]
+        );
+        assert_eq!(
+            query_related(&pool, "huge", &[Relation::Antonym])
+                .await
+                .unwrap(),
+            [("tiny".to_owned(), 1.0)]
+        );
+        assert!(query_related(&pool, "huge", &[Relation::Derived])
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn extend_by_opened_databases() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("related.db");
+        let path = path.to_str().unwrap();
+        Sqlite::create_database(path).await.unwrap();
+        let pool = conn(path).await.unwrap();
+        sqlx::raw_sql(
+            "CREATE TABLE merriam (word TEXT, related_words TEXT);
+INSERT INTO merriam VALUES ('huge', 'big');",
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+
+        let mut history = SQLiteHistory::temp(dir.path()).await;
+        history.related = Some(RelatedDatabases(vec![pool]));
+        history.extend_by_merriam("huge").await.unwrap();
+        assert_eq!(history.queue, ["big"]);
+    }
+
+    #[tokio::test]
+    async fn merriam_table_is_see_also() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("merriam.db");
+        let path = path.to_str().unwrap();
+        Sqlite::create_database(path).await.unwrap();
+        let pool = conn(path).await.unwrap();
+        sqlx::raw_sql(
+            "CREATE TABLE merriam (word TEXT, related_words TEXT);
+INSERT INTO merriam VALUES ('huge', 'big,large');",
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+
+        assert_eq!(
+            query_related(&pool, "huge", &[Relation::SeeAlso])
+                .await
+                .unwrap(),
+            [("big".to_owned(), 1.0), ("large".to_owned(), 1.0)]
+        );
+        assert!(query_related(&pool, "huge", &[Relation::Synonym])
+            .await
+            .unwrap()
+            .is_empty());
+    }
+}
@@ -0,0 +1,739 @@
+//! Queue words which sound alike, e.g. council/counsel
+//!
+//! Words sound alike if the IPA of ECDICT is close, or else their Double Metaphone keys match
+
+use anyhow::Result;
+use sqlx::Row;
+
+use crate::csv::{has_ecdict, ECDICT_WORD};
+use crate::fsrs::sqlite_history::SQLiteHistory;
+
+/// edit distance of the normalized IPA
+const MAX_DISTANCE: usize = 1;
+
+/// Drop stress, length marks and brackets: `/'kaunsəl/` -> `kaunsəl`
+pub fn normalize_ipa(ipa: &str) -> String {
+    ipa.chars()
+        .filter(|c| {
+            !matches!(
+                c,
+                '\'' | 'ˈ' | 'ˌ' | ':' | 'ː' | '/' | '[' | ']' | '(' | ')' | ','
+            )
+        })
+        .filter(|c| !c.is_whitespace())
+        .collect()
+}
+
+/// Keys longer than this are cut
+const KEY_LEN: usize = 4;
+
+/// Lawrence Philips' Double Metaphone: (primary, alternate) keys
+struct DoubleMetaphone {
+    w: Vec<char>,
+    slavo_germanic: bool,
+    primary: String,
+    alternate: String,
+}
+
+impl DoubleMetaphone {
+    fn at(&self, i: isize) -> char {
+        usize::try_from(i)
+            .ok()
+            .and_then(|i| self.w.get(i))
+            .copied()
+            .unwrap_or('\0')
+    }
+
+    fn is_vowel(&self, i: isize) -> bool {
+        matches!(self.at(i), 'A' | 'E' | 'I' | 'O' | 'U' | 'Y')
+    }
+
+    /// One of `patterns`, all of the same length, starts at `i`
+    fn is(&self, i: isize, patterns: &[&str]) -> bool {
+        let len = patterns[0].len() as isize;
+        if i < 0 || i + len > self.w.len() as isize {
+            return false;
+        }
+        let s: String = self.w[i as usize..(i + len) as usize].iter().collect();
+        patterns.contains(&s.as_str())
+    }
+
+    fn last(&self) -> isize {
+        self.w.len() as isize - 1
+    }
+
+    fn add(&mut self, primary: &str, alternate: &str) {
+        self.primary.push_str(primary);
+        self.alternate.push_str(alternate);
+    }
+
+    fn add_both(&mut self, key: &str) {
+        self.add(key, key);
+    }
+
+    fn is_done(&self) -> bool {
+        self.primary.len() >= KEY_LEN && self.alternate.len() >= KEY_LEN
+    }
+
+    fn germanic(&self) -> bool {
+        self.is(0, &["VAN ", "VON "]) || self.is(0, &["SCH"])
+    }
+
+    fn c(&mut self, i: isize) -> isize {
+        // various germanic
+        if self.is(i, &["CHIA"])
+            || (i > 1
+                && !self.is_vowel(i - 2)
+                && self.is(i - 1, &["ACH"])
+                && (!matches!(self.at(i + 2), 'I' | 'E') || self.is(i - 2, &["BACHER", "MACHER"])))
+        {
+            self.add_both("K");
+            return i + 2;
+        }
+        if i == 0 && self.is(i, &["CAESAR"]) {
+            self.add_both("S");
+            return i + 2;
+        }
+        if self.is(i, &["CH"]) {
+            if i > 0 && self.is(i, &["CHAE"]) {
+                // michael
+                self.add("K", "X");
+            } else if i == 0
+                && (self.is(i + 1, &["HARAC", "HARIS"])
+                    || self.is(i + 1, &["HOR", "HYM", "HIA", "HEM"]))
+                && !self.is(0, &["CHORE"])
+            {
+                // greek roots: chemistry, chorus
+                self.add_both("K");
+            } else if self.germanic()
+                || self.is(i - 2, &["ORCHES", "ARCHIT", "ORCHID"])
+                || self.is(i + 2, &["T", "S"])
+                || ((i == 0 || self.is(i - 1, &["A", "O", "U", "E"]))
+                    && (self.is(i + 2, &["L", "R", "N", "M", "B", "H", "F", "V", "W", " "])
+                        || i + 1 == self.last()))
+            {
+                self.add_both("K");
+            } else if i == 0 {
+                self.add_both("X");
+            } else if self.is(0, &["MC"]) {
+                self.add_both("K");
+            } else {
+                self.add("X", "K");
+            }
+            return i + 2;
+        }
+        if self.is(i, &["CZ"]) && !self.is(i - 2, &["WICZ"]) {
+            // czerny
+            self.add("S", "X");
+            return i + 2;
+        }
+        if self.is(i + 1, &["CIA"]) {
+            // focaccia
+            self.add_both("X");
+            return i + 3;
+        }
+        if self.is(i, &["CC"]) && !(i == 1 && self.at(0) == 'M') {
+            // but not mcclelland
+            if self.is(i + 2, &["I", "E", "H"]) && !self.is(i + 2, &["HU"]) {
+                if (i == 1 && self.at(0) == 'A') || self.is(i - 1, &["UCCEE", "UCCES"]) {
+                    // accident, accede, succeed
+                    self.add_both("KS");
+                } else {
+                    // bacci, bertucci
+                    self.add_both("X");
+                }
+                return i + 3;
+            }
+            self.add_both("K");
+            return i + 2;
+        }
+        if self.is(i, &["CK", "CG", "CQ"]) {
+            self.add_both("K");
+            return i + 2;
+        }
+        if self.is(i, &["CI", "CE", "CY"]) {
+            if self.is(i, &["CIO", "CIE", "CIA"]) {
+                self.add("S", "X");
+            } else {
+                self.add_both("S");
+            }
+            return i + 2;
+        }
+        self.add_both("K");
+        if self.is(i + 1, &[" C", " Q", " G"]) {
+            // mac caffrey, mac gregor
+            i + 3
+        } else if self.is(i + 1, &["C", "K", "Q"]) && !self.is(i + 1, &["CE", "CI"]) {
+            i + 2
+        } else {
+            i + 1
+        }
+    }
+
+    fn d(&mut self, i: isize) -> isize {
+        if self.is(i, &["DG"]) {
+            if self.is(i + 2, &["I", "E", "Y"]) {
+                // edge
+                self.add_both("J");
+                return i + 3;
+            }
+            // edgar
+            self.add_both("TK");
+            return i + 2;
+        }
+        self.add_both("T");
+        if self.is(i, &["DT", "DD"]) {
+            i + 2
+        } else {
+            i + 1
+        }
+    }
+
+    fn g(&mut self, i: isize) -> isize {
+        let next = self.at(i + 1);
+        if next == 'H' {
+            if i > 0 && !self.is_vowel(i - 1) {
+                self.add_both("K");
+            } else if i == 0 {
+                // ghislane, ghiradelli
+                self.add_both(if self.at(i + 2) == 'I' { "J" } else { "K" });
+            } else if (i > 1 && self.is(i - 2, &["B", "H", "D"]))
+                || (i > 2 && self.is(i - 3, &["B", "H", "D"]))
+                || (i > 3 && self.is(i - 4, &["B", "H"]))
+            {
+                // silent: hugh, bough, broughton
+            } else if i > 2 && self.at(i - 1) == 'U' && self.is(i - 3, &["C", "G", "L", "R", "T"]) {
+                // laugh, cough, rough
+                self.add_both("F");
+            } else if self.at(i - 1) != 'I' {
+                self.add_both("K");
+            }
+            return i + 2;
+        }
+        if next == 'N' {
+            if i == 1 && self.is_vowel(0) && !self.slavo_germanic {
+                self.add("KN", "N");
+            } else if !self.is(i + 2, &["EY"]) && !self.slavo_germanic {
+                // not cagney
+                self.add("N", "KN");
+            } else {
+                self.add_both("KN");
+            }
+            return i + 2;
+        }
+        if self.is(i + 1, &["LI"]) && !self.slavo_germanic {
+            // tagliaro
+            self.add("KL", "L");
+            return i + 2;
+        }
+        if i == 0
+            && (next == 'Y'
+                || self.is(
+                    i + 1,
+                    &[
+                        "ES", "EP", "EB", "EL", "EY", "IB", "IL", "IN", "IE", "EI", "ER",
+                    ],
+                ))
+        {
+            self.add("K", "J");
+            return i + 2;
+        }
+        if (self.is(i + 1, &["ER"]) || next == 'Y')
+            && !self.is(0, &["DANGER", "RANGER", "MANGER"])
+            && !self.is(i - 1, &["E", "I"])
+            && !self.is(i - 1, &["RGY", "OGY"])
+        {
+            self.add("K", "J");
+            return i + 2;
+        }
+        if self.is(i + 1, &["E", "I", "Y"]) || self.is(i - 1, &["AGGI", "OGGI"]) {
+            if self.germanic() || self.is(i + 1, &["ET"]) {
+                self.add_both("K");
+            } else if self.is(i + 1, &["IER"]) {
+                self.add_both("J");
+            } else {
+                self.add("J", "K");
+            }
+            return i + 2;
+        }
+        self.add_both("K");
+        if next == 'G' {
+            i + 2
+        } else {
+            i + 1
+        }
+    }
+
+    fn h(&mut self, i: isize) -> isize {
+        // only between vowels, or first before a vowel
+        if (i == 0 || self.is_vowel(i - 1)) && self.is_vowel(i + 1) {
+            self.add_both("H");
+            i + 2
+        } else {
+            i + 1
+        }
+    }
+
+    fn j(&mut self, i: isize) -> isize {
+        if self.is(i, &["JOSE"]) || self.is(0, &["SAN "]) {
+            // spanish: jose, san jacinto
+            if (i == 0 && self.at(i + 4) == ' ') || self.w.len() == 4 || self.is(0, &["SAN "]) {
+                self.add_both("H");
+            } else {
+                self.add("J", "H");
+            }
+            return i + 1;
+        }
+        if i == 0 {
+            // yankelovich, jankelowicz
+            self.add("J", "A");
+        } else if self.is_vowel(i - 1)
+            && !self.slavo_germanic
+            && matches!(self.at(i + 1), 'A' | 'O')
+        {
+            // spanish pronunciation of bajador
+            self.add("J", "H");
+        } else if i == self.last() {
+            self.add("J", "");
+        } else if !self.is(i + 1, &["L", "T", "K", "S", "N", "M", "B", "Z"])
+            && !self.is(i - 1, &["S", "K", "L"])
+        {
+            self.add_both("J");
+        }
+        if self.at(i + 1) == 'J' {
+            i + 2
+        } else {
+            i + 1
+        }
+    }
+
+    fn l(&mut self, i: isize) -> isize {
+        if self.at(i + 1) != 'L' {
+            self.add_both("L");
+            return i + 1;
+        }
+        let last = self.last();
+        // spanish: cabrillo, gallegos
+        if (i == last - 2 && self.is(i - 1, &["ILLO", "ILLA", "ALLE"]))
+            || ((self.is(last - 1, &["AS", "OS"]) || self.is(last, &["A", "O"]))
+                && self.is(i - 1, &["ALLE"]))
+        {
+            self.add("L", "");
+        } else {
+            self.add_both("L");
+        }
+        i + 2
+    }
+
+    fn m(&mut self, i: isize) -> isize {
+        self.add_both("M");
+        // dumb, thumb
+        if self.at(i + 1) == 'M'
+            || (self.is(i - 1, &["UMB"]) && (i + 1 == self.last() || self.is(i + 2, &["ER"])))
+        {
+            i + 2
+        } else {
+            i + 1
+        }
+    }
+
+    fn r(&mut self, i: isize) -> isize {
+        // french: rogier
+        if i == self.last()
+            && !self.slavo_germanic
+            && self.is(i - 2, &["IE"])
+            && !self.is(i - 4, &["ME", "MA"])
+        {
+            self.add("", "R");
+        } else {
+            self.add_both("R");
+        }
+        if self.at(i + 1) == 'R' {
+            i + 2
+        } else {
+            i + 1
+        }
+    }
+
+    fn s(&mut self, i: isize) -> isize {
+        if self.is(i - 1, &["ISL", "YSL"]) {
+            // silent: island, isle, carlisle
+            return i + 1;
+        }
+        if i == 0 && self.is(i, &["SUGAR"]) {
+            self.add("X", "S");
+            return i + 1;
+        }
+        if self.is(i, &["SH"]) {
+            if self.is(i + 1, &["HEIM", "HOEK", "HOLM", "HOLZ"]) {
+                // germanic
+                self.add_both("S");
+            } else {
+                self.add_both("X");
+            }
+            return i + 2;
+        }
+        if self.is(i, &["SIO", "SIA"]) || self.is(i, &["SIAN"]) {
+            // italian, armenian
+            if self.slavo_germanic {
+                self.add_both("S");
+            } else {
+                self.add("S", "X");
+            }
+            return i + 3;
+        }
+        if (i == 0 && self.is(i + 1, &["M", "N", "L", "W"])) || self.is(i + 1, &["Z"]) {
+            // smith matches schmidt, snider matches schneider
+            self.add("S", "X");
+            return if self.is(i + 1, &["Z"]) { i + 2 } else { i + 1 };
+        }
+        if self.is(i, &["SC"]) {
+            if self.at(i + 2) == 'H' {
+                if self.is(i + 3, &["OO", "ER", "EN", "UY", "ED", "EM"]) {
+                    // dutch: school, schooner; schermerhorn, schenker
+                    if self.is(i + 3, &["ER", "EN"]) {
+                        self.add("X", "SK");
+                    } else {
+                        self.add_both("SK");
+                    }
+                } else if i == 0 && !self.is_vowel(3) && self.at(3) != 'W' {
+                    self.add("X", "S");
+                } else {
+                    self.add_both("X");
+                }
+            } else if self.is(i + 2, &["I", "E", "Y"]) {
+                self.add_both("S");
+            } else {
+                self.add_both("SK");
+            }
+            return i + 3;
+        }
+        if i == self.last() && self.is(i - 2, &["AI", "OI"]) {
+            // french: resnais, artois
+            self.add("", "S");
+        } else {
+            self.add_both("S");
+        }
+        if self.is(i + 1, &["S", "Z"]) {
+            i + 2
+        } else {
+            i + 1
+        }
+    }
+
+    fn t(&mut self, i: isize) -> isize {
+        if self.is(i, &["TION"]) || self.is(i, &["TIA", "TCH"]) {
+            self.add_both("X");
+            return i + 3;
+        }
+        if self.is(i, &["TH"]) || self.is(i, &["TTH"]) {
+            // thomas, thames, or germanic
+            if self.is(i + 2, &["OM", "AM"]) || self.germanic() {
+                self.add_both("T");
+            } else {
+                self.add("0", "T");
+            }
+            return i + 2;
+        }
+        self.add_both("T");
+        if self.is(i + 1, &["T", "D"]) {
+            i + 2
+        } else {
+            i + 1
+        }
+    }
+
+    fn w(&mut self, i: isize) -> isize {
+        if self.is(i, &["WR"]) {
+            self.add_both("R");
+            return i + 2;
+        }
+        if i == 0 && (self.is_vowel(i + 1) || self.is(i, &["WH"])) {
+            if self.is_vowel(i + 1) {
+                // wasserman matches vasserman
+                self.add("A", "F");
+            } else {
+                // uomo matches womo
+                self.add_both("A");
+            }
+            return i + 1;
+        }
+        if (i == self.last() && self.is_vowel(i - 1))
+            || self.is(i - 1, &["EWSKI", "EWSKY", "OWSKI", "OWSKY"])
+            || self.is(0, &["SCH"])
+        {
+            // arnow matches arnoff
+            self.add("", "F");
+            return i + 1;
+        }
+        if self.is(i, &["WICZ", "WITZ"]) {
+            // polish: filipowicz
+            self.add("TS", "FX");
+            return i + 4;
+        }
+        i + 1
+    }
+
+    fn x(&mut self, i: isize) -> isize {
+        if i == 0 {
+            // xavier
+            self.add_both("S");
+            return i + 1;
+        }
+        // french: breaux
+        if !(i == self.last() && (self.is(i - 3, &["IAU", "EAU"]) || self.is(i - 2, &["AU", "OU"])))
+        {
+            self.add_both("KS");
+        }
+        if self.is(i + 1, &["C", "X"]) {
+            i + 2
+        } else {
+            i + 1
+        }
+    }
+
+    fn z(&mut self, i: isize) -> isize {
+        if self.at(i + 1) == 'H' {
+            // chinese pinyin: zhao
+            self.add_both("J");
+            return i + 2;
+        }
+        if self.is(i + 1, &["ZO", "ZI", "ZA"])
+            || (self.slavo_germanic && i > 0 && self.at(i - 1) != 'T')
+        {
+            self.add("S", "TS");
+        } else {
+            self.add_both("S");
+        }
+        if self.at(i + 1) == 'Z' {
+            i + 2
+        } else {
+            i + 1
+        }
+    }
+
+    /// Skip a doubled letter
+    fn single(&mut self, i: isize, key: &str) -> isize {
+        let c = self.at(i);
+        self.add_both(key);
+        if self.at(i + 1) == c {
+            i + 2
+        } else {
+            i + 1
+        }
+    }
+
+    fn encode(mut self) -> (String, String) {
+        // silent: gnome, knight, pneumonia, wrack, psychology
+        let mut i = if self.is(0, &["GN", "KN", "PN", "WR", "PS"]) {
+            1
+        } else {
+            0
+        };
+        while i < self.w.len() as isize && !self.is_done() {
+            i = match self.at(i) {
+                'A' | 'E' | 'I' | 'O' | 'U' | 'Y' => {
+                    if i == 0 {
+                        self.add_both("A");
+                    }
+                    i + 1
+                }
+                'B' => self.single(i, "P"),
+                'Ç' => {
+                    self.add_both("S");
+                    i + 1
+                }
+                'C' => self.c(i),
+                'D' => self.d(i),
+                'F' => self.single(i, "F"),
+                'G' => self.g(i),
+                'H' => self.h(i),
+                'J' => self.j(i),
+                'K' => self.single(i, "K"),
+                'L' => self.l(i),
+                'M' => self.m(i),
+                'N' => self.single(i, "N"),
+                'Ñ' => {
+                    self.add_both("N");
+                    i + 1
+                }
+                'P' => {
+                    if self.at(i + 1) == 'H' {
+                        self.add_both("F");
+                        i + 2
+                    } else {
+                        // campbell, raspberry
+                        self.add_both("P");
+                        if self.is(i + 1, &["P", "B"]) {
+                            i + 2
+                        } else {
+                            i + 1
+                        }
+                    }
+                }
+                'Q' => self.single(i, "K"),
+                'R' => self.r(i),
+                'S' => self.s(i),
+                'T' => self.t(i),
+                'V' => self.single(i, "F"),
+                'W' => self.w(i),
+                'X' => self.x(i),
+                'Z' => self.z(i),
+                _ => i + 1,
+            };
+        }
+        self.primary.truncate(KEY_LEN);
+        self.alternate.truncate(KEY_LEN);
+        (self.primary, self.alternate)
+    }
+}
+
+/// (primary, alternate) Double Metaphone keys, the same if there is no alternate
+pub fn double_metaphone(word: &str) -> (String, String) {
+    let w: Vec<char> = word.trim().to_uppercase().chars().collect();
+    let s: String = w.iter().collect();
+    DoubleMetaphone {
+        slavo_germanic: s.contains(['W', 'K']) || s.contains("CZ"),
+        w,
+        primary: String::new(),
+        alternate: String::new(),
+    }
+    .encode()
+}
+
+struct Sound {
+    ipa: Option<String>,
+    metaphone: (String, String),
+}
+
+impl Sound {
+    fn new(word: &str, phonetic: Option<&str>) -> Self {
+        Self {
+            ipa: phonetic.map(normalize_ipa).filter(|ipa| !ipa.is_empty()),
+            metaphone: double_metaphone(word),
+        }
+    }
+
+    /// `None` if they don't sound alike: neither the IPA nor the Metaphone keys are close
+    fn distance(&self, other: &Sound) -> Option<usize> {
+        let ipa = match (&self.ipa, &other.ipa) {
+            (Some(a), Some(b)) => Some(strsim::levenshtein(a, b)),
+            _ => None,
+        };
+        // Metaphone is coarse: only the same key counts, primary or alternate
+        let (a, b) = (&self.metaphone, &other.metaphone);
+        let same = |x: &String, y: &String| !x.is_empty() && x == y;
+        let metaphone =
+            (same(&a.0, &b.0) || same(&a.0, &b.1) || same(&a.1, &b.0) || same(&a.1, &b.1))
+                .then_some(MAX_DISTANCE);
+        ipa.filter(|d| *d <= MAX_DISTANCE).or(metaphone)
+    }
+}
+
+impl SQLiteHistory {
+    /// Words sounding alike, the closest first
+    pub async fn extend_by_phonetic(&mut self, word: &str) -> Result<()> {
+        // ecdict is imported by `init_ecdict`
+        let has_ecdict = has_ecdict(&self.conn).await?;
+
+        let rows: Vec<(String, Option<String>)> = if has_ecdict {
+            sqlx::query("SELECT word, (SELECT phonetic FROM ecdict WHERE ecdict.word = fsrs.word COLLATE NOCASE ORDER BY ecdict.word = fsrs.word DESC LIMIT 1) FROM fsrs WHERE word != $1 AND session_id != $2;")
+                .bind(word)
+                .bind(self.session_id)
+                .fetch_all(&self.conn)
+                .await?
+                .into_iter()
+                .map(|sqlite_row| (sqlite_row.get(0), sqlite_row.get(1)))
+                .collect()
+        } else {
+            sqlx::query("SELECT word FROM fsrs WHERE word != $1 AND session_id != $2;")
+                .bind(word)
+                .bind(self.session_id)
+                .fetch_all(&self.conn)
+                .await?
+                .into_iter()
+                .map(|sqlite_row| (sqlite_row.get(0), None))
+                .collect()
+        };
+
+        let phonetic: Option<String> = if has_ecdict {
+            sqlx::query(&format!("SELECT phonetic FROM ecdict WHERE {ECDICT_WORD};"))
+                .bind(word)
+                .fetch_optional(&self.conn)
+                .await?
+                .map(|sqlite_row| sqlite_row.get(0))
+        } else {
+            None
+        };
+        let sound = Sound::new(word, phonetic.as_deref());
+
+        let mut similar_words: Vec<(usize, String)> = rows
+            .into_iter()
+            .flat_map(|(w, phonetic)| {
+                sound
+                    .distance(&Sound::new(&w, phonetic.as_deref()))
+                    .map(|distance| (distance, w))
+            })
+            .collect();
+        similar_words.sort_by_key(|(distance, _)| *distance);
+
+        self.queue.extend(similar_words.into_iter().map(|(_, w)| w));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keys(word: &str) -> (String, String) {
+        double_metaphone(word)
+    }
+
+    #[test]
+    fn known_keys() {
+        assert_eq!(keys("Smith"), ("SM0".into(), "XMT".into()));
+        assert_eq!(keys("Schmidt"), ("XMT".into(), "SMT".into()));
+        assert_eq!(keys("Thompson"), ("TMPS".into(), "TMPS".into()));
+        assert_eq!(keys("Thumb"), ("0M".into(), "TM".into()));
+        assert_eq!(keys("Michael"), ("MKL".into(), "MXL".into()));
+        assert_eq!(keys("Xavier"), ("SF".into(), "SFR".into()));
+        assert_eq!(keys("Arnow"), ("ARN".into(), "ARNF".into()));
+        assert_eq!(keys("knight"), ("NT".into(), "NT".into()));
+        assert_eq!(keys("laugh"), ("LF".into(), "LF".into()));
+        assert_eq!(keys(""), ("".into(), "".into()));
+    }
+
+    #[test]
+    fn either_key_matches() {
+        let smith = Sound::new("Smith", None);
+        assert_eq!(
+            smith.distance(&Sound::new("Schmidt", None)),
+            Some(MAX_DISTANCE)
+        );
+        assert_eq!(
+            Sound::new("council", None).distance(&Sound::new("counsel", None)),
+            Some(MAX_DISTANCE)
+        );
+        assert_eq!(smith.distance(&Sound::new("Thompson", None)), None);
+    }
+
+    #[test]
+    fn metaphone_is_a_fallback() {
+        let a = Sound::new("council", Some("/'kaunsəl/"));
+        assert_eq!(
+            a.distance(&Sound::new("counsel", Some("ˈkaunsəl"))),
+            Some(0)
+        );
+        // IPA of the dictionaries differ, Metaphone keys still match
+        assert_eq!(
+            a.distance(&Sound::new("counsel", Some("'kaʊnsl"))),
+            Some(MAX_DISTANCE)
+        );
+        assert_eq!(
+            a.distance(&Sound::new("cancel", Some("'kænsəl"))),
+            Some(MAX_DISTANCE)
+        );
+        assert_eq!(normalize_ipa("[ˈkaʊn.sl̩]"), "kaʊn.sl̩");
+    }
+}
@@ -0,0 +1,135 @@
+//! Review log: every rating, kept since schema 4
+//!
+//! A card can be rebuilt by replaying its log, if the log is complete
+
+use anyhow::Result;
+use chrono::DateTime;
+use rs_fsrs::{Card, Rating, FSRS};
+use sqlx::sqlite::SqlitePool;
+use sqlx::Row;
+
+use crate::fsrs::sqlite_history::SQLiteHistory;
+use crate::merriam::has_table;
+
+pub fn rating_to_i64(rating: Rating) -> i64 {
+    match rating {
+        Rating::Again => 1,
+        Rating::Hard => 2,
+        Rating::Good => 3,
+        Rating::Easy => 4,
+    }
+}
+
+pub fn rating_from_i64(rating: i64) -> Option<Rating> {
+    match rating {
+        1 => Some(Rating::Again),
+        2 => Some(Rating::Hard),
+        3 => Some(Rating::Good),
+        4 => Some(Rating::Easy),
+        _ => None,
+    }
+}
+
+/// `reviews`: (milliseconds since the unix epoch, rating) in chronological order
+pub fn replay(fsrs: &FSRS, reviews: &[(i64, Rating)]) -> Card {
+    let mut card = Card::new();
+    for (ms, rating) in reviews {
+        let Some(now) = DateTime::from_timestamp_millis(*ms) else {
+            continue;
+        };
+        card = fsrs.next(card, now, *rating).card;
+    }
+    card
+}
+
+/// (milliseconds, rating) of the word in chronological order
+/// Empty if the database predates the review log
+pub async fn get_reviews(pool: &SqlitePool, word: &str) -> Result<Vec<(i64, Rating)>> {
+    if !has_table(pool, "review").await? {
+        return Ok(Vec::new());
+    }
+    let v = sqlx::query("SELECT timestamp, rating FROM review WHERE word = $1 ORDER BY timestamp;")
+        .bind(word)
+        .fetch_all(pool)
+        .await?
+        .into_iter()
+        .flat_map(|sqlite_row| {
+            let rating = rating_from_i64(sqlite_row.get(1))?;
+            Some((sqlite_row.get(0), rating))
+        })
+        .collect();
+    Ok(v)
+}
+
+impl SQLiteHistory {
+    /// `timestamp`: milliseconds since the unix epoch
+    pub async fn log_review(&self, word: &str, rating: Rating, timestamp: i64) -> Result<()> {
+        sqlx::query("INSERT OR IGNORE INTO review (word, rating, timestamp) VALUES ($1, $2, $3);")
+            .bind(word)
+            .bind(rating_to_i64(rating))
+            .bind(timestamp)
+            .execute(&self.conn)
+            .await?;
+        Ok(())
+    }
+
+    pub async fn reviews(&self, word: &str) -> Result<Vec<(i64, Rating)>> {
+        get_reviews(&self.conn, word).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rs_fsrs::{Parameters, State};
+
+    #[test]
+    fn ratings() {
+        for i in 1..=4 {
+            assert_eq!(rating_to_i64(rating_from_i64(i).unwrap()), i);
+        }
+        assert_eq!(rating_from_i64(0), None);
+        assert_eq!(rating_from_i64(5), None);
+    }
+
+    #[test]
+    fn replay_in_order() {
+        let fsrs = FSRS::new(Parameters::default());
+        assert_eq!(replay(&fsrs, &[]).state, State::New);
+
+        let day = 86_400_000;
+        let reviews = [
+            (1_700_000_000_000, Rating::Good),
+            (1_700_000_000_000 + 3 * day, Rating::Again),
+            // out of range: skipped
+            (i64::MAX, Rating::Easy),
+        ];
+        let card = replay(&fsrs, &reviews);
+        assert_eq!(card.reps, 2);
+        assert_eq!(card.last_review.timestamp_millis(), reviews[1].0);
+    }
+
+    #[tokio::test]
+    async fn reviews_in_chronological_order() {
+        let dir = tempfile::tempdir().unwrap();
+        let history = SQLiteHistory::temp(dir.path()).await;
+        history.log_review("quay", Rating::Hard, 20).await.unwrap();
+        history.log_review("quay", Rating::Good, 10).await.unwrap();
+        // the same review once
+        history.log_review("quay", Rating::Good, 10).await.unwrap();
+        history.log_review("brine", Rating::Easy, 15).await.unwrap();
+        assert_eq!(
+            history.reviews("quay").await.unwrap(),
+            [(10, Rating::Good), (20, Rating::Hard)]
+        );
+
+        // before schema 4
+        let pool = crate::fsrs::sqlite_history::conn(&format!(
+            "sqlite:{}?mode=rwc",
+            dir.path().join("old.db").display()
+        ))
+        .await
+        .unwrap();
+        assert!(get_reviews(&pool, "quay").await.unwrap().is_empty());
+    }
+}
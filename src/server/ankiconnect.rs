@@ -0,0 +1,234 @@
+//! The subset of AnkiConnect used by goldendict-ng's "Add to Anki" button
+//!
+//! <https://git.sr.ht/~foosoft/anki-connect>
+//! Notes go to history.db: the word field becomes a card, the sentence field its context
+//! Browser origins follow `cors_origin_list`, AnkiConnect's `webCorsOriginList`
+
+use anyhow::anyhow;
+use anyhow::Result;
+use chrono::Utc;
+use serde_json::{json, Value};
+
+use super::http::{Request, Response};
+use crate::anki::strip_html;
+use crate::fsrs::get_word_ignore_case;
+use crate::fsrs::sqlite_history::SQLiteHistory;
+use crate::import::{julianday, Context};
+
+const VERSION: i64 = 6;
+const DECK: &str = "goldendict";
+const MODEL: &str = "Basic";
+
+/// Matched case-insensitively, the first field if none matches
+const WORD_FIELDS: [&str; 4] = ["word", "front", "expression", "headword"];
+const SENTENCE_FIELDS: [&str; 3] = ["sentence", "context", "example"];
+
+#[derive(Debug, serde::Deserialize)]
+struct Call {
+    action: String,
+    #[serde(default)]
+    version: Option<i64>,
+    #[serde(default)]
+    params: Value,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct Note {
+    #[serde(default)]
+    fields: serde_json::Map<String, Value>,
+    #[serde(default)]
+    tags: Vec<String>,
+}
+
+fn field<'a>(fields: &'a serde_json::Map<String, Value>, names: &[&str]) -> Option<&'a str> {
+    fields
+        .iter()
+        .find(|(k, _)| names.contains(&k.to_lowercase().as_str()))
+        .and_then(|(_, v)| v.as_str())
+}
+
+async fn add_note(history: &mut SQLiteHistory, params: &Value) -> Result<Value> {
+    let note: Note = serde_json::from_value(params["note"].clone())?;
+    let word = field(&note.fields, &WORD_FIELDS)
+        .or_else(|| note.fields.values().find_map(Value::as_str))
+        .map(strip_html)
+        .filter(|word| !word.is_empty())
+        .ok_or_else(|| anyhow!("cannot create note because it is empty"))?;
+
+    let (word, _) = history.add_word(&word).await?;
+    if let Some(sentence) = field(&note.fields, &SENTENCE_FIELDS)
+        .map(strip_html)
+        .filter(|s| !s.is_empty() && *s != word)
+    {
+        history
+            .insert_context(
+                &word,
+                &Context {
+                    sentence,
+                    book: None,
+                    source: "goldendict".to_owned(),
+                    timestamp: Some(julianday(Utc::now().timestamp() as f64)),
+                },
+            )
+            .await?;
+    }
+    for tag in &note.tags {
+        history.add_tag(&word, tag).await?;
+    }
+
+    // note id: rowid of the card
+    let id: i64 = sqlx::query_scalar("SELECT rowid FROM fsrs WHERE word = $1;")
+        .bind(&word)
+        .fetch_one(&history.conn)
+        .await?;
+    Ok(json!(id))
+}
+
+/// `deck:goldendict "word"` or `word:word` -> rowids of the words
+async fn gui_browse(history: &SQLiteHistory, params: &Value) -> Result<Value> {
+    let query = params["query"].as_str().unwrap_or_default();
+    let mut ids = Vec::new();
+    for term in query.split_whitespace() {
+        let term = match term.split_once(':') {
+            Some(("deck" | "note" | "tag" | "is" | "added" | "rated", _)) => continue,
+            Some((_, value)) => value,
+            None => term,
+        };
+        let Ok(word) = get_word_ignore_case(&history.conn, term.trim_matches('"')).await else {
+            continue;
+        };
+        let id: i64 = sqlx::query_scalar("SELECT rowid FROM fsrs WHERE word = $1;")
+            .bind(&word)
+            .fetch_one(&history.conn)
+            .await?;
+        ids.push(id);
+    }
+    Ok(json!(ids))
+}
+
+/// No dialog like Anki's: origins are allowed in helper.json
+fn request_permission(allowed: bool) -> Value {
+    if allowed {
+        json!({
+            "permission": "granted",
+            "requireApiKey": false,
+            "version": VERSION,
+        })
+    } else {
+        json!({ "permission": "denied" })
+    }
+}
+
+async fn call(history: &mut SQLiteHistory, call: &Call) -> Result<Value> {
+    match call.action.as_str() {
+        "version" => Ok(json!(VERSION)),
+        "deckNames" => Ok(json!([DECK])),
+        "modelNames" => Ok(json!([MODEL])),
+        "modelFieldNames" => Ok(json!(["Front", "Back", "Sentence"])),
+        "addNote" => add_note(history, &call.params).await,
+        "guiBrowse" => gui_browse(history, &call.params).await,
+        action => Err(anyhow!("unsupported action: {action}")),
+    }
+}
+
+/// Version 4 and below return the bare result
+/// `allowed`: the origin of the request may call, otherwise only `requestPermission`
+pub async fn handle(history: &mut SQLiteHistory, request: &Request, allowed: bool) -> Response {
+    let call: Call = match request.json() {
+        Ok(call) => call,
+        Err(e) if allowed => {
+            return Response::json(200, &json!({ "result": null, "error": e.to_string() }))
+        }
+        Err(_) => return Response::error(403, "origin not in cors_origin_list"),
+    };
+    let result = match call.action.as_str() {
+        "requestPermission" => Ok(request_permission(allowed)),
+        _ if !allowed => return Response::error(403, "origin not in cors_origin_list"),
+        _ => self::call(history, &call).await,
+    };
+    let body = match (call.version.unwrap_or(4) >= 5, result) {
+        (true, Ok(result)) => json!({ "result": result, "error": null }),
+        (true, Err(e)) => json!({ "result": null, "error": e.to_string() }),
+        (false, Ok(result)) => result,
+        (false, Err(e)) => json!({ "error": e.to_string() }),
+    };
+    let mut response = Response::json(200, &body);
+    // the page reads the denial
+    response.allow_origin = request.header("origin").map(str::to_owned);
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn request(body: Value) -> Request {
+        Request {
+            method: "POST".to_owned(),
+            path: "/".to_owned(),
+            query: HashMap::new(),
+            headers: HashMap::from([("origin".to_owned(), "https://example.com".to_owned())]),
+            body: body.to_string().into_bytes(),
+        }
+    }
+
+    fn json_body(response: &Response) -> Value {
+        serde_json::from_slice(&response.body).unwrap()
+    }
+
+    #[tokio::test]
+    async fn permission_follows_the_allow_list() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut history = SQLiteHistory::temp(dir.path()).await;
+        let permission = request(json!({ "action": "requestPermission", "version": 6 }));
+
+        let response = handle(&mut history, &permission, true).await;
+        assert_eq!(json_body(&response)["result"]["permission"], "granted");
+
+        let response = handle(&mut history, &permission, false).await;
+        assert_eq!(response.status, 200);
+        assert_eq!(json_body(&response)["result"]["permission"], "denied");
+        assert_eq!(
+            response.allow_origin.as_deref(),
+            Some("https://example.com")
+        );
+
+        let add = request(json!({
+            "action": "addNote",
+            "version": 6,
+            "params": { "note": { "fields": { "Word": "<b>quay</b>", "Sentence": "down to the quay" }, "tags": ["sea"] } },
+        }));
+        let response = handle(&mut history, &add, false).await;
+        assert_eq!(response.status, 403);
+        assert!(history.stored_word("quay").await.unwrap().is_none());
+
+        let response = handle(&mut history, &add, true).await;
+        assert!(json_body(&response)["result"].is_i64());
+        assert_eq!(history.tags("quay").await.unwrap(), ["sea"]);
+        assert_eq!(
+            history.contexts("quay").await.unwrap()[0].sentence,
+            "down to the quay"
+        );
+    }
+
+    #[tokio::test]
+    async fn bare_result_before_version_5() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut history = SQLiteHistory::temp(dir.path()).await;
+        let response = handle(
+            &mut history,
+            &request(json!({ "action": "deckNames" })),
+            true,
+        )
+        .await;
+        assert_eq!(json_body(&response), json!([DECK]));
+        let response = handle(
+            &mut history,
+            &request(json!({ "action": "sync", "version": 6 })),
+            true,
+        )
+        .await;
+        assert_eq!(json_body(&response)["error"], "unsupported action: sync");
+    }
+}
@@ -0,0 +1,242 @@
+//! Just enough HTTP/1.1 for a local server: one request per connection
+
+use anyhow::anyhow;
+use anyhow::Result;
+use std::collections::HashMap;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+const MAX_HEADER: usize = 64 * 1024;
+const MAX_BODY: usize = 16 * 1024 * 1024;
+
+#[derive(Debug)]
+pub struct Request {
+    pub method: String,
+    /// decoded, without the query
+    pub path: String,
+    pub query: HashMap<String, String>,
+    /// lowercase names
+    pub headers: HashMap<String, String>,
+    pub body: Vec<u8>,
+}
+
+impl Request {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.get(name).map(String::as_str)
+    }
+
+    pub fn json<T: serde::de::DeserializeOwned>(&self) -> Result<T> {
+        Ok(serde_json::from_slice(&self.body)?)
+    }
+}
+
+#[derive(Debug)]
+pub struct Response {
+    pub status: u16,
+    pub content_type: &'static str,
+    pub body: Vec<u8>,
+    /// echoed in `Access-Control-Allow-Origin`, no CORS headers if `None`
+    pub allow_origin: Option<String>,
+}
+
+impl Response {
+    pub fn json(status: u16, value: &serde_json::Value) -> Self {
+        Self {
+            status,
+            content_type: "application/json",
+            body: value.to_string().into_bytes(),
+            allow_origin: None,
+        }
+    }
+
+    pub fn html(body: &str) -> Self {
+        Self {
+            status: 200,
+            content_type: "text/html; charset=utf-8",
+            body: body.as_bytes().to_vec(),
+            allow_origin: None,
+        }
+    }
+
+    pub fn error(status: u16, message: impl std::fmt::Display) -> Self {
+        Self::json(status, &serde_json::json!({ "error": message.to_string() }))
+    }
+
+    pub fn empty(status: u16) -> Self {
+        Self {
+            status,
+            content_type: "text/plain",
+            body: Vec::new(),
+            allow_origin: None,
+        }
+    }
+}
+
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        204 => "No Content",
+        400 => "Bad Request",
+        403 => "Forbidden",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        _ => "Internal Server Error",
+    }
+}
+
+fn decode(s: &str) -> String {
+    urlencoding::decode(s)
+        .map(|s| s.into_owned())
+        .unwrap_or_else(|_| s.to_owned())
+}
+
+/// `+` is a space in the query only
+fn decode_query(s: &str) -> String {
+    decode(&s.replace('+', " "))
+}
+
+fn parse_query(query: &str) -> HashMap<String, String> {
+    query
+        .split('&')
+        .filter(|s| !s.is_empty())
+        .map(|pair| match pair.split_once('=') {
+            Some((k, v)) => (decode_query(k), decode_query(v)),
+            None => (decode_query(pair), String::new()),
+        })
+        .collect()
+}
+
+pub async fn read_request(stream: &mut TcpStream) -> Result<Request> {
+    let mut buf = Vec::new();
+    let mut chunk = [0; 4096];
+    let header_end = loop {
+        if let Some(i) = buf.windows(4).position(|w| w == b"\r\n\r\n") {
+            break i;
+        }
+        if buf.len() > MAX_HEADER {
+            return Err(anyhow!("header too large"));
+        }
+        let n = stream.read(&mut chunk).await?;
+        if n == 0 {
+            return Err(anyhow!("connection closed"));
+        }
+        buf.extend_from_slice(&chunk[..n]);
+    };
+
+    let head = std::str::from_utf8(&buf[..header_end])?;
+    let mut lines = head.split("\r\n");
+    let mut request_line = lines.next().unwrap_or_default().split(' ');
+    let method = request_line.next().unwrap_or_default().to_owned();
+    let target = request_line.next().unwrap_or("/");
+
+    let headers: HashMap<String, String> = lines
+        .flat_map(|line| line.split_once(':'))
+        .map(|(k, v)| (k.trim().to_lowercase(), v.trim().to_owned()))
+        .collect();
+    let content_length: usize = headers
+        .get("content-length")
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(0);
+    if content_length > MAX_BODY {
+        return Err(anyhow!("body too large"));
+    }
+
+    let mut body = buf[header_end + 4..].to_vec();
+    while body.len() < content_length {
+        let n = stream.read(&mut chunk).await?;
+        if n == 0 {
+            break;
+        }
+        body.extend_from_slice(&chunk[..n]);
+    }
+    body.truncate(content_length);
+
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    Ok(Request {
+        method,
+        path: decode(path),
+        query: parse_query(query),
+        headers,
+        body,
+    })
+}
+
+pub async fn write_response(stream: &mut TcpStream, response: &Response) -> Result<()> {
+    let cors = match &response.allow_origin {
+        Some(origin) => format!("Access-Control-Allow-Origin: {origin}\r\nVary: Origin\r\nAccess-Control-Allow-Methods: GET, POST, DELETE, OPTIONS\r\nAccess-Control-Allow-Headers: Content-Type\r\n"),
+        None => String::new(),
+    };
+    let head = format!(
+        "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\n{cors}Connection: close\r\n\r\n",
+        response.status,
+        reason(response.status),
+        response.content_type,
+        response.body.len(),
+    );
+    stream.write_all(head.as_bytes()).await?;
+    stream.write_all(&response.body).await?;
+    stream.flush().await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::net::TcpListener;
+
+    #[test]
+    fn query() {
+        let query = parse_query("word=ice+cream&x=%2B1&flag&&=empty");
+        assert_eq!(query["word"], "ice cream");
+        assert_eq!(query["x"], "+1");
+        assert_eq!(query["flag"], "");
+        assert_eq!(query[""], "empty");
+        // `+` is kept in the path
+        assert_eq!(decode("/api/words/a+b%20c"), "/api/words/a+b c");
+    }
+
+    /// `parts` written one by one
+    async fn request(parts: &[&[u8]]) -> Result<Request> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let mut client = TcpStream::connect(listener.local_addr()?).await?;
+        let (mut server, _) = listener.accept().await?;
+        for part in parts {
+            client.write_all(part).await?;
+            client.flush().await?;
+        }
+        drop(client);
+        read_request(&mut server).await
+    }
+
+    #[tokio::test]
+    async fn split_request() {
+        let request = request(&[
+            b"POST /api/rate?x=1 HTTP/1.1\r\nContent-Type: application/json\r\nCONTENT-LENGTH: 7\r\nOrigin: http://localhost\r",
+            b"\n\r\n{\"a\":",
+            b"1}trailing",
+        ])
+        .await
+        .unwrap();
+        assert_eq!(request.method, "POST");
+        assert_eq!(request.path, "/api/rate");
+        assert_eq!(request.query["x"], "1");
+        assert_eq!(request.header("origin"), Some("http://localhost"));
+        assert_eq!(request.body, b"{\"a\":1}");
+        assert_eq!(request.json::<serde_json::Value>().unwrap()["a"], 1);
+    }
+
+    #[tokio::test]
+    async fn bad_requests() {
+        assert!(request(&[b"GET / HTTP/1.1\r\n"]).await.is_err());
+        let too_large = format!(
+            "POST / HTTP/1.1\r\nContent-Length: {}\r\n\r\n",
+            MAX_BODY + 1
+        );
+        assert!(request(&[too_large.as_bytes()]).await.is_err());
+        // a short body is read as is
+        let request = request(&[b"POST / HTTP/1.1\r\nContent-Length: 10\r\n\r\nabc"])
+            .await
+            .unwrap();
+        assert_eq!(request.body, b"abc");
+    }
+}
@@ -0,0 +1,499 @@
+//! Local HTTP/JSON API of the review engine, used by `serve`
+//!
+//! ```text
+//! GET    /                   review page, see `web`
+//! GET    /api/next           next due word
+//! POST   /api/words          {"word": "..", "sentence": "..", "book": ".."}
+//! GET    /api/words/<word>   card, tags and contexts
+//! DELETE /api/words/<word>
+//! POST   /api/rate           {"word": "..", "rating": "good"} or 1 ~ 4
+//! GET    /api/stats
+//! GET    /api/definition/<word>
+//! POST   /               AnkiConnect, see `ankiconnect`
+//! ```
+//!
+//! Requests are handled one by one: review futures are not `Send`
+
+pub mod ankiconnect;
+pub mod http;
+pub mod web;
+
+use anyhow::anyhow;
+use anyhow::Result;
+use chrono::Utc;
+use rs_fsrs::Rating;
+use serde_json::json;
+use sqlx::Row;
+use std::time::Duration;
+use tokio::net::TcpListener;
+
+use crate::dictionary::Dictionary;
+use crate::dump::Entry;
+use crate::fsrs::sqlite_history::SQLiteHistory;
+use crate::fsrs::{get_card, get_word_ignore_case};
+use crate::import::{julianday, Context};
+use crate::revlog::rating_from_i64;
+use http::{read_request, write_response, Request, Response};
+use web::PAGE;
+
+const READ_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, serde::Deserialize)]
+struct AddWord {
+    word: String,
+    #[serde(default)]
+    sentence: Option<String>,
+    #[serde(default)]
+    book: Option<String>,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct Rate {
+    word: String,
+    rating: serde_json::Value,
+}
+
+pub fn parse_rating(value: &serde_json::Value) -> Result<Rating> {
+    let rating = match value {
+        serde_json::Value::Number(n) => n.as_i64().and_then(rating_from_i64),
+        serde_json::Value::String(s) => match s.to_lowercase().as_str() {
+            "again" => Some(Rating::Again),
+            "hard" => Some(Rating::Hard),
+            "good" => Some(Rating::Good),
+            "easy" => Some(Rating::Easy),
+            s => s.parse().ok().and_then(rating_from_i64),
+        },
+        _ => None,
+    };
+    rating.ok_or_else(|| anyhow!("rating: again, hard, good, easy or 1 ~ 4"))
+}
+
+#[derive(Debug, Default, serde::Serialize)]
+pub struct Stats {
+    pub total: i64,
+    pub due: i64,
+    pub new: i64,
+    pub learning: i64,
+    pub review: i64,
+    pub relearning: i64,
+    pub reviewed_today: i64,
+}
+
+impl SQLiteHistory {
+    pub async fn stats(&self) -> Result<Stats> {
+        let mut stats = Stats::default();
+        for sqlite_row in sqlx::query("SELECT state, COUNT(*) FROM fsrs GROUP BY state;")
+            .fetch_all(&self.conn)
+            .await?
+        {
+            let state: &str = sqlite_row.get(0);
+            let count: i64 = sqlite_row.get(1);
+            stats.total += count;
+            match state.trim_matches('"') {
+                "New" => stats.new = count,
+                "Learning" => stats.learning = count,
+                "Review" => stats.review = count,
+                "Relearning" => stats.relearning = count,
+                _ => {}
+            }
+        }
+        stats.due = sqlx::query("SELECT COUNT(*) FROM fsrs WHERE timediff('now', substr(due, 2, length(due) - 2)) LIKE '+%';")
+            .fetch_one(&self.conn)
+            .await?
+            .get(0);
+
+        let now = Utc::now().timestamp_millis();
+        let today = now - now.rem_euclid(86400 * 1000);
+        stats.reviewed_today = sqlx::query("SELECT COUNT(*) FROM review WHERE timestamp >= $1;")
+            .bind(today)
+            .fetch_one(&self.conn)
+            .await?
+            .get(0);
+        Ok(stats)
+    }
+
+    /// Like `add_word`: an existing card is moved to the end
+    pub async fn add_word(&mut self, word: &str) -> Result<(String, bool)> {
+        match get_word_ignore_case(&self.conn, word).await {
+            Ok(word) => {
+                let card = get_card(&self.conn, &word).await?;
+                self.insert_or_replace(&word, card).await?;
+                Ok((word, false))
+            }
+            Err(_) => self.insert_if_absent(word).await,
+        }
+    }
+
+    pub async fn entry(&self, word: &str) -> Result<Option<Entry>> {
+        let Ok(word) = get_word_ignore_case(&self.conn, word).await else {
+            return Ok(None);
+        };
+        let mut entry = Entry::new(word.clone(), get_card(&self.conn, &word).await?);
+        entry.tags = self.tags(&word).await?;
+        entry.contexts = self.contexts(&word).await?;
+        Ok(Some(entry))
+    }
+}
+
+async fn add(history: &mut SQLiteHistory, body: AddWord) -> Result<Response> {
+    let word = body.word.trim();
+    if word.is_empty() {
+        return Ok(Response::error(400, "empty word"));
+    }
+    let (word, new) = history.add_word(word).await?;
+    if let Some(sentence) = body.sentence.filter(|s| !s.trim().is_empty()) {
+        history
+            .insert_context(
+                &word,
+                &Context {
+                    sentence,
+                    book: body.book,
+                    source: "api".to_owned(),
+                    timestamp: Some(julianday(Utc::now().timestamp() as f64)),
+                },
+            )
+            .await?;
+    }
+    Ok(Response::json(200, &json!({ "word": word, "new": new })))
+}
+
+async fn api(
+    history: &mut SQLiteHistory,
+    dictionaries: &mut [Dictionary],
+    request: &Request,
+) -> Result<Response> {
+    let method = request.method.as_str();
+    let path = request.path.as_str();
+
+    if let Some(word) = path.strip_prefix("/api/definition/") {
+        return match web::definition(dictionaries, word).await? {
+            Some(html) => Ok(Response::json(200, &json!({ "word": word, "html": html }))),
+            None => Ok(Response::error(404, format!("{word} not found"))),
+        };
+    }
+
+    if let Some(word) = path.strip_prefix("/api/words/") {
+        return match method {
+            "GET" => match history.entry(word).await? {
+                Some(entry) => Ok(Response::json(200, &serde_json::to_value(entry)?)),
+                None => Ok(Response::error(404, format!("{word} not found"))),
+            },
+            "DELETE" => match get_word_ignore_case(&history.conn, word).await {
+                Ok(word) => {
+                    history.delete(&word).await?;
+                    Ok(Response::json(200, &json!({ "word": word })))
+                }
+                Err(_) => Ok(Response::error(404, format!("{word} not found"))),
+            },
+            _ => Ok(Response::error(405, "GET or DELETE")),
+        };
+    }
+
+    match (method, path) {
+        ("GET", "/") => Ok(Response::html(PAGE)),
+        ("GET", "/api/next") => match history.next_to_review().await {
+            Ok(word) => Ok(Response::json(200, &json!({ "word": word }))),
+            Err(e) => Ok(Response::error(404, format!("all reviewed: {e}"))),
+        },
+        ("POST", "/api/words") => match request.json() {
+            Ok(body) => add(history, body).await,
+            Err(e) => Ok(Response::error(400, e)),
+        },
+        ("POST", "/api/rate") => {
+            let rate: Rate = match request.json() {
+                Ok(rate) => rate,
+                Err(e) => return Ok(Response::error(400, e)),
+            };
+            let rating = match parse_rating(&rate.rating) {
+                Ok(rating) => rating,
+                Err(e) => return Ok(Response::error(400, e)),
+            };
+            let Ok(word) = get_word_ignore_case(&history.conn, &rate.word).await else {
+                return Ok(Response::error(404, format!("{} not found", rate.word)));
+            };
+            history.update(&word, rating).await?;
+            let entry = history.entry(&word).await?;
+            Ok(Response::json(200, &serde_json::to_value(entry)?))
+        }
+        ("GET", "/api/stats") => Ok(Response::json(
+            200,
+            &serde_json::to_value(history.stats().await?)?,
+        )),
+        _ => Ok(Response::error(404, format!("{method} {path}"))),
+    }
+}
+
+/// A loopback name or `addr`, the bound address
+/// Any other name resolving to this server is DNS rebinding, `Origin` and `Host` then agree
+fn is_local_host(host: &str, addr: &str) -> bool {
+    if host == addr {
+        return true;
+    }
+    // `[::1]:8765`, not the colons of `[::1]`
+    let name = match host.rsplit_once(':') {
+        Some((name, port)) if !port.contains(']') => name,
+        _ => host,
+    };
+    matches!(
+        name.to_ascii_lowercase().as_str(),
+        "localhost" | "127.0.0.1" | "[::1]"
+    )
+}
+
+/// Requests without `Origin` come from scripts and goldendict-ng, not from a web page
+/// The review page is served by the `Host` itself, checked by `is_local_host`
+fn is_allowed(origins: &[String], request: &Request) -> bool {
+    let Some(origin) = request.header("origin") else {
+        return true;
+    };
+    origins.iter().any(|x| x == "*" || x == origin)
+        || request
+            .header("host")
+            .is_some_and(|host| origin == format!("http://{host}"))
+}
+
+/// `origins`: see `Config::cors_origin_list`
+/// `addr`: the bound address
+pub async fn handle(
+    history: &mut SQLiteHistory,
+    dictionaries: &mut [Dictionary],
+    origins: &[String],
+    addr: &str,
+    request: &Request,
+) -> Response {
+    if !request
+        .header("host")
+        .is_some_and(|host| is_local_host(host, addr))
+    {
+        return Response::error(403, "Host is not localhost");
+    }
+    let allowed = is_allowed(origins, request);
+    let mut response = if request.method == "POST" && request.path == "/" {
+        // requestPermission answers any origin
+        ankiconnect::handle(history, request, allowed).await
+    } else if !allowed {
+        Response::error(403, "origin not in cors_origin_list")
+    } else if request.method == "OPTIONS" {
+        Response::empty(204)
+    } else {
+        match api(history, dictionaries, request).await {
+            Ok(response) => response,
+            Err(e) => Response::error(500, e),
+        }
+    };
+    if allowed {
+        response.allow_origin = request.header("origin").map(str::to_owned);
+    }
+    response
+}
+
+pub async fn serve(
+    history: &mut SQLiteHistory,
+    dictionaries: &mut [Dictionary],
+    origins: &[String],
+    addr: &str,
+) -> Result<()> {
+    let listener = TcpListener::bind(addr).await?;
+    accept(&listener, history, dictionaries, origins).await
+}
+
+async fn accept(
+    listener: &TcpListener,
+    history: &mut SQLiteHistory,
+    dictionaries: &mut [Dictionary],
+    origins: &[String],
+) -> Result<()> {
+    let addr = listener.local_addr()?.to_string();
+    loop {
+        let (mut stream, _) = listener.accept().await?;
+        let request = match tokio::time::timeout(READ_TIMEOUT, read_request(&mut stream)).await {
+            Ok(Ok(request)) => request,
+            Ok(Err(e)) => {
+                let _ = write_response(&mut stream, &Response::error(400, e)).await;
+                continue;
+            }
+            Err(_) => continue,
+        };
+        let response = handle(history, dictionaries, origins, &addr, &request).await;
+        if let Err(e) = write_response(&mut stream, &response).await {
+            eprintln!("{e}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpStream;
+
+    /// The raw response to `head`, sent with `body`
+    /// `HOST` stands for the address of the server, also the `Host` unless `head` has one
+    async fn send(
+        history: &mut SQLiteHistory,
+        origins: &[String],
+        head: &str,
+        body: &str,
+    ) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let client = async {
+            let mut stream = TcpStream::connect(addr).await.unwrap();
+            let host = if head.contains("Host: ") {
+                String::new()
+            } else {
+                format!("\r\nHost: {addr}")
+            };
+            let request = format!(
+                "{head}{host}\r\nContent-Length: {}\r\n\r\n{body}",
+                body.len()
+            )
+            .replace("HOST", &addr.to_string());
+            stream.write_all(request.as_bytes()).await.unwrap();
+            let mut response = String::new();
+            stream.read_to_string(&mut response).await.unwrap();
+            response.replace(&addr.to_string(), "HOST")
+        };
+        tokio::select! {
+            result = accept(&listener, history, &mut [], origins) => panic!("{result:?}"),
+            response = client => response,
+        }
+    }
+
+    fn allow_origin(response: &str) -> Option<&str> {
+        response
+            .lines()
+            .find_map(|line| line.strip_prefix("Access-Control-Allow-Origin: "))
+    }
+
+    #[tokio::test]
+    async fn cors_allow_list() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut history = SQLiteHistory::temp(dir.path()).await;
+        let origins = ["http://localhost".to_owned()];
+
+        // curl and goldendict-ng send no Origin
+        let response = send(&mut history, &origins, "GET /api/stats HTTP/1.1", "").await;
+        assert!(response.starts_with("HTTP/1.1 200 OK"), "{response}");
+        assert_eq!(allow_origin(&response), None);
+
+        let preflight = "OPTIONS /api/words HTTP/1.1\r\nAccess-Control-Request-Method: POST";
+        let response = send(
+            &mut history,
+            &origins,
+            &format!("{preflight}\r\nOrigin: http://localhost"),
+            "",
+        )
+        .await;
+        assert!(
+            response.starts_with("HTTP/1.1 204 No Content"),
+            "{response}"
+        );
+        assert_eq!(allow_origin(&response), Some("http://localhost"));
+        assert!(response.contains("Vary: Origin"));
+
+        let response = send(
+            &mut history,
+            &origins,
+            &format!("{preflight}\r\nOrigin: https://example.com"),
+            "",
+        )
+        .await;
+        assert!(response.starts_with("HTTP/1.1 403 Forbidden"), "{response}");
+        assert_eq!(allow_origin(&response), None);
+
+        // a simple request is not preflighted: refused, not only unreadable
+        let add = "POST /api/words HTTP/1.1\r\nContent-Type: text/plain";
+        let body = r#"{"word": "quay"}"#;
+        let response = send(
+            &mut history,
+            &origins,
+            &format!("{add}\r\nOrigin: https://example.com"),
+            body,
+        )
+        .await;
+        assert!(response.starts_with("HTTP/1.1 403"), "{response}");
+        assert!(history.stored_word("quay").await.unwrap().is_none());
+
+        // the review page calls its own host
+        let response = send(
+            &mut history,
+            &origins,
+            &format!("{add}\r\nOrigin: http://HOST"),
+            body,
+        )
+        .await;
+        assert!(response.starts_with("HTTP/1.1 200"), "{response}");
+        assert!(history.stored_word("quay").await.unwrap().is_some());
+
+        let response = send(
+            &mut history,
+            &["*".to_owned()],
+            "GET /api/stats HTTP/1.1\r\nOrigin: https://example.com",
+            "",
+        )
+        .await;
+        assert!(response.starts_with("HTTP/1.1 200"), "{response}");
+        assert_eq!(allow_origin(&response), Some("https://example.com"));
+    }
+
+    #[tokio::test]
+    async fn dns_rebinding() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut history = SQLiteHistory::temp(dir.path()).await;
+        history.insert_if_absent("quay").await.unwrap();
+        let origins = ["*".to_owned()];
+
+        // evil.example resolved to 127.0.0.1: its page is same-origin to the browser
+        let delete = "DELETE /api/words/quay HTTP/1.1\r\nHost: evil.example:8765";
+        let response = send(
+            &mut history,
+            &origins,
+            &format!("{delete}\r\nOrigin: http://evil.example:8765"),
+            "",
+        )
+        .await;
+        assert!(response.starts_with("HTTP/1.1 403"), "{response}");
+        assert_eq!(allow_origin(&response), None);
+        let response = send(&mut history, &origins, delete, "").await;
+        assert!(response.starts_with("HTTP/1.1 403"), "{response}");
+        assert!(history.stored_word("quay").await.unwrap().is_some());
+
+        for host in [
+            "localhost:8765",
+            "LOCALHOST",
+            "127.0.0.1",
+            "[::1]:8765",
+            "[::1]",
+        ] {
+            assert!(is_local_host(host, "0.0.0.0:8765"), "{host}");
+        }
+        for host in ["evil.example", "localhost.evil.example:8765", "[::2]", ""] {
+            assert!(!is_local_host(host, "0.0.0.0:8765"), "{host}");
+        }
+        assert!(is_local_host("192.168.1.2:8765", "192.168.1.2:8765"));
+    }
+
+    #[tokio::test]
+    async fn request_line_and_query() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut history = SQLiteHistory::temp(dir.path()).await;
+        let origins = ["http://localhost".to_owned()];
+        history.insert_if_absent("ice cream").await.unwrap();
+
+        let response = send(
+            &mut history,
+            &origins,
+            "GET /api/words/ice%20cream?x=a+b HTTP/1.1",
+            "",
+        )
+        .await;
+        assert!(response.starts_with("HTTP/1.1 200"), "{response}");
+        assert!(response.contains(r#""word":"ice cream""#), "{response}");
+
+        let response = send(&mut history, &origins, "PUT /api/stats HTTP/1.1", "").await;
+        assert!(response.starts_with("HTTP/1.1 404"), "{response}");
+        assert_eq!(parse_rating(&json!("3")).unwrap(), Rating::Good);
+        assert!(parse_rating(&json!(5)).is_err());
+    }
+}
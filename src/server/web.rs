@@ -0,0 +1,53 @@
+//! Review page of `serve`
+//!
+//! The definition comes from ECDICT, if imported, and `dictionaries` of the config
+
+use anyhow::Result;
+
+use crate::dictionary::{escape, Dictionary};
+
+pub const PAGE: &str = include_str!("review.html");
+
+/// HTML sections of every dictionary having the word
+pub async fn definition(dictionaries: &mut [Dictionary], word: &str) -> Result<Option<String>> {
+    let mut html = String::new();
+    for dictionary in dictionaries {
+        if let Some(record) = dictionary.lookup(word).await? {
+            html.push_str(&format!(
+                "<section><h2>{}</h2>{record}</section>",
+                escape(&dictionary.name)
+            ));
+        }
+    }
+    Ok((!html.is_empty()).then_some(html))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    fn tiny() -> Dictionary {
+        Dictionary::open(&Path::new(env!("CARGO_MANIFEST_DIR")).join("testdata/tiny.mdx")).unwrap()
+    }
+
+    #[tokio::test]
+    async fn one_section_per_dictionary() {
+        let mut renamed = tiny();
+        renamed.name = "<tiny & co>".to_owned();
+        let mut dictionaries = [tiny(), renamed];
+        assert_eq!(
+            definition(&mut dictionaries, "PEAR").await.unwrap().as_deref(),
+            Some("<section><h2>tiny</h2><p>pear</p></section><section><h2>&lt;tiny &amp; co&gt;</h2><p>pear</p></section>")
+        );
+        assert_eq!(definition(&mut dictionaries, "banana").await.unwrap(), None);
+        assert_eq!(definition(&mut [], "pear").await.unwrap(), None);
+    }
+
+    #[test]
+    fn page_calls_the_api() {
+        for path in ["/api/next", "/api/rate", "/api/definition/"] {
+            assert!(PAGE.contains(path), "{path}");
+        }
+    }
+}
@@ -0,0 +1,237 @@
+//! Extend by spelling: the default strategy
+//!
+//! All words are indexed once per session
+//! Edit distances use a BK-tree, Jaro-Winkler is not a metric and scans every word
+
+use anyhow::Result;
+use sqlx::Row;
+use std::collections::HashSet;
+
+use crate::fsrs::sqlite_history::SQLiteHistory;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum Metric {
+    #[default]
+    Levenshtein,
+    /// transposition counts as one edit: form/from
+    Damerau,
+    JaroWinkler,
+}
+
+impl Metric {
+    /// `None` for Jaro-Winkler, a similarity rather than a distance
+    fn edit_distance(self) -> Option<fn(&str, &str) -> usize> {
+        match self {
+            Metric::Levenshtein => Some(strsim::levenshtein),
+            Metric::Damerau => Some(strsim::damerau_levenshtein),
+            Metric::JaroWinkler => None,
+        }
+    }
+}
+
+struct Node {
+    word: String,
+    /// (distance to this node, index of the child)
+    children: Vec<(usize, usize)>,
+}
+
+/// <https://en.wikipedia.org/wiki/BK-tree>
+pub struct BkTree {
+    nodes: Vec<Node>,
+    distance: fn(&str, &str) -> usize,
+}
+
+impl BkTree {
+    pub fn new(distance: fn(&str, &str) -> usize) -> Self {
+        Self {
+            nodes: Vec::new(),
+            distance,
+        }
+    }
+
+    pub fn insert(&mut self, word: String) {
+        if self.nodes.is_empty() {
+            self.nodes.push(Node {
+                word,
+                children: Vec::new(),
+            });
+            return;
+        }
+
+        let mut current = 0;
+        loop {
+            let d = (self.distance)(&word, &self.nodes[current].word);
+            if d == 0 {
+                return;
+            }
+            match self.nodes[current].children.iter().find(|(cd, _)| *cd == d) {
+                Some((_, child)) => current = *child,
+                None => {
+                    let child = self.nodes.len();
+                    self.nodes.push(Node {
+                        word,
+                        children: Vec::new(),
+                    });
+                    self.nodes[current].children.push((d, child));
+                    return;
+                }
+            }
+        }
+    }
+
+    /// Words within `max_distance`, with their distance
+    pub fn find(&self, word: &str, max_distance: usize) -> Vec<(usize, &str)> {
+        let mut res = Vec::new();
+        if self.nodes.is_empty() {
+            return res;
+        }
+
+        let mut stack = vec![0];
+        while let Some(i) = stack.pop() {
+            let node = &self.nodes[i];
+            let d = (self.distance)(word, &node.word);
+            if d <= max_distance {
+                res.push((d, node.word.as_str()));
+            }
+            // triangle inequality
+            let low = d.saturating_sub(max_distance);
+            let high = d + max_distance;
+            stack.extend(
+                node.children
+                    .iter()
+                    .filter(|(cd, _)| (low..=high).contains(cd))
+                    .map(|(_, child)| *child),
+            );
+        }
+        res
+    }
+}
+
+pub enum SpellingIndex {
+    BkTree(BkTree),
+    Words(Vec<String>),
+}
+
+impl SpellingIndex {
+    pub fn new(metric: Metric, words: Vec<String>) -> Self {
+        match metric.edit_distance() {
+            None => SpellingIndex::Words(words),
+            Some(distance) => {
+                let mut tree = BkTree::new(distance);
+                for word in words {
+                    tree.insert(word);
+                }
+                SpellingIndex::BkTree(tree)
+            }
+        }
+    }
+
+    /// Similar words, the most similar first
+    fn similar(&self, word: &str, max_distance: usize, min_similarity: f64) -> Vec<String> {
+        match self {
+            SpellingIndex::BkTree(tree) => {
+                let mut v = tree.find(word, max_distance);
+                v.sort_by_key(|(d, _)| *d);
+                v.into_iter().map(|(_, w)| w.to_owned()).collect()
+            }
+            SpellingIndex::Words(words) => {
+                let mut v: Vec<(f64, &String)> = words
+                    .iter()
+                    .map(|w| (strsim::jaro_winkler(word, w), w))
+                    .filter(|(similarity, _)| *similarity >= min_similarity)
+                    .collect();
+                v.sort_by(|a, b| b.0.total_cmp(&a.0));
+                v.into_iter().map(|(_, w)| w.clone()).collect()
+            }
+        }
+    }
+}
+
+impl SQLiteHistory {
+    pub(crate) async fn leven(&mut self, word: &str) -> Result<()> {
+        if self.spelling_index.is_none() {
+            let words = self.all_words().await?;
+            self.spelling_index = Some(SpellingIndex::new(self.metric, words));
+        }
+
+        // reviewed in this session
+        let reviewed: HashSet<String> = sqlx::query("SELECT word FROM fsrs WHERE session_id = $1;")
+            .bind(self.session_id)
+            .fetch_all(&self.conn)
+            .await?
+            .into_iter()
+            .map(|sqlite_row| sqlite_row.get(0))
+            .collect();
+
+        let similar_words = self
+            .spelling_index
+            .as_ref()
+            .unwrap()
+            .similar(word, self.max_distance, self.min_similarity)
+            .into_iter()
+            .filter(|a| a != word && !reviewed.contains(a));
+
+        self.queue.extend(similar_words);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn words() -> Vec<String> {
+        [
+            "form", "from", "farm", "firm", "fort", "forum", "format", "storm", "worm", "apple",
+        ]
+        .map(String::from)
+        .to_vec()
+    }
+
+    #[test]
+    fn bk_tree_matches_brute_force() {
+        for metric in [Metric::Levenshtein, Metric::Damerau] {
+            let distance = metric.edit_distance().unwrap();
+            let words = words();
+            let mut tree = BkTree::new(distance);
+            for word in &words {
+                tree.insert(word.clone());
+            }
+            // duplicates are ignored
+            tree.insert("form".to_owned());
+            assert_eq!(tree.nodes.len(), words.len());
+
+            for query in ["form", "fprm", "aple", "xyz"] {
+                for max_distance in 0..4 {
+                    let mut found = tree.find(query, max_distance);
+                    found.sort();
+                    let mut expected: Vec<(usize, &str)> = words
+                        .iter()
+                        .map(|w| (distance(query, w), w.as_str()))
+                        .filter(|(d, _)| *d <= max_distance)
+                        .collect();
+                    expected.sort();
+                    assert_eq!(found, expected, "{metric:?} {query} {max_distance}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn transposition_is_one_edit() {
+        let index = SpellingIndex::new(Metric::Damerau, words());
+        assert_eq!(index.similar("form", 1, 0.0)[..2], ["form", "from"]);
+        let index = SpellingIndex::new(Metric::Levenshtein, words());
+        assert!(!index.similar("form", 1, 0.0).contains(&"from".to_owned()));
+    }
+
+    #[test]
+    fn jaro_winkler_by_similarity() {
+        let index = SpellingIndex::new(Metric::JaroWinkler, words());
+        assert!(matches!(index, SpellingIndex::Words(_)));
+        let v = index.similar("formt", 0, 0.9);
+        assert_eq!(v[0], "format");
+        assert!(v.iter().all(|w| strsim::jaro_winkler("formt", w) >= 0.9));
+        assert!(!v.contains(&"apple".to_owned()));
+    }
+}
@@ -0,0 +1,552 @@
+//! StarDict (.ifo, .idx(.gz), .dict(.dz)) reader
+//!
+//! Format: <https://github.com/huzheng001/stardict-3/blob/master/dict/doc/StarDictFileFormat>
+//!
+//! Headwords are found by binary search in the .idx, sorted ignoring ASCII case
+//! Other letters differ in case byte by byte: non-ASCII words fall back to a linear scan
+//! A .dict.dz is read chunk by chunk if it's a dictzip, otherwise decompressed at once
+
+use anyhow::anyhow;
+use anyhow::Context;
+use anyhow::Result;
+use miniz_oxide::inflate::core::inflate_flags::{
+    TINFL_FLAG_HAS_MORE_INPUT, TINFL_FLAG_USING_NON_WRAPPING_OUTPUT_BUF,
+};
+use miniz_oxide::inflate::core::{decompress, DecompressorOxide};
+use miniz_oxide::inflate::decompress_to_vec;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::fs;
+use std::fs::File;
+use std::io::Read;
+use std::io::Seek;
+use std::io::SeekFrom;
+use std::path::{Path, PathBuf};
+
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+const FHCRC: u8 = 0x02;
+const FEXTRA: u8 = 0x04;
+const FNAME: u8 = 0x08;
+const FCOMMENT: u8 = 0x10;
+/// enough for the longest gzip extra field
+const MAX_GZIP_HEADER: u64 = 128 * 1024;
+
+/// (length of the header, extra field)
+fn gzip_header(bytes: &[u8]) -> Result<(usize, &[u8])> {
+    let corrupted = || anyhow!("corrupted gzip header");
+    if bytes.len() < 10 || bytes[..2] != GZIP_MAGIC || bytes[2] != 8 {
+        return Err(anyhow!("not a gzip file"));
+    }
+    let flags = bytes[3];
+    let mut pos = 10;
+    let mut extra: &[u8] = &[];
+    if flags & FEXTRA != 0 {
+        let len = u16::from_le_bytes(bytes.get(pos..pos + 2).ok_or_else(corrupted)?.try_into()?);
+        extra = bytes
+            .get(pos + 2..pos + 2 + len as usize)
+            .ok_or_else(corrupted)?;
+        pos += 2 + len as usize;
+    }
+    for flag in [FNAME, FCOMMENT] {
+        if flags & flag != 0 {
+            let len = bytes
+                .get(pos..)
+                .ok_or_else(corrupted)?
+                .iter()
+                .position(|&b| b == 0)
+                .ok_or_else(corrupted)?;
+            pos += len + 1;
+        }
+    }
+    if flags & FHCRC != 0 {
+        bytes.get(pos..pos + 2).ok_or_else(corrupted)?;
+        pos += 2;
+    }
+    Ok((pos, extra))
+}
+
+/// The whole member, the trailer ignored
+fn gunzip(bytes: &[u8]) -> Result<Vec<u8>> {
+    let (header, _) = gzip_header(bytes)?;
+    let deflated = bytes
+        .get(header..)
+        .ok_or_else(|| anyhow!("corrupted gzip header"))?;
+    decompress_to_vec(deflated).map_err(|e| anyhow!("{e}"))
+}
+
+/// (chunk length, chunk sizes) of the `RA` subfield
+fn dictzip_chunks(extra: &[u8]) -> Option<(usize, Vec<usize>)> {
+    let mut rest = extra;
+    while rest.len() >= 4 {
+        let len = u16::from_le_bytes([rest[2], rest[3]]) as usize;
+        let data = rest.get(4..4 + len)?;
+        if &rest[..2] == b"RA" && data.len() >= 6 {
+            let chunk_len = u16::from_le_bytes([data[2], data[3]]) as usize;
+            let count = u16::from_le_bytes([data[4], data[5]]) as usize;
+            let sizes = data[6..]
+                .chunks_exact(2)
+                .take(count)
+                .map(|b| u16::from_le_bytes([b[0], b[1]]) as usize)
+                .collect::<Vec<_>>();
+            return (sizes.len() == count).then_some((chunk_len, sizes));
+        }
+        rest = &rest[4 + len..];
+    }
+    None
+}
+
+struct Dictzip {
+    file: File,
+    chunk_len: usize,
+    /// (offset in the file, compressed size)
+    chunks: Vec<(u64, usize)>,
+    /// last decompressed chunk
+    cache: Option<(usize, Vec<u8>)>,
+}
+
+impl Dictzip {
+    /// Chunks are flushed fully: each one inflates alone
+    fn chunk(&mut self, i: usize) -> Result<&[u8]> {
+        if self.cache.as_ref().is_none_or(|(cached, _)| *cached != i) {
+            let (offset, size) = *self.chunks.get(i).ok_or_else(|| anyhow!("corrupted .dz"))?;
+            let mut compressed = vec![0; size];
+            self.file.seek(SeekFrom::Start(offset))?;
+            self.file.read_exact(&mut compressed)?;
+
+            let mut out = vec![0; self.chunk_len];
+            let (status, _, len) = decompress(
+                &mut DecompressorOxide::new(),
+                &compressed,
+                &mut out,
+                0,
+                TINFL_FLAG_USING_NON_WRAPPING_OUTPUT_BUF | TINFL_FLAG_HAS_MORE_INPUT,
+            );
+            if (status as i8) < 0 {
+                return Err(anyhow!("corrupted .dz: {status:?}"));
+            }
+            out.truncate(len);
+            self.cache = Some((i, out));
+        }
+        Ok(&self.cache.as_ref().unwrap().1)
+    }
+
+    fn read(&mut self, offset: u64, size: u32) -> Result<Vec<u8>> {
+        let mut buf = Vec::with_capacity(size as usize);
+        let mut pos = offset as usize;
+        let end = pos + size as usize;
+        while pos < end {
+            let chunk_len = self.chunk_len;
+            let chunk = self.chunk(pos / chunk_len)?;
+            let start = pos % chunk_len;
+            let take = (end - pos).min(chunk.len().saturating_sub(start));
+            if take == 0 {
+                return Err(anyhow!("corrupted .dz"));
+            }
+            buf.extend_from_slice(&chunk[start..start + take]);
+            pos += take;
+        }
+        Ok(buf)
+    }
+}
+
+enum Dict {
+    File(File),
+    Dictzip(Dictzip),
+    /// gzip without the chunk table
+    Memory(Vec<u8>),
+}
+
+impl Dict {
+    fn open(path: &Path) -> Result<Self> {
+        let mut file = File::open(path).with_context(|| format!("fail to open {:?}", path))?;
+        if path.extension().and_then(|x| x.to_str()) != Some("dz") {
+            return Ok(Self::File(file));
+        }
+
+        let mut head = Vec::new();
+        (&mut file).take(MAX_GZIP_HEADER).read_to_end(&mut head)?;
+        let (header, extra) = gzip_header(&head)?;
+        let Some((chunk_len, sizes)) = dictzip_chunks(extra) else {
+            return Ok(Self::Memory(gunzip(&fs::read(path)?)?));
+        };
+        let mut offset = header as u64;
+        let chunks = sizes
+            .into_iter()
+            .map(|size| {
+                let chunk = (offset, size);
+                offset += size as u64;
+                chunk
+            })
+            .collect();
+        Ok(Self::Dictzip(Dictzip {
+            file,
+            chunk_len,
+            chunks,
+            cache: None,
+        }))
+    }
+
+    fn read(&mut self, offset: u64, size: u32) -> Result<Vec<u8>> {
+        match self {
+            Dict::File(file) => {
+                let mut buf = vec![0; size as usize];
+                file.seek(SeekFrom::Start(offset))?;
+                file.read_exact(&mut buf)?;
+                Ok(buf)
+            }
+            Dict::Dictzip(dictzip) => dictzip.read(offset, size),
+            Dict::Memory(bytes) => bytes
+                .get(offset as usize..offset as usize + size as usize)
+                .map(<[u8]>::to_vec)
+                .ok_or_else(|| anyhow!("corrupted .dict")),
+        }
+    }
+}
+
+pub struct StarDict {
+    pub name: String,
+    /// `sametypesequence` of the .ifo, empty if every field carries its type
+    types: String,
+    idx: Vec<u8>,
+    /// 4 or 8 bytes
+    offset_len: usize,
+    /// start of every entry in `idx`, in file order
+    entries: Vec<usize>,
+    dict: Dict,
+}
+
+/// `key=value` lines after the magic line
+fn parse_ifo(path: &Path) -> Result<HashMap<String, String>> {
+    let s = fs::read_to_string(path).with_context(|| format!("fail to open {:?}", path))?;
+    let mut lines = s.lines();
+    if lines.next().map(str::trim) != Some("StarDict's dict ifo file") {
+        return Err(anyhow!("{:?} is not a StarDict .ifo", path));
+    }
+    Ok(lines
+        .flat_map(|line| line.split_once('='))
+        .map(|(k, v)| (k.trim().to_owned(), v.trim().to_owned()))
+        .collect())
+}
+
+/// The first existing path of `stem` + one of `extensions`
+fn sibling(ifo: &Path, extensions: &[&str]) -> Result<PathBuf> {
+    extensions
+        .iter()
+        .map(|extension| ifo.with_extension(extension))
+        .find(|path| path.exists())
+        .ok_or_else(|| anyhow!("{:?} not found", ifo.with_extension(extensions[0])))
+}
+
+/// Start of every entry
+fn parse_idx(idx: &[u8], offset_len: usize) -> Result<Vec<usize>> {
+    let mut entries = Vec::new();
+    let mut pos = 0;
+    while pos < idx.len() {
+        let len = idx[pos..]
+            .iter()
+            .position(|&b| b == 0)
+            .ok_or_else(|| anyhow!("corrupted .idx"))?;
+        entries.push(pos);
+        pos += len + 1 + offset_len + 4;
+    }
+    if pos != idx.len() {
+        return Err(anyhow!("corrupted .idx"));
+    }
+    Ok(entries)
+}
+
+/// `g_ascii_strcasecmp`, the order of the .idx
+fn ascii_casecmp(a: &[u8], b: &[u8]) -> Ordering {
+    a.iter()
+        .map(u8::to_ascii_lowercase)
+        .cmp(b.iter().map(u8::to_ascii_lowercase))
+}
+
+/// (type, data) of the fields of an entry
+fn parse_fields(data: &[u8], types: &str) -> Vec<(char, Vec<u8>)> {
+    let types: Vec<char> = types.chars().collect();
+    let mut fields = Vec::new();
+    let mut rest = data;
+    while !rest.is_empty() {
+        // every field starts with its type without `sametypesequence`
+        let t = if types.is_empty() {
+            let t = rest[0] as char;
+            rest = &rest[1..];
+            t
+        } else if fields.len() < types.len() {
+            types[fields.len()]
+        } else {
+            break;
+        };
+        // with `sametypesequence`, the last field has neither size nor terminator
+        let last = fields.len() + 1 == types.len();
+        let len = if last {
+            rest.len()
+        } else if t.is_ascii_uppercase() {
+            if rest.len() < 4 {
+                break;
+            }
+            let len = u32::from_be_bytes([rest[0], rest[1], rest[2], rest[3]]) as usize;
+            rest = &rest[4..];
+            len.min(rest.len())
+        } else {
+            rest.iter().position(|&b| b == 0).unwrap_or(rest.len())
+        };
+        fields.push((t, rest[..len].to_vec()));
+        rest = &rest[len..];
+        // terminator of a string field
+        if !last && t.is_ascii_lowercase() && !rest.is_empty() {
+            rest = &rest[1..];
+        }
+    }
+    fields
+}
+
+impl StarDict {
+    /// `path`: the .ifo
+    pub fn open(path: &Path) -> Result<Self> {
+        let ifo = parse_ifo(path)?;
+        let offset_len = match ifo.get("idxoffsetbits").map(String::as_str) {
+            Some("64") => 8,
+            _ => 4,
+        };
+        let idx_path = sibling(path, &["idx", "idx.gz"])?;
+        let mut idx =
+            fs::read(&idx_path).with_context(|| format!("fail to open {:?}", idx_path))?;
+        if idx.starts_with(&GZIP_MAGIC) {
+            idx = gunzip(&idx)?;
+        }
+        let entries = parse_idx(&idx, offset_len)?;
+
+        Ok(Self {
+            name: ifo.get("bookname").cloned().unwrap_or_default(),
+            types: ifo.get("sametypesequence").cloned().unwrap_or_default(),
+            idx,
+            offset_len,
+            entries,
+            dict: Dict::open(&sibling(path, &["dict", "dict.dz"])?)?,
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    fn headword_at(&self, start: usize) -> &[u8] {
+        let len = self.idx[start..].iter().position(|&b| b == 0).unwrap_or(0);
+        &self.idx[start..start + len]
+    }
+
+    fn headword(&self, i: usize) -> &[u8] {
+        self.headword_at(self.entries[i])
+    }
+
+    /// (offset, size) in the .dict
+    fn location(&self, i: usize) -> (u64, u32) {
+        let pos = self.entries[i] + self.headword(i).len() + 1;
+        let offset = match self.offset_len {
+            8 => u64::from_be_bytes(self.idx[pos..pos + 8].try_into().unwrap()),
+            _ => u32::from_be_bytes(self.idx[pos..pos + 4].try_into().unwrap()) as u64,
+        };
+        let pos = pos + self.offset_len;
+        let size = u32::from_be_bytes(self.idx[pos..pos + 4].try_into().unwrap());
+        (offset, size)
+    }
+
+    /// Indexes of the headwords equal to `word` ignoring case
+    fn find(&self, word: &str) -> Vec<usize> {
+        let bytes = word.as_bytes();
+        let start = self
+            .entries
+            .partition_point(|&pos| ascii_casecmp(self.headword_at(pos), bytes) == Ordering::Less);
+        let found: Vec<usize> = (start..self.entries.len())
+            .take_while(|&i| ascii_casecmp(self.headword(i), bytes) == Ordering::Equal)
+            .collect();
+        if !found.is_empty() || word.is_ascii() {
+            return found;
+        }
+        // É and é are not next to each other in the .idx
+        let word = word.to_lowercase();
+        (0..self.entries.len())
+            .filter(|&i| String::from_utf8_lossy(self.headword(i)).to_lowercase() == word)
+            .collect()
+    }
+
+    /// (type, text) of every field, case insensitive
+    /// `h`: HTML, `m`: plain text, `t`: phonetic, see the format for others
+    pub fn lookup(&mut self, word: &str) -> Result<Option<Vec<(char, String)>>> {
+        let found = self.find(word);
+        if found.is_empty() {
+            return Ok(None);
+        }
+        let mut fields = Vec::new();
+        for i in found {
+            let (offset, size) = self.location(i);
+            let data = self.dict.read(offset, size)?;
+            fields.extend(
+                parse_fields(&data, &self.types)
+                    .into_iter()
+                    // binary fields: images, sounds
+                    .filter(|(t, _)| t.is_ascii_lowercase())
+                    .map(|(t, data)| (t, String::from_utf8_lossy(&data).into_owned())),
+            );
+        }
+        Ok(Some(fields))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fixture(name: &str) -> StarDict {
+        StarDict::open(
+            &Path::new(env!("CARGO_MANIFEST_DIR"))
+                .join("testdata/stardict")
+                .join(name),
+        )
+        .unwrap()
+    }
+
+    /// headword, 32-bit offset and size
+    fn idx_entry(word: &str, offset: u32, size: u32) -> Vec<u8> {
+        let mut v = word.as_bytes().to_vec();
+        v.push(0);
+        v.extend(offset.to_be_bytes());
+        v.extend(size.to_be_bytes());
+        v
+    }
+
+    #[test]
+    fn idx_entries() {
+        let idx = [idx_entry("a", 0, 1), idx_entry("bc", 1, 2)].concat();
+        assert_eq!(parse_idx(&idx, 4).unwrap(), [0, 10]);
+        assert_eq!(parse_idx(&[], 4).unwrap(), Vec::<usize>::new());
+        // truncated offset, missing terminator
+        assert!(parse_idx(&idx[..idx.len() - 1], 4).is_err());
+        assert!(parse_idx(b"abc", 4).is_err());
+        assert!(parse_idx(&idx, 8).is_err());
+    }
+
+    #[test]
+    fn fields_with_sametypesequence() {
+        // the last field has no terminator
+        assert_eq!(
+            parse_fields(b"ru:n\0to move fast", "tm"),
+            [('t', b"ru:n".to_vec()), ('m', b"to move fast".to_vec())]
+        );
+        // a binary field is sized
+        assert_eq!(
+            parse_fields(b"\0\0\0\x02abtext", "Wm"),
+            [('W', b"ab".to_vec()), ('m', b"text".to_vec())]
+        );
+        assert_eq!(parse_fields(b"", "m"), []);
+    }
+
+    #[test]
+    fn fields_with_their_type() {
+        assert_eq!(
+            parse_fields(b"mone\0W\0\0\0\x01xh<b>two</b>\0", ""),
+            [
+                ('m', b"one".to_vec()),
+                ('W', b"x".to_vec()),
+                ('h', b"<b>two</b>".to_vec())
+            ]
+        );
+        // truncated size
+        assert_eq!(parse_fields(b"mone\0W\0\0", ""), [('m', b"one".to_vec())]);
+        // a missing terminator ends the field
+        assert_eq!(parse_fields(b"mone", ""), [('m', b"one".to_vec())]);
+    }
+
+    #[test]
+    fn idx_order() {
+        let mut words = ["banana", "Éclair", "apple", "Apple", "ab", "a_b", "B"];
+        words.sort_by(|a, b| ascii_casecmp(a.as_bytes(), b.as_bytes()).then(a.cmp(b)));
+        assert_eq!(
+            words,
+            ["a_b", "ab", "Apple", "apple", "B", "banana", "Éclair"]
+        );
+        assert_eq!(ascii_casecmp(b"ABC", b"abc"), Ordering::Equal);
+        assert_eq!(ascii_casecmp(b"ab", b"abc"), Ordering::Less);
+    }
+
+    #[test]
+    fn gzip_headers() {
+        let header = [0x1f, 0x8b, 8, FEXTRA | FNAME | FHCRC, 0, 0, 0, 0, 0, 0xff];
+        let bytes = [&header[..], &[2, 0, b'R', b'A'], b"a\0", &[0, 0]].concat();
+        assert_eq!(gzip_header(&bytes).unwrap(), (bytes.len(), &b"RA"[..]));
+        // every truncation is an error, not a panic
+        for len in 0..bytes.len() {
+            assert!(gzip_header(&bytes[..len]).is_err(), "{len}");
+        }
+        assert!(gzip_header(&[0; 10]).is_err());
+    }
+
+    #[test]
+    fn plain() {
+        let mut stardict = fixture("plain.ifo");
+        assert_eq!(stardict.name, "plain");
+        assert_eq!(stardict.len(), 4);
+        assert_eq!(
+            stardict.lookup("APPLE").unwrap().unwrap(),
+            [
+                ('t', "ˈæpəl".to_owned()),
+                ('m', "the company".to_owned()),
+                ('t', "ˈæpəl".to_owned()),
+                ('m', "a round fruit".to_owned())
+            ]
+        );
+        assert_eq!(stardict.lookup("banana").unwrap().unwrap().len(), 2);
+        assert_eq!(stardict.lookup("bananas").unwrap(), None);
+        assert_eq!(stardict.lookup("").unwrap(), None);
+        // by the linear scan
+        assert_eq!(
+            stardict.lookup("éclair").unwrap().unwrap()[1],
+            ('m', "a pastry".to_owned())
+        );
+    }
+
+    #[test]
+    fn typed_and_gzipped() {
+        let mut stardict = fixture("typed.ifo");
+        assert!(matches!(stardict.dict, Dict::Memory(_)));
+        // the sound is dropped
+        assert_eq!(
+            stardict.lookup("quay").unwrap().unwrap(),
+            [
+                ('m', "a landing place".to_owned()),
+                ('h', "<b>quay</b>".to_owned())
+            ]
+        );
+        assert_eq!(
+            stardict.lookup("Brine").unwrap().unwrap(),
+            [('m', "water with salt".to_owned())]
+        );
+    }
+
+    #[test]
+    fn dictzip_chunks_read() {
+        let mut stardict = fixture("dictzip.ifo");
+        let Dict::Dictzip(dictzip) = &stardict.dict else {
+            panic!("not read as a dictzip");
+        };
+        assert_eq!(dictzip.chunk_len, 16);
+        assert!(dictzip.chunks.len() > 4);
+        // entries span several chunks, read out of order
+        for word in ["dog", "ant", "cat", "bee", "ant"] {
+            assert_eq!(
+                stardict.lookup(word).unwrap().unwrap(),
+                [('m', format!("{word}: {}definition", "long ".repeat(5)))]
+            );
+        }
+        let Dict::Dictzip(dictzip) = &mut stardict.dict else {
+            unreachable!()
+        };
+        assert!(dictzip.read(10_000, 1).is_err());
+    }
+}
@@ -0,0 +1,93 @@
+//! Tags of words, e.g. the favorites folders a word is in
+
+use anyhow::Result;
+use sqlx::Row;
+
+use crate::fsrs::sqlite_history::SQLiteHistory;
+
+impl SQLiteHistory {
+    /// Returns false if the word already has the tag
+    pub async fn add_tag(&self, word: &str, tag: &str) -> Result<bool> {
+        let res = sqlx::query("INSERT OR IGNORE INTO tag (word, tag) VALUES ($1, $2);")
+            .bind(word)
+            .bind(tag)
+            .execute(&self.conn)
+            .await?;
+        Ok(res.rows_affected() > 0)
+    }
+
+    pub async fn has_tag(&self, word: &str, tag: &str) -> Result<bool> {
+        let row = sqlx::query("SELECT word FROM tag WHERE word = $1 AND tag = $2;")
+            .bind(word)
+            .bind(tag)
+            .fetch_optional(&self.conn)
+            .await?;
+        Ok(row.is_some())
+    }
+
+    pub async fn tags(&self, word: &str) -> Result<Vec<String>> {
+        let tags: Vec<String> = sqlx::query("SELECT tag FROM tag WHERE word = $1 ORDER BY tag;")
+            .bind(word)
+            .fetch_all(&self.conn)
+            .await?
+            .into_iter()
+            .map(|sqlite_row| sqlite_row.get(0))
+            .collect();
+        Ok(tags)
+    }
+
+    /// Words forgotten again and again
+    pub async fn leeches(&self, min_lapses: i32) -> Result<Vec<String>> {
+        let words: Vec<String> =
+            sqlx::query("SELECT word FROM fsrs WHERE lapses >= $1 ORDER BY lapses DESC;")
+                .bind(min_lapses)
+                .fetch_all(&self.conn)
+                .await?
+                .into_iter()
+                .map(|sqlite_row| sqlite_row.get(0))
+                .collect();
+        Ok(words)
+    }
+
+    /// Words scheduled at least `min_days` later
+    pub async fn learned(&self, min_days: i64) -> Result<Vec<String>> {
+        let words: Vec<String> =
+            sqlx::query("SELECT word FROM fsrs WHERE scheduled_days >= $1 ORDER BY word;")
+                .bind(min_days)
+                .fetch_all(&self.conn)
+                .await?
+                .into_iter()
+                .map(|sqlite_row| sqlite_row.get(0))
+                .collect();
+        Ok(words)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rs_fsrs::Card;
+
+    #[tokio::test]
+    async fn tags_leeches_and_learned() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut history = SQLiteHistory::temp(dir.path()).await;
+        for (word, lapses, scheduled_days) in [("affect", 9, 1), ("apple", 0, 30), ("pear", 8, 21)]
+        {
+            let mut card = Card::new();
+            card.lapses = lapses;
+            card.scheduled_days = scheduled_days;
+            history.write_card(word, card).await.unwrap();
+        }
+
+        assert!(history.add_tag("apple", "Fruit").await.unwrap());
+        assert!(!history.add_tag("apple", "Fruit").await.unwrap());
+        history.add_tag("apple", "Food").await.unwrap();
+        assert!(history.has_tag("apple", "Fruit").await.unwrap());
+        assert!(!history.has_tag("pear", "Fruit").await.unwrap());
+        assert_eq!(history.tags("apple").await.unwrap(), ["Food", "Fruit"]);
+
+        assert_eq!(history.leeches(8).await.unwrap(), ["affect", "pear"]);
+        assert_eq!(history.learned(21).await.unwrap(), ["apple", "pear"]);
+    }
+}
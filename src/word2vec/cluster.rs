@@ -0,0 +1,356 @@
+//! Spherical k-means, or average-linkage agglomerative clustering, over the normalized word vectors
+//! Used by `review --clusters`
+
+use anyhow::anyhow;
+use anyhow::Result;
+use rand::prelude::IndexedRandom;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use rayon::prelude::*;
+
+use super::INDEX;
+use crate::fsrs::sqlite_history::SQLiteHistory;
+
+const ITERATIONS: usize = 20;
+/// the same due words are clustered the same way
+const KMEANS_SEED: u64 = 100;
+/// words of the heading
+const HEADING_LEN: usize = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum Method {
+    #[default]
+    Kmeans,
+    /// merges the most similar clusters until `k` are left, slower
+    Agglomerative,
+}
+
+#[derive(Debug, Clone)]
+pub struct Cluster {
+    /// words nearest to the centroid
+    pub heading: String,
+    pub words: Vec<String>,
+}
+
+fn dot(a: &[f32], b: &[f32]) -> f32 {
+    a.iter().zip(b).map(|(x, y)| x * y).sum()
+}
+
+fn normalize(v: &mut [f32]) {
+    let norm = v.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if norm != 0.0 {
+        v.iter_mut().for_each(|x| *x /= norm);
+    }
+}
+
+fn nearest(centroids: &[Vec<f32>], vector: &[f32]) -> usize {
+    centroids
+        .iter()
+        .enumerate()
+        .max_by(|(_, a), (_, b)| dot(a, vector).total_cmp(&dot(b, vector)))
+        .map(|(i, _)| i)
+        .unwrap()
+}
+
+/// Returns the cluster of each vector
+fn kmeans(vectors: &[&[f32]], k: usize) -> Vec<usize> {
+    let dim = vectors[0].len();
+    let mut rng = StdRng::seed_from_u64(KMEANS_SEED);
+
+    // k-means++: seeds drawn by the squared distance to the chosen ones
+    let mut centroids: Vec<Vec<f32>> = vec![vectors.choose(&mut rng).unwrap().to_vec()];
+    while centroids.len() < k {
+        let weights: Vec<f32> = vectors
+            .par_iter()
+            .map(|v| {
+                let similarity = dot(&centroids[nearest(&centroids, v)], v);
+                (1.0 - similarity).max(0.0).powi(2)
+            })
+            .collect();
+        let total: f32 = weights.iter().sum();
+        if total == 0.0 {
+            break;
+        }
+        let mut r = rng.random::<f32>() * total;
+        let i = weights
+            .iter()
+            .position(|w| {
+                r -= w;
+                r <= 0.0
+            })
+            .unwrap_or(vectors.len() - 1);
+        centroids.push(vectors[i].to_vec());
+    }
+
+    let mut assignment = vec![usize::MAX; vectors.len()];
+    for _ in 0..ITERATIONS {
+        let next: Vec<usize> = vectors.par_iter().map(|v| nearest(&centroids, v)).collect();
+        if next == assignment {
+            break;
+        }
+        assignment = next;
+
+        let mut sums = vec![vec![0f32; dim]; centroids.len()];
+        for (v, &c) in vectors.iter().zip(&assignment) {
+            sums[c].iter_mut().zip(v.iter()).for_each(|(s, x)| *s += x);
+        }
+        for (centroid, mut sum) in centroids.iter_mut().zip(sums) {
+            // keep the old centroid of an empty cluster
+            if sum.iter().any(|x| *x != 0.0) {
+                normalize(&mut sum);
+                *centroid = sum;
+            }
+        }
+    }
+    assignment
+}
+
+/// Average linkage by the nearest-neighbor chain, O(n²) time and memory
+/// Returns the cluster of each vector
+fn agglomerative(vectors: &[&[f32]], k: usize) -> Vec<usize> {
+    let n = vectors.len();
+    // similarity of clusters, a cluster is named after one of its vectors
+    let mut similarity: Vec<f32> = (0..n * n)
+        .into_par_iter()
+        .map(|i| dot(vectors[i / n], vectors[i % n]))
+        .collect();
+    let mut size = vec![1usize; n];
+    let mut active: Vec<usize> = (0..n).collect();
+    let mut chain: Vec<usize> = Vec::new();
+    // (similarity, a, b): b merged into a
+    let mut merges: Vec<(f32, usize, usize)> = Vec::with_capacity(n);
+
+    while active.len() > 1 {
+        if chain.is_empty() {
+            chain.push(active[0]);
+        }
+        let a = *chain.last().unwrap();
+        let previous = chain.len().checked_sub(2).map(|i| chain[i]);
+        // ties go to the previous one of the chain, or it may never end
+        let b = active
+            .iter()
+            .copied()
+            .filter(|&c| c != a)
+            .max_by(|&x, &y| {
+                similarity[a * n + x]
+                    .total_cmp(&similarity[a * n + y])
+                    .then((Some(x) == previous).cmp(&(Some(y) == previous)))
+            })
+            .unwrap();
+        if Some(b) != previous {
+            chain.push(b);
+            continue;
+        }
+        chain.truncate(chain.len() - 2);
+        merges.push((similarity[a * n + b], a, b));
+
+        // Lance-Williams update
+        for &c in &active {
+            let merged = (size[a] as f32 * similarity[a * n + c]
+                + size[b] as f32 * similarity[b * n + c])
+                / (size[a] + size[b]) as f32;
+            similarity[a * n + c] = merged;
+            similarity[c * n + a] = merged;
+        }
+        size[a] += size[b];
+        active.retain(|&c| c != b);
+    }
+
+    // the chain finds merges out of order: keep the n - k most similar
+    merges.sort_by(|x, y| y.0.total_cmp(&x.0));
+    let mut parent: Vec<usize> = (0..n).collect();
+    let root = |parent: &[usize], mut i: usize| {
+        while parent[i] != i {
+            i = parent[i];
+        }
+        i
+    };
+    for &(_, a, b) in merges.iter().take(n.saturating_sub(k)) {
+        let (a, b) = (root(&parent, a), root(&parent, b));
+        parent[b] = a;
+    }
+    let roots: Vec<usize> = (0..n).filter(|&i| parent[i] == i).collect();
+    (0..n)
+        .map(|i| roots.binary_search(&root(&parent, i)).unwrap())
+        .collect()
+}
+
+/// `words` and their vectors grouped, the largest cluster first
+fn group(words: &[String], vectors: &[&[f32]], k: usize, method: Method) -> Vec<Cluster> {
+    let k = k.min(words.len());
+    let assignment = match method {
+        Method::Kmeans => kmeans(vectors, k),
+        Method::Agglomerative => agglomerative(vectors, k),
+    };
+
+    let mut members: Vec<Vec<usize>> = vec![Vec::new(); k];
+    for (i, c) in assignment.into_iter().enumerate() {
+        members[c].push(i);
+    }
+
+    let mut clusters: Vec<Cluster> = members
+        .into_iter()
+        .filter(|m| !m.is_empty())
+        .map(|mut m| {
+            let mut centroid = vec![0f32; vectors[0].len()];
+            for i in &m {
+                centroid
+                    .iter_mut()
+                    .zip(vectors[*i])
+                    .for_each(|(s, x)| *s += x);
+            }
+            normalize(&mut centroid);
+            m.sort_by(|a, b| dot(vectors[*b], &centroid).total_cmp(&dot(vectors[*a], &centroid)));
+            let heading: Vec<&str> = m.iter().take(HEADING_LEN).map(|i| &*words[*i]).collect();
+            Cluster {
+                heading: heading.join(" · "),
+                words: m.iter().map(|i| words[*i].clone()).collect(),
+            }
+        })
+        .collect();
+    clusters.sort_by_key(|c| std::cmp::Reverse(c.words.len()));
+    clusters
+}
+
+impl SQLiteHistory {
+    /// Cluster all due words by meaning, the largest cluster first
+    /// Words missing in the word2vec index are left out
+    pub async fn clusters(&self, k: usize, method: Method) -> Result<Vec<Cluster>> {
+        let index = INDEX
+            .as_ref()
+            .ok_or_else(|| anyhow!("run `word2vec_index` first"))?;
+        if k == 0 {
+            return Err(anyhow!("the number of clusters should > 0"));
+        }
+
+        let words: Vec<String> = self
+            .all_words_need_review()
+            .await?
+            .into_iter()
+            .filter(|w| index.get_vector(w).is_some())
+            .collect();
+        if words.is_empty() {
+            return Ok(Vec::new());
+        }
+        let vectors: Vec<&[f32]> = words.iter().flat_map(|w| index.get_vector(w)).collect();
+        Ok(group(&words, &vectors, k, method))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `n` noisy vectors around each axis of `dim`
+    fn blobs(n: usize, dim: usize) -> (Vec<String>, Vec<Vec<f32>>) {
+        let mut rng = StdRng::seed_from_u64(1);
+        let mut words = Vec::new();
+        let mut vectors = Vec::new();
+        for axis in 0..dim {
+            for i in 0..n {
+                let mut v: Vec<f32> = (0..dim).map(|_| rng.random::<f32>() * 0.05).collect();
+                v[axis] += 1.0;
+                normalize(&mut v);
+                words.push(format!("{axis}-{i}"));
+                vectors.push(v);
+            }
+        }
+        (words, vectors)
+    }
+
+    fn check(method: Method) {
+        let (words, vectors) = blobs(20, 3);
+        let vectors: Vec<&[f32]> = vectors.iter().map(Vec::as_slice).collect();
+        let clusters = group(&words, &vectors, 3, method);
+        assert_eq!(clusters.len(), 3);
+        for cluster in &clusters {
+            assert_eq!(cluster.words.len(), 20);
+            let axis = cluster.words[0].split('-').next().unwrap();
+            assert!(cluster.words.iter().all(|w| w.starts_with(axis)));
+            assert_eq!(cluster.heading.split(" · ").count(), HEADING_LEN);
+        }
+    }
+
+    #[test]
+    fn kmeans_separates_blobs() {
+        check(Method::Kmeans);
+    }
+
+    #[test]
+    fn agglomerative_separates_blobs() {
+        check(Method::Agglomerative);
+    }
+
+    /// merge the most similar pair, average linkage, until `k` are left
+    fn naive_average_linkage(vectors: &[&[f32]], k: usize) -> Vec<Vec<usize>> {
+        let mut clusters: Vec<Vec<usize>> = (0..vectors.len()).map(|i| vec![i]).collect();
+        while clusters.len() > k {
+            let mut best = (f32::MIN, 0, 0);
+            for i in 0..clusters.len() {
+                for j in i + 1..clusters.len() {
+                    let mut sum = 0.0;
+                    for &x in &clusters[i] {
+                        for &y in &clusters[j] {
+                            sum += dot(vectors[x], vectors[y]);
+                        }
+                    }
+                    let s = sum / (clusters[i].len() * clusters[j].len()) as f32;
+                    if s > best.0 {
+                        best = (s, i, j);
+                    }
+                }
+            }
+            let merged = clusters.remove(best.2);
+            clusters[best.1].extend(merged);
+        }
+        clusters
+    }
+
+    #[test]
+    fn agglomerative_matches_naive_average_linkage() {
+        let mut rng = StdRng::seed_from_u64(2);
+        for _ in 0..20 {
+            let vectors: Vec<Vec<f32>> = (0..30)
+                .map(|_| {
+                    let mut v: Vec<f32> = (0..4).map(|_| rng.random::<f32>() - 0.5).collect();
+                    normalize(&mut v);
+                    v
+                })
+                .collect();
+            let vectors: Vec<&[f32]> = vectors.iter().map(Vec::as_slice).collect();
+            let assignment = agglomerative(&vectors, 4);
+            for cluster in naive_average_linkage(&vectors, 4) {
+                assert!(cluster
+                    .iter()
+                    .all(|&i| assignment[i] == assignment[cluster[0]]));
+            }
+        }
+    }
+
+    #[test]
+    fn more_clusters_than_words() {
+        let (words, vectors) = blobs(1, 2);
+        let vectors: Vec<&[f32]> = vectors.iter().map(Vec::as_slice).collect();
+        for method in [Method::Kmeans, Method::Agglomerative] {
+            let clusters = group(&words, &vectors, 10, method);
+            assert_eq!(clusters.len(), 2);
+        }
+    }
+
+    #[tokio::test]
+    async fn heading_is_cleared_after_the_last_cluster() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut history = SQLiteHistory::temp(dir.path()).await;
+        history.extend_stradegy = crate::fsrs::sqlite_history::ExtendStradegy::NoExtend;
+        history.clusters.push_back(Cluster {
+            heading: "apple".to_owned(),
+            words: vec!["apple".to_owned(), "pear".to_owned()],
+        });
+        assert_eq!(history.next_to_review().await.unwrap(), "apple");
+        assert_eq!(history.cluster_heading.as_deref(), Some("apple"));
+        assert_eq!(history.next_to_review().await.unwrap(), "pear");
+        assert_eq!(history.cluster_heading.as_deref(), Some("apple"));
+        assert!(history.next_to_review().await.is_err());
+        assert_eq!(history.cluster_heading, None);
+    }
+}
@@ -0,0 +1,527 @@
+//! Hierarchical Navigable Small World graph
+//! <https://arxiv.org/abs/1603.09320>
+//!
+//! Vectors are normalized, so distance is `1 - cosine similarity`
+
+use anyhow::anyhow;
+use anyhow::Result;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::cmp::Ordering;
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::fs;
+use std::fs::File;
+use std::io::BufWriter;
+use std::io::Write;
+use std::path::Path;
+
+/// max neighbors in upper levels
+const M: usize = 16;
+/// max neighbors in level 0
+const M0: usize = 2 * M;
+const EF_CONSTRUCTION: usize = 100;
+const MAGIC: &[u8; 8] = b"GDHNSW01";
+/// levels are drawn from a seeded rng: the same words build the same graph
+const LEVEL_SEED: u64 = 100;
+
+#[derive(Debug, Clone, Copy)]
+struct Candidate {
+    distance: f32,
+    id: u32,
+}
+
+impl PartialEq for Candidate {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Candidate {}
+
+impl PartialOrd for Candidate {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Candidate {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.distance
+            .total_cmp(&other.distance)
+            .then(self.id.cmp(&other.id))
+    }
+}
+
+pub struct Hnsw {
+    dim: usize,
+    words: Vec<String>,
+    ids: HashMap<String, u32>,
+    /// `words.len() * dim` floats
+    vectors: Vec<f32>,
+    /// neighbors[node][level]
+    neighbors: Vec<Vec<Vec<u32>>>,
+    entry_point: Option<u32>,
+    max_level: usize,
+    rng: StdRng,
+}
+
+fn normalize(v: &[f32]) -> Vec<f32> {
+    let norm = v.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if norm == 0.0 {
+        v.to_vec()
+    } else {
+        v.iter().map(|x| x / norm).collect()
+    }
+}
+
+fn dot(a: &[f32], b: &[f32]) -> f32 {
+    a.iter().zip(b).map(|(x, y)| x * y).sum()
+}
+
+fn random_level(rng: &mut StdRng) -> usize {
+    let ml = 1.0 / (M as f64).ln();
+    let r: f64 = rng.random::<f64>().max(f64::MIN_POSITIVE);
+    (-r.ln() * ml).floor() as usize
+}
+
+impl Hnsw {
+    pub fn new(dim: usize) -> Self {
+        Self {
+            dim,
+            words: Vec::new(),
+            ids: HashMap::new(),
+            vectors: Vec::new(),
+            neighbors: Vec::new(),
+            entry_point: None,
+            max_level: 0,
+            rng: StdRng::seed_from_u64(LEVEL_SEED),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.words.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.words.is_empty()
+    }
+
+    pub fn words(&self) -> &[String] {
+        &self.words
+    }
+
+    /// The normalized vector of an indexed word
+    pub fn get_vector(&self, word: &str) -> Option<&[f32]> {
+        self.ids.get(word).map(|id| self.vector(*id))
+    }
+
+    fn vector(&self, id: u32) -> &[f32] {
+        let i = id as usize * self.dim;
+        &self.vectors[i..i + self.dim]
+    }
+
+    fn distance(&self, query: &[f32], id: u32) -> f32 {
+        1.0 - dot(query, self.vector(id))
+    }
+
+    pub fn insert(&mut self, word: String, vector: &[f32]) -> Result<()> {
+        if vector.len() != self.dim {
+            return Err(anyhow!(
+                "{word}: expect dimension {}, found {}",
+                self.dim,
+                vector.len()
+            ));
+        }
+        if self.ids.contains_key(&word) {
+            return Ok(());
+        }
+
+        let query = normalize(vector);
+        let id = self.words.len() as u32;
+        let level = random_level(&mut self.rng);
+        self.ids.insert(word.clone(), id);
+        self.words.push(word);
+        self.vectors.extend_from_slice(&query);
+        self.neighbors.push(vec![Vec::new(); level + 1]);
+
+        let Some(mut entry_point) = self.entry_point else {
+            self.entry_point = Some(id);
+            self.max_level = level;
+            return Ok(());
+        };
+
+        for l in (level + 1..=self.max_level).rev() {
+            entry_point = self.greedy_search(&query, entry_point, l);
+        }
+
+        for l in (0..=level.min(self.max_level)).rev() {
+            let candidates = self.search_layer(&query, entry_point, EF_CONSTRUCTION, l);
+            let m = if l == 0 { M0 } else { M };
+            let selected: Vec<u32> = candidates.iter().take(m).map(|c| c.id).collect();
+            for &n in &selected {
+                self.neighbors[n as usize][l].push(id);
+                if self.neighbors[n as usize][l].len() > m {
+                    self.shrink(n, l, m);
+                }
+            }
+            self.neighbors[id as usize][l] = selected;
+            entry_point = candidates[0].id;
+        }
+
+        if level > self.max_level {
+            self.max_level = level;
+            self.entry_point = Some(id);
+        }
+        Ok(())
+    }
+
+    /// keep the `m` closest neighbors
+    fn shrink(&mut self, id: u32, level: usize, m: usize) {
+        let query = self.vector(id).to_vec();
+        let mut neighbors: Vec<Candidate> = self.neighbors[id as usize][level]
+            .iter()
+            .map(|&n| Candidate {
+                distance: self.distance(&query, n),
+                id: n,
+            })
+            .collect();
+        neighbors.sort();
+        neighbors.truncate(m);
+        self.neighbors[id as usize][level] = neighbors.into_iter().map(|c| c.id).collect();
+    }
+
+    fn greedy_search(&self, query: &[f32], entry_point: u32, level: usize) -> u32 {
+        let mut current = entry_point;
+        let mut distance = self.distance(query, current);
+        loop {
+            let mut changed = false;
+            for &n in &self.neighbors[current as usize][level] {
+                let d = self.distance(query, n);
+                if d < distance {
+                    distance = d;
+                    current = n;
+                    changed = true;
+                }
+            }
+            if !changed {
+                return current;
+            }
+        }
+    }
+
+    /// sorted by distance, closest first
+    fn search_layer(
+        &self,
+        query: &[f32],
+        entry_point: u32,
+        ef: usize,
+        level: usize,
+    ) -> Vec<Candidate> {
+        let first = Candidate {
+            distance: self.distance(query, entry_point),
+            id: entry_point,
+        };
+        let mut visited = HashSet::from([entry_point]);
+        let mut candidates = BinaryHeap::from([Reverse(first)]);
+        let mut results = BinaryHeap::from([first]);
+
+        while let Some(Reverse(c)) = candidates.pop() {
+            let furthest = results.peek().map_or(f32::MAX, |x| x.distance);
+            if c.distance > furthest {
+                break;
+            }
+            for &n in &self.neighbors[c.id as usize][level] {
+                if !visited.insert(n) {
+                    continue;
+                }
+                let candidate = Candidate {
+                    distance: self.distance(query, n),
+                    id: n,
+                };
+                let furthest = results.peek().map_or(f32::MAX, |x| x.distance);
+                if results.len() < ef || candidate.distance < furthest {
+                    candidates.push(Reverse(candidate));
+                    results.push(candidate);
+                    if results.len() > ef {
+                        results.pop();
+                    }
+                }
+            }
+        }
+
+        results.into_sorted_vec()
+    }
+
+    /// `k` nearest words with their cosine similarity, most similar first
+    pub fn search(&self, vector: &[f32], k: usize) -> Vec<(f32, &str)> {
+        let Some(mut entry_point) = self.entry_point else {
+            return Vec::new();
+        };
+        if vector.len() != self.dim {
+            return Vec::new();
+        }
+        let query = normalize(vector);
+        for l in (1..=self.max_level).rev() {
+            entry_point = self.greedy_search(&query, entry_point, l);
+        }
+        self.search_layer(&query, entry_point, k.max(EF_CONSTRUCTION), 0)
+            .into_iter()
+            .take(k)
+            .map(|c| (1.0 - c.distance, &*self.words[c.id as usize]))
+            .collect()
+    }
+
+    /// Every word more similar than `min_similarity`, most similar first
+    /// The `k` nearest are fetched, then twice as many until one is under the threshold
+    pub fn search_above(&self, vector: &[f32], min_similarity: f32, k: usize) -> Vec<(f32, &str)> {
+        let mut k = k.max(1);
+        loop {
+            let mut v = self.search(vector, k);
+            if v.len() < k || v.last().is_some_and(|(s, _)| *s <= min_similarity) {
+                v.retain(|(s, _)| *s > min_similarity);
+                return v;
+            }
+            k *= 2;
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let mut w = BufWriter::new(File::create(path)?);
+        w.write_all(MAGIC)?;
+        write_u32(&mut w, self.dim as u32)?;
+        write_u32(&mut w, self.words.len() as u32)?;
+        write_u32(&mut w, self.max_level as u32)?;
+        write_u32(&mut w, self.entry_point.unwrap_or(u32::MAX))?;
+        for (id, word) in self.words.iter().enumerate() {
+            write_u32(&mut w, word.len() as u32)?;
+            w.write_all(word.as_bytes())?;
+            for x in self.vector(id as u32) {
+                w.write_all(&x.to_le_bytes())?;
+            }
+            let levels = &self.neighbors[id];
+            write_u32(&mut w, levels.len() as u32)?;
+            for neighbors in levels {
+                write_u32(&mut w, neighbors.len() as u32)?;
+                for n in neighbors {
+                    write_u32(&mut w, *n)?;
+                }
+            }
+        }
+        w.flush()?;
+        Ok(())
+    }
+
+    /// Lengths are checked against the file: a corrupt index is an error, not a huge allocation
+    pub fn load(path: &Path) -> Result<Self> {
+        let bytes = fs::read(path)?;
+        let mut r = Reader(&bytes);
+        if r.bytes(MAGIC.len())? != MAGIC {
+            return Err(anyhow!("{:?} is not a word2vec index", path));
+        }
+        let dim = r.u32()? as usize;
+        let len = r.u32()? as usize;
+        let max_level = r.u32()? as usize;
+        let entry_point = Some(r.u32()?).filter(|x| *x != u32::MAX);
+        // every word takes at least its length, vector and level count
+        if dim == 0 || len.saturating_mul(dim.saturating_mul(4).saturating_add(8)) > r.0.len() {
+            return Err(anyhow!("{:?}: corrupt index", path));
+        }
+
+        let mut hnsw = Self::new(dim);
+        hnsw.max_level = max_level;
+        hnsw.entry_point = entry_point;
+        hnsw.vectors.reserve(len * dim);
+        for id in 0..len {
+            let word_len = r.u32()? as usize;
+            let word = String::from_utf8(r.bytes(word_len)?.to_vec())?;
+            for x in r.bytes(dim * 4)?.chunks_exact(4) {
+                hnsw.vectors.push(f32::from_le_bytes(x.try_into().unwrap()));
+            }
+            let levels = r.u32()? as usize;
+            if levels == 0 || levels > max_level + 1 {
+                return Err(anyhow!("{:?}: corrupt index", path));
+            }
+            let mut neighbors = Vec::with_capacity(levels);
+            for _ in 0..levels {
+                let n = r.u32()? as usize;
+                let v: Vec<u32> = r
+                    .bytes(n.saturating_mul(4))?
+                    .chunks_exact(4)
+                    .map(|x| u32::from_le_bytes(x.try_into().unwrap()))
+                    .collect();
+                neighbors.push(v);
+            }
+            hnsw.ids.insert(word.clone(), id as u32);
+            hnsw.words.push(word);
+            hnsw.neighbors.push(neighbors);
+        }
+        if !hnsw.is_consistent() {
+            return Err(anyhow!("{:?}: corrupt index", path));
+        }
+        Ok(hnsw)
+    }
+
+    /// Every node reached by a search exists and has the level it's reached at
+    fn is_consistent(&self) -> bool {
+        let levels = |id: u32| self.neighbors.get(id as usize).map_or(0, Vec::len);
+        let entry_point_ok = match self.entry_point {
+            Some(id) => levels(id) == self.max_level + 1,
+            None => self.is_empty(),
+        };
+        entry_point_ok
+            && self.neighbors.iter().all(|node| {
+                node.iter()
+                    .enumerate()
+                    .all(|(l, neighbors)| neighbors.iter().all(|&n| levels(n) > l))
+            })
+    }
+}
+
+/// Bounds-checked reads of the index file
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, n: usize) -> Result<&'a [u8]> {
+        if n > self.0.len() {
+            return Err(anyhow!("unexpected end of index"));
+        }
+        let (head, tail) = self.0.split_at(n);
+        self.0 = tail;
+        Ok(head)
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        Ok(u32::from_le_bytes(self.bytes(4)?.try_into().unwrap()))
+    }
+}
+
+fn write_u32(w: &mut impl Write, x: u32) -> Result<()> {
+    w.write_all(&x.to_le_bytes())?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn random_vectors(n: usize, dim: usize, seed: u64) -> Vec<Vec<f32>> {
+        let mut rng = StdRng::seed_from_u64(seed);
+        (0..n)
+            .map(|_| (0..dim).map(|_| rng.random::<f32>() - 0.5).collect())
+            .collect()
+    }
+
+    fn build(vectors: &[Vec<f32>]) -> Hnsw {
+        let mut hnsw = Hnsw::new(vectors[0].len());
+        for (i, v) in vectors.iter().enumerate() {
+            hnsw.insert(i.to_string(), v).unwrap();
+        }
+        hnsw
+    }
+
+    fn brute_force(vectors: &[Vec<f32>], query: &[f32], k: usize) -> Vec<String> {
+        let query = normalize(query);
+        let mut v: Vec<(f32, usize)> = vectors
+            .iter()
+            .enumerate()
+            .map(|(i, x)| (dot(&query, &normalize(x)), i))
+            .collect();
+        v.sort_by(|a, b| b.0.total_cmp(&a.0));
+        v.into_iter().take(k).map(|(_, i)| i.to_string()).collect()
+    }
+
+    #[test]
+    fn recall_against_brute_force() {
+        let vectors = random_vectors(1000, 16, 1);
+        let hnsw = build(&vectors);
+        let k = 10;
+        let mut found = 0;
+        let queries = random_vectors(50, 16, 2);
+        for query in &queries {
+            let expected = brute_force(&vectors, query, k);
+            found += hnsw
+                .search(query, k)
+                .iter()
+                .filter(|(_, w)| expected.iter().any(|e| e == w))
+                .count();
+        }
+        let recall = found as f64 / (queries.len() * k) as f64;
+        assert!(recall > 0.95, "recall {recall}");
+    }
+
+    #[test]
+    fn search_is_sorted_and_bounded() {
+        let vectors = random_vectors(100, 8, 3);
+        let hnsw = build(&vectors);
+        let v = hnsw.search(&vectors[3], 200);
+        assert_eq!(v.len(), 100);
+        assert_eq!(v[0].1, "3");
+        assert!(v.windows(2).all(|x| x[0].0 >= x[1].0));
+        assert!(hnsw.search(&[1.0], 5).is_empty());
+        assert!(Hnsw::new(8).search(&vectors[0], 5).is_empty());
+    }
+
+    #[test]
+    fn search_above_goes_past_k() {
+        let vectors = random_vectors(500, 4, 4);
+        let hnsw = build(&vectors);
+        let query = &vectors[0];
+        let expected = vectors
+            .iter()
+            .filter(|v| dot(&normalize(query), &normalize(v)) > 0.1)
+            .count();
+        let v = hnsw.search_above(query, 0.1, 5);
+        assert!(v.len() > 5);
+        assert!(v.iter().all(|(s, _)| *s > 0.1));
+        assert!(
+            v.len() as f64 > expected as f64 * 0.95,
+            "{} of {expected}",
+            v.len()
+        );
+    }
+
+    #[test]
+    fn save_and_load() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("word2vec.hnsw");
+        let vectors = random_vectors(200, 8, 5);
+        let hnsw = build(&vectors);
+        hnsw.save(&path).unwrap();
+        let loaded = Hnsw::load(&path).unwrap();
+        assert_eq!(loaded.words(), hnsw.words());
+        assert_eq!(loaded.get_vector("7"), hnsw.get_vector("7"));
+        assert_eq!(loaded.search(&vectors[7], 5), hnsw.search(&vectors[7], 5));
+    }
+
+    #[test]
+    fn corrupt_index_is_an_error() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("word2vec.hnsw");
+        build(&random_vectors(50, 8, 6)).save(&path).unwrap();
+        let bytes = fs::read(&path).unwrap();
+
+        fs::write(&path, &bytes[..bytes.len() - 3]).unwrap();
+        assert!(Hnsw::load(&path).is_err());
+
+        // huge word count
+        let mut huge = bytes.clone();
+        huge[12..16].copy_from_slice(&u32::MAX.to_le_bytes());
+        fs::write(&path, &huge).unwrap();
+        assert!(Hnsw::load(&path).is_err());
+
+        // huge length of the first word
+        let mut huge = bytes.clone();
+        huge[24..28].copy_from_slice(&u32::MAX.to_le_bytes());
+        fs::write(&path, &huge).unwrap();
+        assert!(Hnsw::load(&path).is_err());
+
+        // entry point out of range
+        let mut huge = bytes;
+        huge[20..24].copy_from_slice(&1000u32.to_le_bytes());
+        fs::write(&path, &huge).unwrap();
+        assert!(Hnsw::load(&path).is_err());
+    }
+}
@@ -0,0 +1,119 @@
+use anyhow::anyhow;
+use anyhow::Result;
+use std::collections::HashSet;
+use std::env;
+use std::path::PathBuf;
+use std::sync::LazyLock;
+use word2vec::wordvectors::WordVector;
+
+use crate::dictionary_dir;
+use crate::fsrs::sqlite_history::SQLiteHistory;
+use hnsw::Hnsw;
+use oov::{embed, NgramIndex};
+
+pub mod cluster;
+pub mod hnsw;
+pub mod oov;
+
+const THREHOLD: f32 = 0.5;
+/// neighbors first fetched from the index for each reviewed word
+const TOP_K: usize = 50;
+
+/// Built by `word2vec_index`
+pub fn index_path() -> PathBuf {
+    dictionary_dir().join("word2vec.hnsw")
+}
+
+/// `None` if `BIN_PATH` is unset
+pub fn bin_path() -> Option<PathBuf> {
+    env::var_os("BIN_PATH").map(PathBuf::from)
+}
+
+pub fn load_model() -> Result<WordVector> {
+    let bin_path = bin_path().ok_or_else(|| anyhow!("Please set BIN_PATH"))?;
+    let bin_path = bin_path
+        .to_str()
+        .ok_or_else(|| anyhow!("{:?} is not valid UTF-8", bin_path))?;
+    WordVector::load_from_binary(bin_path)
+        .map_err(|e| anyhow!("Unable to load word vector model: {e:?}"))
+}
+
+static INDEX: LazyLock<Option<Hnsw>> = LazyLock::new(|| {
+    let path = index_path();
+    if !path.exists() {
+        return None;
+    }
+    match Hnsw::load(&path) {
+        Ok(index) => Some(index),
+        Err(e) => {
+            eprintln!("fail to load {:?}: {e}", path);
+            None
+        }
+    }
+});
+
+/// n-gram fallback for words added after the index is built
+static NGRAM_INDEX: LazyLock<Option<NgramIndex<'static>>> = LazyLock::new(|| {
+    INDEX
+        .as_ref()
+        .map(|index| NgramIndex::new(index.words().iter().map(String::as_str)))
+});
+
+/// word2vec extension does nothing without the index
+pub fn available() -> bool {
+    INDEX.is_some()
+}
+
+impl SQLiteHistory {
+    async fn rank_similar_words(&self, word: &str) -> Result<Vec<(f32, String)>> {
+        let Some(index) = &*INDEX else {
+            return Ok(Vec::new());
+        };
+        // out of vocabulary: skip silently
+        let Some(vector) = embed(word, |w| index.get_vector(w), NGRAM_INDEX.as_ref()) else {
+            return Ok(Vec::new());
+        };
+
+        let need_review: HashSet<String> =
+            self.all_words_need_review().await?.into_iter().collect();
+
+        // due words are kept wherever they rank, not only in the first `TOP_K`
+        let v: Vec<(f32, String)> = index
+            .search_above(&vector, THREHOLD, TOP_K)
+            .into_iter()
+            .filter(|(_, x)| *x != word && need_review.contains(*x))
+            .map(|(similarity, x)| (similarity, x.to_owned()))
+            .collect();
+        Ok(v)
+    }
+
+    pub async fn extend_by_word2vec(&mut self, word: &str) -> Result<()> {
+        let v = self.rank_similar_words(word).await?;
+        self.queue.extend(v.into_iter().map(|(_, w)| w));
+        Ok(())
+    }
+
+    /// Build the index over the vectors of every word in `fsrs`
+    /// Phrases and unknown words get vectors from `oov::embed`
+    pub async fn build_word2vec_index(&self, model: &WordVector) -> Result<Hnsw> {
+        let words = self.all_words().await?;
+        let lookup = |w: &str| model.get_vector(w).map(Vec::as_slice);
+        let ngram_index = NgramIndex::new(
+            words
+                .iter()
+                .map(String::as_str)
+                .filter(|w| lookup(w).is_some()),
+        );
+
+        let mut index = None;
+        for word in words.iter() {
+            let Some(vector) = embed(word, lookup, Some(&ngram_index)) else {
+                continue;
+            };
+            index
+                .get_or_insert_with(|| Hnsw::new(vector.len()))
+                .insert(word.clone(), &vector)?;
+        }
+        index.ok_or_else(|| anyhow!("no word in history.db is found in the model"))
+    }
+}
@@ -0,0 +1,202 @@
+//! Vectors for words missing in the model
+//!
+//! 1. the word itself, or its lowercase
+//! 2. phrase: average of the token vectors
+//! 3. character n-grams: average of the vectors of words spelled alike
+//!
+//! Give up if nothing applies
+
+use std::collections::HashMap;
+
+const N: usize = 3;
+/// words spelled alike used by the n-gram fallback
+const NGRAM_NEIGHBORS: usize = 3;
+/// dice coefficient of the n-grams
+const NGRAM_THREHOLD: f32 = 0.6;
+
+fn ngrams(word: &str) -> Vec<String> {
+    // `<` and `>` mark the boundary, like fastText
+    let chars: Vec<char> = format!("<{}>", word.to_lowercase()).chars().collect();
+    let mut v: Vec<String> = chars
+        .windows(N.min(chars.len()))
+        .map(|w| w.iter().collect())
+        .collect();
+    v.sort();
+    v.dedup();
+    v
+}
+
+/// n-gram -> words containing it
+pub struct NgramIndex<'a> {
+    words: Vec<&'a str>,
+    ngram_count: Vec<usize>,
+    postings: HashMap<String, Vec<u32>>,
+}
+
+impl<'a> NgramIndex<'a> {
+    pub fn new(words: impl IntoIterator<Item = &'a str>) -> Self {
+        let mut index = Self {
+            words: Vec::new(),
+            ngram_count: Vec::new(),
+            postings: HashMap::new(),
+        };
+        for word in words {
+            let id = index.words.len() as u32;
+            let ngrams = ngrams(word);
+            index.ngram_count.push(ngrams.len());
+            for ngram in ngrams {
+                index.postings.entry(ngram).or_default().push(id);
+            }
+            index.words.push(word);
+        }
+        index
+    }
+
+    /// Words spelled alike, most alike first
+    fn similar(&self, word: &str) -> Vec<(f32, &'a str)> {
+        let ngrams = ngrams(word);
+        let mut shared: HashMap<u32, usize> = HashMap::new();
+        for ngram in &ngrams {
+            for id in self.postings.get(ngram).into_iter().flatten() {
+                *shared.entry(*id).or_default() += 1;
+            }
+        }
+        let mut v: Vec<(f32, &str)> = shared
+            .into_iter()
+            .map(|(id, n)| {
+                let dice = 2.0 * n as f32 / (ngrams.len() + self.ngram_count[id as usize]) as f32;
+                (dice, self.words[id as usize])
+            })
+            .filter(|(dice, w)| *dice >= NGRAM_THREHOLD && !w.eq_ignore_ascii_case(word))
+            .collect();
+        v.sort_by(|a, b| b.0.total_cmp(&a.0));
+        v.truncate(NGRAM_NEIGHBORS);
+        v
+    }
+}
+
+fn weighted_average<'b>(v: impl IntoIterator<Item = (f32, &'b [f32])>) -> Option<Vec<f32>> {
+    let mut sum: Option<Vec<f32>> = None;
+    let mut total = 0.0;
+    for (weight, vector) in v {
+        let sum = sum.get_or_insert_with(|| vec![0.0; vector.len()]);
+        if sum.len() != vector.len() {
+            continue;
+        }
+        sum.iter_mut()
+            .zip(vector)
+            .for_each(|(s, x)| *s += weight * x);
+        total += weight;
+    }
+    if total == 0.0 {
+        return None;
+    }
+    sum.map(|s| s.into_iter().map(|x| x / total).collect::<Vec<f32>>())
+        .filter(|s| s.iter().any(|x| *x != 0.0))
+}
+
+fn exact<'b>(word: &str, lookup: &impl Fn(&str) -> Option<&'b [f32]>) -> Option<&'b [f32]> {
+    lookup(word).or_else(|| lookup(&word.to_lowercase()))
+}
+
+/// `lookup`: the vector of an in-vocabulary word
+/// `ngram_index`: words for the n-gram fallback
+pub fn embed<'b>(
+    word: &str,
+    lookup: impl Fn(&str) -> Option<&'b [f32]>,
+    ngram_index: Option<&NgramIndex>,
+) -> Option<Vec<f32>> {
+    if let Some(v) = exact(word, &lookup) {
+        return Some(v.to_vec());
+    }
+
+    let tokens: Vec<&str> = word
+        .split(|c: char| c.is_whitespace() || c == '-' || c == '_')
+        .filter(|t| !t.is_empty())
+        .collect();
+    if tokens.len() > 1 {
+        let v = weighted_average(
+            tokens
+                .iter()
+                .flat_map(|t| exact(t, &lookup))
+                .map(|v| (1.0, v)),
+        );
+        if v.is_some() {
+            return v;
+        }
+    }
+
+    let ngram_index = ngram_index?;
+    weighted_average(
+        ngram_index
+            .similar(word)
+            .into_iter()
+            .flat_map(|(dice, w)| lookup(w).map(|v| (dice, v))),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn model() -> HashMap<&'static str, Vec<f32>> {
+        HashMap::from([
+            ("ice", vec![1.0, 0.0]),
+            ("cream", vec![0.0, 1.0]),
+            ("London", vec![0.5, 0.5]),
+            ("information", vec![1.0, 1.0]),
+        ])
+    }
+
+    #[test]
+    fn ngrams_mark_the_boundary() {
+        assert_eq!(ngrams("ab"), ["<ab", "ab>"]);
+        assert_eq!(ngrams("a"), ["<a>"]);
+        assert_eq!(ngrams("Aaa"), ["<aa", "aa>", "aaa"]);
+    }
+
+    #[test]
+    fn exact_then_lowercase() {
+        let model = model();
+        let lookup = |w: &str| model.get(w).map(Vec::as_slice);
+        assert_eq!(embed("London", lookup, None).unwrap(), [0.5, 0.5]);
+        assert_eq!(embed("ICE", lookup, None).unwrap(), [1.0, 0.0]);
+        assert!(embed("london", lookup, None).is_none());
+    }
+
+    #[test]
+    fn phrase_is_the_average_of_tokens() {
+        let model = model();
+        let lookup = |w: &str| model.get(w).map(Vec::as_slice);
+        assert_eq!(embed("ice cream", lookup, None).unwrap(), [0.5, 0.5]);
+        assert_eq!(embed("ice-cream", lookup, None).unwrap(), [0.5, 0.5]);
+        // unknown tokens are skipped
+        assert_eq!(embed("ice xyz", lookup, None).unwrap(), [1.0, 0.0]);
+        assert!(embed("xyz qqq", lookup, None).is_none());
+    }
+
+    #[test]
+    fn ngram_fallback() {
+        let model = model();
+        let lookup = |w: &str| model.get(w).map(Vec::as_slice);
+        let index = NgramIndex::new(model.keys().copied());
+        assert_eq!(index.similar("informations")[0].1, "information");
+        assert!(index.similar("ice").is_empty());
+        assert_eq!(
+            embed("informations", lookup, Some(&index)).unwrap().len(),
+            2
+        );
+        assert!(embed("zzzz", lookup, Some(&index)).is_none());
+    }
+
+    #[test]
+    fn weights() {
+        let (a, b) = ([1.0, 0.0], [0.0, 1.0]);
+        assert_eq!(
+            weighted_average([(3.0, &a[..]), (1.0, &b[..])]).unwrap(),
+            [0.75, 0.25]
+        );
+        assert!(weighted_average([(0.0, &a[..])]).is_none());
+        assert!(weighted_average(std::iter::empty()).is_none());
+    }
+}
@@ -0,0 +1,112 @@
+#!/usr/bin/env python3
+"""Writes tiny.mdx: version 2.0, UTF-8, encrypted key block info, one `@@@LINK=`
+and tiny.mdd: UTF-16 keys, the sound of tiny.mdx"""
+import struct
+import zlib
+
+R = [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15,
+     7, 4, 13, 1, 10, 6, 15, 3, 12, 0, 9, 5, 2, 14, 11, 8,
+     3, 10, 14, 4, 9, 15, 8, 1, 2, 7, 0, 6, 13, 11, 5, 12,
+     1, 9, 11, 10, 0, 8, 12, 4, 13, 3, 7, 15, 14, 5, 6, 2]
+RP = [5, 14, 7, 0, 9, 2, 11, 4, 13, 6, 15, 8, 1, 10, 3, 12,
+      6, 11, 3, 7, 0, 13, 5, 10, 14, 15, 8, 12, 4, 9, 1, 2,
+      15, 5, 1, 3, 7, 14, 6, 9, 11, 8, 12, 2, 10, 0, 4, 13,
+      8, 6, 4, 1, 3, 11, 15, 0, 5, 12, 2, 13, 9, 7, 10, 14]
+S = [11, 14, 15, 12, 5, 8, 7, 9, 11, 13, 14, 15, 6, 7, 9, 8,
+     7, 6, 8, 13, 11, 9, 7, 15, 7, 12, 15, 9, 11, 7, 13, 12,
+     11, 13, 6, 7, 14, 9, 13, 15, 14, 8, 13, 6, 5, 12, 7, 5,
+     11, 12, 14, 15, 14, 15, 9, 8, 9, 14, 5, 6, 8, 6, 5, 12]
+SP = [8, 9, 9, 11, 13, 15, 15, 5, 7, 7, 8, 11, 14, 14, 12, 6,
+      9, 13, 15, 7, 12, 8, 9, 11, 7, 7, 12, 7, 6, 15, 13, 11,
+      9, 7, 15, 11, 8, 6, 6, 14, 12, 13, 5, 14, 13, 13, 7, 5,
+      15, 5, 8, 11, 14, 14, 6, 14, 6, 9, 12, 9, 12, 5, 15, 8]
+K = [0x00000000, 0x5a827999, 0x6ed9eba1, 0x8f1bbcdc]
+KP = [0x50a28be6, 0x5c4dd124, 0x6d703ef3, 0x00000000]
+M = 0xffffffff
+
+
+def f(j, x, y, z):
+    if j < 16:
+        return x ^ y ^ z
+    if j < 32:
+        return (x & y) | (~x & z)
+    if j < 48:
+        return (x | ~y & M) ^ z
+    return (x & z) | (y & ~z)
+
+
+def rol(x, n):
+    return ((x << n) | (x >> (32 - n))) & M
+
+
+def ripemd128(message):
+    h = [0x67452301, 0xefcdab89, 0x98badcfe, 0x10325476]
+    padded = message + b'\x80'
+    padded += b'\0' * ((56 - len(padded)) % 64)
+    padded += struct.pack('<Q', len(message) * 8)
+    for i in range(0, len(padded), 64):
+        x = struct.unpack('<16I', padded[i:i + 64])
+        a, b, c, d = h
+        ap, bp, cp, dp = h
+        for j in range(64):
+            t = rol((a + (f(j, b, c, d) & M) + x[R[j]] + K[j // 16]) & M, S[j])
+            a, d, c, b = d, c, b, t
+            t = rol((ap + (f(63 - j, bp, cp, dp) & M) + x[RP[j]] + KP[j // 16]) & M, SP[j])
+            ap, dp, cp, bp = dp, cp, bp, t
+        h = [(h[1] + c + dp) & M, (h[2] + d + ap) & M, (h[3] + a + bp) & M, (h[0] + b + cp) & M]
+    return struct.pack('<4I', *h)
+
+
+def block(data):
+    return struct.pack('<I', 2) + struct.pack('>I', zlib.adler32(data)) + zlib.compress(data)
+
+
+def encrypt(block):
+    key = ripemd128(block[4:8] + struct.pack('<I', 0x3695))
+    res = bytearray(block[:8])
+    previous = 0x36
+    for i, t in enumerate(block[8:]):
+        b = t ^ previous ^ (i & 0xff) ^ key[i % len(key)]
+        b = ((b << 4) | (b >> 4)) & 0xff
+        previous = b
+        res.append(b)
+    return bytes(res)
+
+
+def write(path, entries, mdd=False):
+    """entries: (key, record), records of an mdd are bytes"""
+    entries = sorted(entries, key=lambda e: e[0].lower())
+    encoding, terminator = ('utf-16-le', b'\0\0') if mdd else ('utf-8', b'\0')
+    records = b''
+    keys = b''
+    for k, v in entries:
+        keys += struct.pack('>Q', len(records)) + k.encode(encoding) + terminator
+        records += v if mdd else v.encode() + b'\r\n\0'
+    key_block = block(keys)
+    first, last = entries[0][0], entries[-1][0]
+    info = (struct.pack('>Q', len(entries))
+            + struct.pack('>H', len(first)) + first.encode(encoding) + terminator
+            + struct.pack('>H', len(last)) + last.encode(encoding) + terminator
+            + struct.pack('>QQ', len(key_block), len(keys)))
+    info_block = encrypt(block(info))
+    record_block = block(records)
+
+    header = ('<Dictionary GeneratedByEngineVersion="2.0" RequiredEngineVersion="2.0" '
+              'Encrypted="2" {}Title="tiny"/>\r\n\0').format('' if mdd else 'Encoding="UTF-8" ').encode('utf-16-le')
+    out = struct.pack('>I', len(header)) + header + struct.pack('<I', zlib.adler32(header))
+    key_header = struct.pack('>QQQQQ', 1, len(entries), len(info), len(info_block), len(key_block))
+    out += key_header + struct.pack('>I', zlib.adler32(key_header)) + info_block + key_block
+    out += struct.pack('>QQQQ', 1, len(entries), 16, len(record_block))
+    out += struct.pack('>QQ', len(record_block), len(records)) + record_block
+    with open(path, 'wb') as file:
+        file.write(out)
+
+
+assert ripemd128(b'').hex() == 'cdf26213a150dc3ecb610f18f6b38b46'
+assert ripemd128(b'abc').hex() == 'c14a12199c66e4ba84636b0f69144c77'
+write('tiny.mdx', [
+    ('Apple', '<div>apple <a href="sound://us/apple.mp3">play</a></div>'),
+    ('apples', '@@@LINK=Apple'),
+    ('pear', '<p>pear</p>'),
+])
+write('tiny.mdd', [('\\us\\apple.mp3', b'ID3 apple')], mdd=True)
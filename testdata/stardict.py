@@ -0,0 +1,98 @@
+#!/usr/bin/env python3
+"""Writes the StarDict fixtures of stardict.rs into the current directory, testdata/stardict
+
+plain.*: sametypesequence=tm, 64-bit offsets
+typed.*: a type before every field, gzipped .idx, .dict.dz without the chunk table
+dictzip.*: 16-byte dictzip chunks, gzip header with FNAME and FHCRC
+"""
+import functools
+import struct
+import zlib
+
+
+def casecmp(a, b):
+    """g_ascii_strcasecmp, then strcmp"""
+    la, lb = a.lower(), b.lower()
+    if la != lb:
+        return -1 if la < lb else 1
+    return -1 if a < b else (a > b)
+
+
+def write(name, entries, types, offset_bits=32):
+    """entries: (headword, data)"""
+    entries = sorted(((w.encode(), d) for w, d in entries),
+                     key=functools.cmp_to_key(lambda x, y: casecmp(x[0], y[0])))
+    idx, dict_ = b"", b""
+    for word, data in entries:
+        fmt = ">QI" if offset_bits == 64 else ">II"
+        idx += word + b"\0" + struct.pack(fmt, len(dict_), len(data))
+        dict_ += data
+    ifo = f"StarDict's dict ifo file\nversion=3.0.0\nbookname={name}\nwordcount={len(entries)}\n"
+    ifo += f"idxfilesize={len(idx)}\n"
+    if types:
+        ifo += f"sametypesequence={types}\n"
+    if offset_bits == 64:
+        ifo += "idxoffsetbits=64\n"
+    with open(f"{name}.ifo", "w") as f:
+        f.write(ifo)
+    return idx, dict_
+
+
+def gzip(data, flags=0, extra=b"", fname=b""):
+    header = b"\x1f\x8b\x08" + bytes([flags]) + b"\0\0\0\0\0\xff"
+    if extra:
+        header += struct.pack("<H", len(extra)) + extra
+    if fname:
+        header += fname + b"\0"
+    if flags & 0x02:
+        header += struct.pack("<H", zlib.crc32(header) & 0xffff)
+    return header, struct.pack("<II", zlib.crc32(data), len(data))
+
+
+def deflate(data):
+    c = zlib.compressobj(9, zlib.DEFLATED, -15)
+    return c.compress(data) + c.flush()
+
+
+def dictzip(data, chunk_len):
+    c = zlib.compressobj(9, zlib.DEFLATED, -15)
+    chunks = [data[i:i + chunk_len] for i in range(0, len(data), chunk_len)]
+    compressed = []
+    for i, chunk in enumerate(chunks):
+        last = i + 1 == len(chunks)
+        compressed.append(c.compress(chunk) + c.flush(zlib.Z_FINISH if last else zlib.Z_FULL_FLUSH))
+    ra = struct.pack("<HHH", 1, chunk_len, len(chunks))
+    ra += b"".join(struct.pack("<H", len(x)) for x in compressed)
+    extra = b"RA" + struct.pack("<H", len(ra)) + ra
+    header, trailer = gzip(data, 0x04 | 0x08 | 0x02, extra, b"dictzip.dict")
+    return header + b"".join(compressed) + trailer
+
+
+def main():
+    idx, dict_ = write("plain", [
+        ("apple", "ˈæpəl\0a round fruit".encode()),
+        ("Apple", "ˈæpəl\0the company".encode()),
+        ("banana", "bəˈnɑːnə\0a long fruit".encode()),
+        ("Éclair", "eɪˈklɛə\0a pastry".encode()),
+    ], "tm", 64)
+    open("plain.idx", "wb").write(idx)
+    open("plain.dict", "wb").write(dict_)
+
+    sound = b"RIFF\0\0"
+    idx, dict_ = write("typed", [
+        ("quay", b"ma landing place\0W" + struct.pack(">I", len(sound)) + sound + b"h<b>quay</b>\0"),
+        ("brine", b"mwater with salt\0"),
+    ], "")
+    header, trailer = gzip(idx)
+    open("typed.idx.gz", "wb").write(header + deflate(idx) + trailer)
+    header, trailer = gzip(dict_)
+    open("typed.dict.dz", "wb").write(header + deflate(dict_) + trailer)
+
+    idx, dict_ = write("dictzip", [
+        (w, f"{w}: {'long ' * 5}definition".encode()) for w in ["ant", "bee", "cat", "dog"]
+    ], "m")
+    open("dictzip.idx", "wb").write(idx)
+    open("dictzip.dict.dz", "wb").write(dictzip(dict_, 16))
+
+
+main()